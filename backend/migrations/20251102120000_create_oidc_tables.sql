@@ -0,0 +1,24 @@
+-- Add migration script here
+-- Allow accounts created through single sign-on to have no local password
+ALTER TABLE users
+    ALTER COLUMN password_hash DROP NOT NULL;
+-- Link external OpenID Connect identities (provider + subject) to users
+CREATE TABLE user_identities (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider VARCHAR(100) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    email VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (provider, subject)
+);
+CREATE INDEX idx_user_identities_user_id ON user_identities(user_id);
+-- Pending authorization-code requests (state, nonce and PKCE verifier)
+CREATE TABLE oidc_auth_requests (
+    state VARCHAR(128) PRIMARY KEY,
+    provider VARCHAR(100) NOT NULL,
+    nonce VARCHAR(128) NOT NULL,
+    code_verifier VARCHAR(128) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX idx_oidc_auth_requests_created_at ON oidc_auth_requests(created_at);
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Track failed logins per account for exponential lockout
+CREATE TABLE login_failures (
+    email VARCHAR(255) PRIMARY KEY,
+    failed_count INTEGER NOT NULL DEFAULT 0,
+    locked_until TIMESTAMPTZ,
+    last_failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
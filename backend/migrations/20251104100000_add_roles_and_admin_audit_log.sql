@@ -0,0 +1,20 @@
+-- Add migration script here
+-- Roles, account disabling and per-user storage quota overrides
+ALTER TABLE users
+ADD COLUMN role VARCHAR(20) NOT NULL DEFAULT 'user' CHECK (role IN ('user', 'admin')),
+ADD COLUMN disabled_at TIMESTAMPTZ,
+ADD COLUMN storage_quota_bytes BIGINT;
+-- Size of the stored PDF, used for storage usage and quotas
+ALTER TABLE documents
+ADD COLUMN file_size_bytes BIGINT;
+-- Audit trail of administrative actions
+CREATE TABLE admin_audit_log (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    admin_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    action VARCHAR(50) NOT NULL,
+    target_user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    details JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX idx_admin_audit_log_created_at ON admin_audit_log(created_at);
+CREATE INDEX idx_admin_audit_log_target_user_id ON admin_audit_log(target_user_id);
@@ -0,0 +1,6 @@
+-- Add migration script here
+-- Self-service account deletion is scheduled and purged after a grace period
+ALTER TABLE users
+ADD COLUMN deletion_scheduled_at TIMESTAMPTZ;
+CREATE INDEX idx_users_deletion_scheduled_at ON users(deletion_scheduled_at)
+WHERE deletion_scheduled_at IS NOT NULL;
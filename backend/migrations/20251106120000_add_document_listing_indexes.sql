@@ -0,0 +1,7 @@
+-- Add migration script here
+-- Indexes backing sorted, keyset-paginated document listings
+CREATE INDEX idx_documents_user_created_at ON documents(user_id, created_at, id);
+CREATE INDEX idx_documents_user_updated_at ON documents(user_id, updated_at, id);
+CREATE INDEX idx_documents_user_year ON documents(user_id, (COALESCE(year, 0)), id);
+CREATE INDEX idx_documents_user_title ON documents(user_id, (LOWER(title)), id);
+CREATE INDEX idx_documents_user_first_author ON documents(user_id, (LOWER(COALESCE(authors [1], ''))), id);
@@ -0,0 +1,21 @@
+-- Add migration script here
+-- Weighted full-text document: title (A), authors and keywords (B), abstract (C), journal (D).
+-- array_to_string is only STABLE, but over text[] its output is fixed, so the wrapper can be IMMUTABLE
+CREATE OR REPLACE FUNCTION document_search_vector(
+        title TEXT,
+        authors TEXT [],
+        abstract_text TEXT,
+        keywords TEXT [],
+        journal TEXT
+    ) RETURNS tsvector AS $$
+SELECT setweight(to_tsvector('english', COALESCE(title, '')), 'A') ||
+    setweight(to_tsvector('english', COALESCE(array_to_string(authors, ' '), '')), 'B') ||
+    setweight(to_tsvector('english', COALESCE(array_to_string(keywords, ' '), '')), 'B') ||
+    setweight(to_tsvector('english', COALESCE(abstract_text, '')), 'C') ||
+    setweight(to_tsvector('english', COALESCE(journal, '')), 'D')
+$$ LANGUAGE SQL IMMUTABLE PARALLEL SAFE;
+ALTER TABLE documents
+ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+        document_search_vector(title, authors, abstract_text, keywords, journal)
+    ) STORED;
+CREATE INDEX idx_documents_search_vector ON documents USING GIN (search_vector);
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- PDF text extracted once at upload, one row per page, for content search and chat
+CREATE TABLE document_pages (
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    page_number INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    search_vector tsvector GENERATED ALWAYS AS (to_tsvector('english', content)) STORED,
+    PRIMARY KEY (document_id, page_number)
+);
+CREATE INDEX idx_document_pages_search_vector ON document_pages USING GIN (search_vector);
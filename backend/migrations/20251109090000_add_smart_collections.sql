@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Smart collections store a search query; their documents are its matches, not rows in document_collections
+ALTER TABLE collections
+ADD COLUMN query TEXT;
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Embedded passages are stored in document_chunks, which needs the pgvector extension. Semantic
+-- search is optional, so embeddings::ensure_chunk_table creates the extension and the table at
+-- startup only when an embedding provider is configured; Postgres without pgvector still migrates.
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- What each document was last embedded from, so a changed text or model triggers re-embedding
+CREATE TABLE document_embedding_status (
+    document_id UUID PRIMARY KEY REFERENCES documents(id) ON DELETE CASCADE,
+    model TEXT NOT NULL,
+    text_hash TEXT NOT NULL,
+    chunk_count INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    attempted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Trigram indexes over author and keyword arrays so suggestions can match them like titles and journals.
+-- array_to_string is only STABLE, but over text[] its output is fixed, so the wrapper can be IMMUTABLE
+CREATE OR REPLACE FUNCTION text_array_to_string(items TEXT []) RETURNS TEXT AS $$
+SELECT array_to_string(items, ' ')
+$$ LANGUAGE SQL IMMUTABLE PARALLEL SAFE;
+CREATE INDEX IF NOT EXISTS idx_documents_authors_trgm ON documents USING GIN (text_array_to_string(authors) gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_documents_keywords_trgm ON documents USING GIN (text_array_to_string(keywords) gin_trgm_ops);
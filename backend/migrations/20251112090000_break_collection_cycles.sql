@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Earlier moves only rejected a collection becoming its own parent, so deeper cycles could exist.
+-- Collections that are their own ancestor are unreachable from the root; move them back to it.
+WITH RECURSIVE ancestry AS (
+    SELECT id, parent_id AS ancestor_id FROM collections WHERE parent_id IS NOT NULL
+    UNION
+    SELECT a.id, c.parent_id
+    FROM ancestry a
+    INNER JOIN collections c ON c.id = a.ancestor_id
+    WHERE c.parent_id IS NOT NULL
+)
+UPDATE collections SET parent_id = NULL, updated_at = NOW()
+WHERE id IN (SELECT id FROM ancestry WHERE id = ancestor_id);
@@ -0,0 +1,35 @@
+-- Add migration script here
+-- Manual order of collections among their siblings and of documents within a collection.
+-- Positions are fractional so moving an item only rewrites that item, between its new neighbours.
+ALTER TABLE collections
+ADD COLUMN position DOUBLE PRECISION NOT NULL DEFAULT 0;
+ALTER TABLE document_collections
+ADD COLUMN position DOUBLE PRECISION NOT NULL DEFAULT 0;
+-- Start from the orders the listings used so far: folders by name, documents newest first
+UPDATE collections c
+SET position = o.position
+FROM (
+        SELECT id,
+            ROW_NUMBER() OVER (
+                PARTITION BY user_id, parent_id
+                ORDER BY name, created_at
+            ) AS position
+        FROM collections
+    ) o
+WHERE c.id = o.id;
+UPDATE document_collections dc
+SET position = o.position
+FROM (
+        SELECT dc.document_id,
+            dc.collection_id,
+            ROW_NUMBER() OVER (
+                PARTITION BY dc.collection_id
+                ORDER BY d.created_at DESC
+            ) AS position
+        FROM document_collections dc
+            INNER JOIN documents d ON d.id = dc.document_id
+    ) o
+WHERE dc.document_id = o.document_id
+    AND dc.collection_id = o.collection_id;
+CREATE INDEX idx_collections_position ON collections(user_id, parent_id, position);
+CREATE INDEX idx_document_collections_position ON document_collections(collection_id, position);
@@ -0,0 +1,34 @@
+-- Add migration script here
+-- Collections shared with other users. An invitation names an email address and grants
+-- access only once the user signed in with that address accepts it.
+CREATE TABLE collection_shares (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    collection_id UUID NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(10) NOT NULL CHECK (role IN ('viewer', 'editor')),
+    include_subcollections BOOLEAN NOT NULL DEFAULT TRUE,
+    invited_by UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    accepted_at TIMESTAMPTZ,
+    UNIQUE (collection_id, email)
+);
+CREATE INDEX idx_collection_shares_user_id ON collection_shares(user_id);
+CREATE INDEX idx_collection_shares_email ON collection_shares(LOWER(email));
+-- Every collection a user can reach through accepted shares, and whether they may edit it.
+-- Subcollections inherit the share unless it was limited to the collection itself.
+CREATE OR REPLACE FUNCTION shared_collections(viewer UUID) RETURNS TABLE (collection_id UUID, can_edit BOOLEAN) AS $$
+WITH RECURSIVE shared AS (
+    SELECT s.collection_id, s.role = 'editor' AS can_edit, s.include_subcollections
+    FROM collection_shares s
+    WHERE s.user_id = viewer
+    UNION
+    SELECT c.id, sh.can_edit, TRUE
+    FROM collections c
+    INNER JOIN shared sh ON c.parent_id = sh.collection_id
+    WHERE sh.include_subcollections
+)
+SELECT shared.collection_id, bool_or(shared.can_edit)
+FROM shared
+GROUP BY shared.collection_id
+$$ LANGUAGE SQL STABLE;
@@ -0,0 +1,32 @@
+-- Add migration script here
+-- Group libraries: documents and collections owned by a team rather than a single user.
+CREATE TABLE groups (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE group_members (
+    group_id UUID NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(10) NOT NULL CHECK (role IN ('owner', 'admin', 'member', 'reader')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (group_id, user_id)
+);
+CREATE INDEX idx_group_members_user_id ON group_members(user_id);
+-- The owner is charged for the group's storage, so there is exactly one
+CREATE UNIQUE INDEX idx_group_members_owner ON group_members(group_id) WHERE role = 'owner';
+
+-- Documents and collections belong to either a user or a group
+ALTER TABLE documents
+    ALTER COLUMN user_id DROP NOT NULL,
+    ADD COLUMN group_id UUID REFERENCES groups(id) ON DELETE CASCADE,
+    ADD CONSTRAINT documents_single_owner CHECK (num_nonnulls(user_id, group_id) = 1);
+CREATE INDEX idx_documents_group_id ON documents(group_id, created_at DESC) WHERE group_id IS NOT NULL;
+
+ALTER TABLE collections
+    ALTER COLUMN user_id DROP NOT NULL,
+    ADD COLUMN group_id UUID REFERENCES groups(id) ON DELETE CASCADE,
+    ADD CONSTRAINT collections_single_owner CHECK (num_nonnulls(user_id, group_id) = 1);
+CREATE INDEX idx_collections_group_id ON collections(group_id) WHERE group_id IS NOT NULL;
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Public read-only links to collections, for visitors without an account.
+-- The token is the link's only credential; deleting the row revokes it.
+CREATE TABLE collection_links (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    collection_id UUID NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+    token VARCHAR(64) NOT NULL UNIQUE,
+    allow_downloads BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMPTZ,
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX idx_collection_links_collection_id ON collection_links(collection_id);
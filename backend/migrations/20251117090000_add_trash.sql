@@ -0,0 +1,26 @@
+-- Add migration script here
+-- Trash: deleted documents and collections are kept, hidden, until restored or purged.
+-- Everything deleted in one operation shares a deleted_at, which is how a collection's
+-- restore finds the subcollections and documents that went with it.
+ALTER TABLE documents ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE collections ADD COLUMN deleted_at TIMESTAMPTZ;
+CREATE INDEX idx_documents_deleted_at ON documents(deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX idx_collections_deleted_at ON collections(deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- Shares no longer reach into trashed collections
+CREATE OR REPLACE FUNCTION shared_collections(viewer UUID) RETURNS TABLE (collection_id UUID, can_edit BOOLEAN) AS $$
+WITH RECURSIVE shared AS (
+    SELECT s.collection_id, s.role = 'editor' AS can_edit, s.include_subcollections
+    FROM collection_shares s
+    INNER JOIN collections c ON c.id = s.collection_id
+    WHERE s.user_id = viewer AND c.deleted_at IS NULL
+    UNION
+    SELECT c.id, sh.can_edit, TRUE
+    FROM collections c
+    INNER JOIN shared sh ON c.parent_id = sh.collection_id
+    WHERE sh.include_subcollections AND c.deleted_at IS NULL
+)
+SELECT shared.collection_id, bool_or(shared.can_edit)
+FROM shared
+GROUP BY shared.collection_id
+$$ LANGUAGE SQL STABLE;
@@ -0,0 +1,5 @@
+-- Add migration script here
+-- A login request started by a signed-in user links the identity to that account
+-- instead of signing in; identities are never linked to existing accounts by email
+ALTER TABLE oidc_auth_requests
+    ADD COLUMN link_user_id UUID REFERENCES users(id) ON DELETE CASCADE;
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Count failed logins per account rather than per submitted email, so unknown
+-- addresses never create rows; they are only subject to the per-IP rate limit
+DROP TABLE login_failures;
+CREATE TABLE login_failures (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    failed_count INTEGER NOT NULL DEFAULT 0,
+    locked_until TIMESTAMPTZ,
+    last_failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
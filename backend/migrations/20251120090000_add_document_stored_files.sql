@@ -0,0 +1,30 @@
+-- Add migration script here
+-- Path of the PDF uploaded for a document. Only the server writes it, and only this
+-- path is ever read from or removed on disk; pdf_url could be set by clients.
+ALTER TABLE documents ADD COLUMN stored_file_path TEXT;
+-- Carry over uploads that exactly one document refers to. A path shared by several
+-- documents may have been copied in by a client, so none of them keeps it. Sizes of
+-- uploads made before file_size_bytes existed are read from disk at startup.
+UPDATE documents d
+SET stored_file_path = d.pdf_url
+WHERE d.pdf_url LIKE 'uploads/%'
+    AND d.pdf_url NOT LIKE '%..%'
+    AND NOT EXISTS (
+        SELECT 1 FROM documents o WHERE o.pdf_url = d.pdf_url AND o.id <> d.id
+    );
+-- External links clients stored in pdf_url move to the client-writable url where that is empty
+UPDATE documents
+SET url = pdf_url
+WHERE pdf_url NOT LIKE 'uploads/%'
+    AND (url IS NULL OR btrim(url) = '');
+-- Whatever neither column took is set aside rather than dropped with pdf_url
+CREATE TABLE legacy_document_pdf_urls AS
+SELECT id AS document_id, pdf_url
+FROM documents
+WHERE pdf_url IS NOT NULL
+    AND pdf_url IS DISTINCT FROM stored_file_path
+    AND pdf_url IS DISTINCT FROM url;
+ALTER TABLE legacy_document_pdf_urls
+ADD PRIMARY KEY (document_id),
+ADD FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE CASCADE;
+ALTER TABLE documents DROP COLUMN pdf_url;
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- When the PDF text was last extracted, even if it yielded no pages (image-only scans),
+-- so extraction is not retried on every chat
+ALTER TABLE documents ADD COLUMN pages_indexed_at TIMESTAMPTZ;
+UPDATE documents d
+SET pages_indexed_at = d.updated_at
+WHERE EXISTS (SELECT 1 FROM document_pages p WHERE p.document_id = d.id);
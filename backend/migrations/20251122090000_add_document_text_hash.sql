@@ -0,0 +1,46 @@
+-- Add migration script here
+-- Hash of everything a document's embeddings are built from (title, abstract and PDF pages),
+-- kept current by triggers so the embedding task compares it instead of rehashing all text
+ALTER TABLE documents ADD COLUMN text_hash TEXT;
+
+CREATE FUNCTION document_text_hash(doc_id UUID, title TEXT, abstract_text TEXT)
+RETURNS TEXT LANGUAGE sql STABLE AS $$
+    SELECT md5(concat_ws(E'\x1f', title, abstract_text, (
+        SELECT string_agg(p.content, E'\x1e' ORDER BY p.page_number)
+        FROM document_pages p WHERE p.document_id = doc_id
+    )))
+$$;
+
+CREATE FUNCTION documents_set_text_hash() RETURNS trigger LANGUAGE plpgsql AS $$
+BEGIN
+    NEW.text_hash := document_text_hash(NEW.id, NEW.title, NEW.abstract_text);
+    RETURN NEW;
+END
+$$;
+
+CREATE TRIGGER documents_text_hash
+    BEFORE INSERT OR UPDATE OF title, abstract_text ON documents
+    FOR EACH ROW EXECUTE FUNCTION documents_set_text_hash();
+
+-- Pages are replaced as a whole, so rehash once per statement for the documents touched
+CREATE FUNCTION document_pages_refresh_text_hash() RETURNS trigger LANGUAGE plpgsql AS $$
+BEGIN
+    UPDATE documents d
+    SET text_hash = document_text_hash(d.id, d.title, d.abstract_text)
+    WHERE d.id IN (SELECT document_id FROM changed_pages);
+    RETURN NULL;
+END
+$$;
+
+CREATE TRIGGER document_pages_inserted_text_hash
+    AFTER INSERT ON document_pages REFERENCING NEW TABLE AS changed_pages
+    FOR EACH STATEMENT EXECUTE FUNCTION document_pages_refresh_text_hash();
+CREATE TRIGGER document_pages_updated_text_hash
+    AFTER UPDATE ON document_pages REFERENCING NEW TABLE AS changed_pages
+    FOR EACH STATEMENT EXECUTE FUNCTION document_pages_refresh_text_hash();
+CREATE TRIGGER document_pages_deleted_text_hash
+    AFTER DELETE ON document_pages REFERENCING OLD TABLE AS changed_pages
+    FOR EACH STATEMENT EXECUTE FUNCTION document_pages_refresh_text_hash();
+
+UPDATE documents SET text_hash = document_text_hash(id, title, abstract_text);
+ALTER TABLE documents ALTER COLUMN text_hash SET NOT NULL;
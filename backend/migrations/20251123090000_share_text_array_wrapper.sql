@@ -0,0 +1,18 @@
+-- Add migration script here
+-- document_search_vector and the author and keyword trigram indexes share one IMMUTABLE wrapper
+-- for joining text arrays. The output is unchanged, so stored search vectors stay valid.
+COMMENT ON FUNCTION text_array_to_string(TEXT []) IS
+    'array_to_string is only STABLE, but over text[] its output is fixed, so the wrapper can be IMMUTABLE';
+CREATE OR REPLACE FUNCTION document_search_vector(
+        title TEXT,
+        authors TEXT [],
+        abstract_text TEXT,
+        keywords TEXT [],
+        journal TEXT
+    ) RETURNS tsvector AS $$
+SELECT setweight(to_tsvector('english', COALESCE(title, '')), 'A') ||
+    setweight(to_tsvector('english', COALESCE(text_array_to_string(authors), '')), 'B') ||
+    setweight(to_tsvector('english', COALESCE(text_array_to_string(keywords), '')), 'B') ||
+    setweight(to_tsvector('english', COALESCE(abstract_text, '')), 'C') ||
+    setweight(to_tsvector('english', COALESCE(journal, '')), 'D')
+$$ LANGUAGE SQL IMMUTABLE PARALLEL SAFE;
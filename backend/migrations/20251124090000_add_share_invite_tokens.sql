@@ -0,0 +1,12 @@
+-- Add migration script here
+-- Email addresses are not verified, so signing in with the invited address no longer proves
+-- anything. A pending share carries a one-time token the owner sends to the invitee; accepting
+-- it clears the token. Pending shares get one here so their owners can send them on.
+ALTER TABLE collection_shares
+ADD COLUMN invite_token TEXT UNIQUE;
+UPDATE collection_shares
+SET invite_token = replace(gen_random_uuid()::TEXT || gen_random_uuid()::TEXT, '-', '')
+WHERE user_id IS NULL;
+ALTER TABLE collection_shares
+ADD CONSTRAINT collection_shares_pending_token CHECK ((user_id IS NULL) = (invite_token IS NOT NULL));
+DROP INDEX IF EXISTS idx_collection_shares_email;
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Group libraries page through the same sort keys as personal ones
+CREATE INDEX idx_documents_group_created_at ON documents(group_id, created_at, id) WHERE group_id IS NOT NULL;
+CREATE INDEX idx_documents_group_updated_at ON documents(group_id, updated_at, id) WHERE group_id IS NOT NULL;
+CREATE INDEX idx_documents_group_year ON documents(group_id, (COALESCE(year, 0)), id) WHERE group_id IS NOT NULL;
+CREATE INDEX idx_documents_group_title ON documents(group_id, (LOWER(title)), id) WHERE group_id IS NOT NULL;
+CREATE INDEX idx_documents_group_first_author ON documents(group_id, (LOWER(COALESCE(authors [1], ''))), id) WHERE group_id IS NOT NULL;
+-- Covered by idx_documents_group_created_at
+DROP INDEX idx_documents_group_id;
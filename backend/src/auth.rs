@@ -3,24 +3,61 @@ use crate::models::Claims;
 use chrono::{Duration, Utc};
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
 
+// How recently a passwordless single sign-on account must have signed in to set a password
+pub const FIRST_PASSWORD_MAX_SIGN_IN_AGE_SECS: usize = 10 * 60;
+
 pub fn create_jwt(
     user_id: &str,
     email: &str,
+    role: &str,
     secret: &str,
 ) -> Result<String, jsonwebtoken::errors::Error> {
-    let expiration = Utc::now()
-        .checked_add_signed(Duration::hours(24))
-        .expect("Valid timestamp")
-        .timestamp() as usize;
+    let claims = Claims {
+        sub: user_id.to_owned(),
+        email: email.to_owned(),
+        role: role.to_owned(),
+        impersonated_by: None,
+        iat: Utc::now().timestamp() as usize,
+        exp: expiration_after(Duration::hours(24)),
+    };
+    encode_claims(&claims, secret)
+}
 
+// Short-lived token for an admin acting as another user; the admin id stays in the claims
+pub fn create_impersonation_jwt(
+    user_id: &str,
+    email: &str,
+    role: &str,
+    admin_id: &str,
+    secret: &str,
+) -> Result<String, jsonwebtoken::errors::Error> {
     let claims = Claims {
         sub: user_id.to_owned(),
         email: email.to_owned(),
-        exp: expiration,
+        role: role.to_owned(),
+        impersonated_by: Some(admin_id.to_owned()),
+        iat: Utc::now().timestamp() as usize,
+        exp: expiration_after(Duration::hours(1)),
     };
+    encode_claims(&claims, secret)
+}
+
+// Whether the token was issued by a sign-in at most `seconds` ago
+pub fn signed_in_within(claims: &Claims, seconds: usize) -> bool {
+    (Utc::now().timestamp() as usize).saturating_sub(claims.iat) <= seconds
+}
+
+fn expiration_after(duration: Duration) -> usize {
+    Utc::now()
+        .checked_add_signed(duration)
+        .expect("Valid timestamp")
+        .timestamp() as usize
+}
+
+fn encode_claims(claims: &Claims, secret: &str) -> Result<String, jsonwebtoken::errors::Error> {
     encode(
         &Header::default(),
-        &claims,
+        claims,
         &EncodingKey::from_secret(secret.as_bytes()),
     )
 }
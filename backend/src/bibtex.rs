@@ -0,0 +1,147 @@
+// BibTeX export for documents
+use crate::models::Document;
+use std::collections::HashMap;
+
+// Map CrossRef-style publication types to BibTeX entry types
+fn entry_type(publication_type: Option<&str>) -> &'static str {
+    match publication_type.unwrap_or("") {
+        "journal-article" | "article" => "article",
+        "proceedings-article" | "conference-paper" | "inproceedings" => "inproceedings",
+        "book" | "monograph" | "edited-book" => "book",
+        "book-chapter" | "book-section" | "book-part" => "incollection",
+        "report" | "techreport" => "techreport",
+        "dissertation" | "thesis" | "phdthesis" => "phdthesis",
+        "posted-content" | "preprint" => "unpublished",
+        _ => "misc",
+    }
+}
+
+// Escape characters that break LaTeX inside a braced field value
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' | '%' | '#' | '_' | '$' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '{' | '}' | '\\' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Key like "smith2021graph": first author's last name, year and first title word
+pub fn citation_key(document: &Document) -> String {
+    let alphanumeric = |s: &str| -> String {
+        s.chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase()
+    };
+
+    let author = document
+        .authors
+        .as_ref()
+        .and_then(|authors| authors.first())
+        .and_then(|author| author.split_whitespace().last())
+        .map(alphanumeric)
+        .unwrap_or_default();
+
+    let year = document.year.map(|y| y.to_string()).unwrap_or_default();
+
+    let title_word = document
+        .title
+        .split_whitespace()
+        .map(alphanumeric)
+        .find(|w| w.len() > 3)
+        .unwrap_or_default();
+
+    let key = format!("{}{}{}", author, year, title_word);
+    if key.is_empty() {
+        document.id.simple().to_string()
+    } else {
+        key
+    }
+}
+
+pub fn document_to_bibtex(document: &Document, key: &str) -> String {
+    let mut fields: Vec<(&str, String)> = vec![("title", document.title.clone())];
+
+    if let Some(authors) = document.authors.as_ref().filter(|a| !a.is_empty()) {
+        fields.push(("author", authors.join(" and ")));
+    }
+    if let Some(year) = document.year {
+        fields.push(("year", year.to_string()));
+    }
+
+    let journal_field = match entry_type(document.publication_type.as_deref()) {
+        "inproceedings" | "incollection" => "booktitle",
+        _ => "journal",
+    };
+
+    let optional = [
+        (journal_field, &document.journal),
+        ("volume", &document.volume),
+        ("number", &document.issue),
+        ("pages", &document.pages),
+        ("publisher", &document.publisher),
+        ("doi", &document.doi),
+        ("url", &document.url),
+        ("abstract", &document.abstract_text),
+    ];
+    for (name, value) in optional {
+        if let Some(value) = value.as_ref().filter(|v| !v.trim().is_empty()) {
+            fields.push((name, value.clone()));
+        }
+    }
+
+    if let Some(keywords) = document.keywords.as_ref().filter(|k| !k.is_empty()) {
+        fields.push(("keywords", keywords.join(", ")));
+    }
+
+    let body = fields
+        .iter()
+        .map(|(name, value)| {
+            // DOIs and URLs are verbatim; escaping would corrupt them
+            let value = if matches!(*name, "doi" | "url") {
+                value.clone()
+            } else {
+                escape(value)
+            };
+            format!("  {} = {{{}}}", name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(",\n");
+
+    format!(
+        "@{}{{{},\n{}\n}}\n",
+        entry_type(document.publication_type.as_deref()),
+        key,
+        body
+    )
+}
+
+// Render a whole library, suffixing duplicate keys with a, b, c...
+pub fn documents_to_bibtex(documents: &[Document]) -> String {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut output = String::new();
+
+    for document in documents {
+        let base = citation_key(document);
+        let count = seen.entry(base.clone()).or_insert(0);
+        let key = if *count == 0 {
+            base
+        } else {
+            let suffix = (b'a' + ((*count - 1) % 26) as u8) as char;
+            format!("{}{}", base, suffix)
+        };
+        *count += 1;
+
+        output.push_str(&document_to_bibtex(document, &key));
+        output.push('\n');
+    }
+
+    output
+}
@@ -0,0 +1,357 @@
+// Operations applied to many documents at once, in a single transaction
+use crate::groups::Library;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+pub const MAX_BULK_DOCUMENTS: usize = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct BulkRequest {
+    pub document_ids: Vec<Uuid>,
+    #[serde(flatten)]
+    pub action: BulkAction,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BulkAction {
+    Delete,
+    AddToCollection { collection_id: Uuid },
+    RemoveFromCollection { collection_id: Uuid },
+    AddKeywords { keywords: Vec<String> },
+    RemoveKeywords { keywords: Vec<String> },
+    // One of publication_type, journal, publisher or year; null clears it
+    SetField { field: String, value: Value },
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkStatus {
+    Updated,
+    Deleted,
+    // Already in the requested state
+    Unchanged,
+    NotFound,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkItemResult {
+    pub id: Uuid,
+    pub status: BulkStatus,
+}
+
+#[derive(Debug)]
+pub enum BulkError {
+    // The request itself is invalid; nothing was changed
+    Invalid(String),
+    CollectionNotFound,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for BulkError {
+    fn from(e: sqlx::Error) -> Self {
+        BulkError::Database(e)
+    }
+}
+
+enum FieldValue {
+    PublicationType(Option<String>),
+    Journal(Option<String>),
+    Publisher(Option<String>),
+    Year(Option<i32>),
+}
+
+fn parse_field(field: &str, value: Value) -> Result<FieldValue, BulkError> {
+    let invalid = |message: &str| Err(BulkError::Invalid(message.to_string()));
+    match field {
+        "publication_type" | "journal" | "publisher" => {
+            // Blank clears the field, like null
+            let text = match value {
+                Value::Null => None,
+                Value::String(s) if s.trim().is_empty() => None,
+                Value::String(s) => Some(s.trim().to_string()),
+                _ => return invalid("Field value must be a string or null"),
+            };
+            Ok(match field {
+                "publication_type" => FieldValue::PublicationType(text),
+                "journal" => FieldValue::Journal(text),
+                _ => FieldValue::Publisher(text),
+            })
+        }
+        "year" => match value {
+            Value::Null => Ok(FieldValue::Year(None)),
+            Value::Number(n) => match n.as_i64().and_then(|y| i32::try_from(y).ok()) {
+                Some(year) => Ok(FieldValue::Year(Some(year))),
+                None => invalid("Year must be a whole number"),
+            },
+            _ => invalid("Year must be a number or null"),
+        },
+        _ => invalid("Unknown field; expected one of publication_type, journal, publisher, year"),
+    }
+}
+
+// Trimmed, non-empty, without case-insensitive duplicates
+fn normalize_keywords(keywords: Vec<String>) -> Result<Vec<String>, BulkError> {
+    let mut normalized: Vec<String> = Vec::new();
+    for keyword in keywords {
+        let keyword = keyword.trim().to_string();
+        if !keyword.is_empty()
+            && !normalized
+                .iter()
+                .any(|k| k.to_lowercase() == keyword.to_lowercase())
+        {
+            normalized.push(keyword);
+        }
+    }
+    if normalized.is_empty() {
+        return Err(BulkError::Invalid("No keywords given".to_string()));
+    }
+    Ok(normalized)
+}
+
+// Check the target collection before touching anything
+async fn writable_collection(
+    tx: &mut Transaction<'_, Postgres>,
+    library: Library,
+    collection_id: Uuid,
+) -> Result<(), BulkError> {
+    let collection = sqlx::query!(
+        r#"
+        SELECT query FROM collections
+        WHERE id = $1 AND (user_id = $2 OR group_id = $3) AND deleted_at IS NULL
+        "#,
+        collection_id,
+        library.user_id(),
+        library.group_id()
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    .ok_or(BulkError::CollectionNotFound)?;
+
+    if collection.query.is_some() {
+        return Err(BulkError::Invalid(
+            "Smart collections are filled by their search".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// Apply `action` to the library's documents among `document_ids`, all or nothing.
+// Returns one result per requested id, in request order.
+pub async fn apply_bulk_action(
+    db: &PgPool,
+    library: Library,
+    mut document_ids: Vec<Uuid>,
+    action: BulkAction,
+) -> Result<Vec<BulkItemResult>, BulkError> {
+    let mut seen = std::collections::HashSet::new();
+    document_ids.retain(|id| seen.insert(*id));
+    if document_ids.is_empty() {
+        return Err(BulkError::Invalid("No documents given".to_string()));
+    }
+    if document_ids.len() > MAX_BULK_DOCUMENTS {
+        return Err(BulkError::Invalid(format!(
+            "At most {} documents can be changed at once",
+            MAX_BULK_DOCUMENTS
+        )));
+    }
+
+    let mut tx = db.begin().await?;
+
+    // Lock the documents so the per-id results describe what was actually applied.
+    // Only these ids of the library are changed below.
+    let locked = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM documents
+        WHERE id = ANY($1) AND (user_id = $2 OR group_id = $3) AND deleted_at IS NULL
+        FOR UPDATE
+        "#,
+        &document_ids,
+        library.user_id(),
+        library.group_id()
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let owned: Vec<Uuid> = document_ids
+        .iter()
+        .copied()
+        .filter(|id| locked.contains(id))
+        .collect();
+
+    let (changed, changed_status) = match action {
+        // Moved to the trash, where each can be restored on its own
+        BulkAction::Delete => {
+            let deleted = sqlx::query_scalar!(
+                "UPDATE documents SET deleted_at = NOW() WHERE id = ANY($1) RETURNING id",
+                &owned
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+            (deleted, BulkStatus::Deleted)
+        }
+        BulkAction::AddToCollection { collection_id } => {
+            writable_collection(&mut tx, library, collection_id).await?;
+            // Added documents go first, in request order, like single additions
+            let added = sqlx::query_scalar!(
+                r#"
+                WITH base AS (
+                    SELECT COALESCE(MIN(position), 1) AS position
+                    FROM document_collections WHERE collection_id = $2
+                )
+                INSERT INTO document_collections (document_id, collection_id, position)
+                SELECT u.id, $2, base.position - cardinality($1::UUID[]) + u.ordinality - 1
+                FROM unnest($1::UUID[]) WITH ORDINALITY AS u(id, ordinality), base
+                ON CONFLICT DO NOTHING
+                RETURNING document_id
+                "#,
+                &owned,
+                collection_id
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+            (added, BulkStatus::Updated)
+        }
+        BulkAction::RemoveFromCollection { collection_id } => {
+            writable_collection(&mut tx, library, collection_id).await?;
+            let removed = sqlx::query_scalar!(
+                r#"
+                DELETE FROM document_collections
+                WHERE collection_id = $1 AND document_id = ANY($2)
+                RETURNING document_id
+                "#,
+                collection_id,
+                &owned
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+            (removed, BulkStatus::Updated)
+        }
+        BulkAction::AddKeywords { keywords } => {
+            let keywords = normalize_keywords(keywords)?;
+            let updated = sqlx::query_scalar!(
+                r#"
+                WITH additions AS (
+                    SELECT d.id, ARRAY(
+                        SELECT k FROM unnest($2::TEXT[]) WITH ORDINALITY AS n(k, ordinality)
+                        WHERE NOT LOWER(k) = ANY(
+                            SELECT LOWER(btrim(e)) FROM unnest(COALESCE(d.keywords, '{}')) AS e
+                        )
+                        ORDER BY ordinality
+                    ) AS keywords
+                    FROM documents d
+                    WHERE d.id = ANY($1)
+                )
+                UPDATE documents d
+                SET keywords = COALESCE(d.keywords, '{}') || a.keywords
+                FROM additions a
+                WHERE d.id = a.id AND cardinality(a.keywords) > 0
+                RETURNING d.id
+                "#,
+                &owned,
+                &keywords
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+            (updated, BulkStatus::Updated)
+        }
+        BulkAction::RemoveKeywords { keywords } => {
+            let keywords: Vec<String> = normalize_keywords(keywords)?
+                .into_iter()
+                .map(|k| k.to_lowercase())
+                .collect();
+            let updated = sqlx::query_scalar!(
+                r#"
+                UPDATE documents d
+                SET keywords = ARRAY(
+                    SELECT k FROM unnest(d.keywords) WITH ORDINALITY AS n(k, ordinality)
+                    WHERE NOT LOWER(btrim(k)) = ANY($2)
+                    ORDER BY ordinality
+                )
+                WHERE d.id = ANY($1)
+                    AND EXISTS (SELECT 1 FROM unnest(d.keywords) AS k WHERE LOWER(btrim(k)) = ANY($2))
+                RETURNING d.id
+                "#,
+                &owned,
+                &keywords
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+            (updated, BulkStatus::Updated)
+        }
+        BulkAction::SetField { field, value } => {
+            let updated = match parse_field(&field, value)? {
+                FieldValue::Year(year) => {
+                    sqlx::query_scalar!(
+                        r#"
+                        UPDATE documents SET year = $2
+                        WHERE id = ANY($1) AND year IS DISTINCT FROM $2
+                        RETURNING id
+                        "#,
+                        &owned,
+                        year
+                    )
+                    .fetch_all(&mut *tx)
+                    .await?
+                }
+                FieldValue::PublicationType(value) => {
+                    sqlx::query_scalar!(
+                        r#"
+                        UPDATE documents SET publication_type = $2
+                        WHERE id = ANY($1) AND publication_type IS DISTINCT FROM $2
+                        RETURNING id
+                        "#,
+                        &owned,
+                        value
+                    )
+                    .fetch_all(&mut *tx)
+                    .await?
+                }
+                FieldValue::Journal(value) => {
+                    sqlx::query_scalar!(
+                        r#"
+                        UPDATE documents SET journal = $2
+                        WHERE id = ANY($1) AND journal IS DISTINCT FROM $2
+                        RETURNING id
+                        "#,
+                        &owned,
+                        value
+                    )
+                    .fetch_all(&mut *tx)
+                    .await?
+                }
+                FieldValue::Publisher(value) => {
+                    sqlx::query_scalar!(
+                        r#"
+                        UPDATE documents SET publisher = $2
+                        WHERE id = ANY($1) AND publisher IS DISTINCT FROM $2
+                        RETURNING id
+                        "#,
+                        &owned,
+                        value
+                    )
+                    .fetch_all(&mut *tx)
+                    .await?
+                }
+            };
+            (updated, BulkStatus::Updated)
+        }
+    };
+
+    tx.commit().await?;
+
+    Ok(document_ids
+        .into_iter()
+        .map(|id| BulkItemResult {
+            id,
+            status: if changed.contains(&id) {
+                changed_status
+            } else if owned.contains(&id) {
+                BulkStatus::Unchanged
+            } else {
+                BulkStatus::NotFound
+            },
+        })
+        .collect())
+}
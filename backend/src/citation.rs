@@ -0,0 +1,108 @@
+// Plain-text APA-style references, for readers who don't use a reference manager
+use crate::models::Document;
+
+// "Ada M. Lovelace" or "Lovelace, Ada M." -> "Lovelace, A. M."
+fn format_author(author: &str) -> String {
+    let (last, given) = match author.split_once(',') {
+        Some((last, given)) => (last.trim(), given.trim()),
+        None => {
+            let mut parts: Vec<&str> = author.split_whitespace().collect();
+            let last = parts.pop().unwrap_or("");
+            return format_author(&format!("{}, {}", last, parts.join(" ")));
+        }
+    };
+
+    let initials: Vec<String> = given
+        .split(|c: char| c.is_whitespace() || c == '.' || c == '-')
+        .filter_map(|name| name.chars().next())
+        .map(|initial| format!("{}.", initial.to_uppercase()))
+        .collect();
+
+    if initials.is_empty() {
+        last.to_string()
+    } else {
+        format!("{}, {}", last, initials.join(" "))
+    }
+}
+
+fn format_authors(authors: &[String]) -> String {
+    let authors: Vec<String> = authors
+        .iter()
+        .filter(|a| !a.trim().is_empty())
+        .map(|a| format_author(a))
+        .collect();
+
+    match authors.as_slice() {
+        [] => String::new(),
+        [only] => only.clone(),
+        [rest @ .., last] => format!("{}, & {}", rest.join(", "), last),
+    }
+}
+
+// End a sentence with a period unless it already ends with punctuation
+fn sentence(text: &str) -> String {
+    let text = text.trim();
+    if text.ends_with(['.', '?', '!']) {
+        text.to_string()
+    } else {
+        format!("{}.", text)
+    }
+}
+
+pub fn format_reference(document: &Document) -> String {
+    let mut parts = Vec::new();
+
+    let authors = document
+        .authors
+        .as_deref()
+        .map(format_authors)
+        .unwrap_or_default();
+    let year = document
+        .year
+        .map(|y| format!("({}).", y))
+        .unwrap_or_else(|| "(n.d.).".to_string());
+
+    // Without authors, APA moves the title to the front
+    if authors.is_empty() {
+        parts.push(sentence(&document.title));
+        parts.push(year);
+    } else {
+        parts.push(sentence(&authors));
+        parts.push(year);
+        parts.push(sentence(&document.title));
+    }
+
+    if let Some(journal) = document.journal.as_deref().filter(|j| !j.is_empty()) {
+        let mut source = journal.to_string();
+        if let Some(volume) = &document.volume {
+            source.push_str(&format!(", {}", volume));
+            if let Some(issue) = &document.issue {
+                source.push_str(&format!("({})", issue));
+            }
+        }
+        if let Some(pages) = &document.pages {
+            source.push_str(&format!(", {}", pages));
+        }
+        parts.push(sentence(&source));
+    } else if let Some(publisher) = document.publisher.as_deref().filter(|p| !p.is_empty()) {
+        parts.push(sentence(publisher));
+    }
+
+    if let Some(doi) = document.doi.as_deref().filter(|d| !d.is_empty()) {
+        parts.push(format!("https://doi.org/{}", doi));
+    } else if let Some(url) = document.url.as_deref().filter(|u| !u.is_empty()) {
+        parts.push(url.to_string());
+    }
+
+    parts.join(" ")
+}
+
+// One reference per paragraph, sorted as a reference list is
+pub fn documents_to_references(documents: &[Document]) -> String {
+    let mut references: Vec<String> = documents.iter().map(format_reference).collect();
+    references.sort_by_key(|r| r.to_lowercase());
+
+    let mut output = references.join("\n\n");
+    output.push('\n');
+    output
+}
@@ -0,0 +1,243 @@
+// Stored file helpers and background purging of deleted accounts and expired trash
+use crate::{state::AppState, trash::purge_expired_trash};
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+// How often the background task looks for data past its grace period
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Only files written by this server under uploads/ may be read back or removed
+// (documents' stored_file_path and users' profile_image_url are never client-supplied)
+pub fn is_managed_upload(path: &str) -> bool {
+    path.starts_with("uploads/") && !path.split('/').any(|part| part == "..")
+}
+
+// Remove a stored upload, ignoring files that are already gone
+async fn remove_stored_file(path: &str) {
+    if is_managed_upload(path) {
+        let _ = tokio::fs::remove_file(path).await;
+    }
+}
+
+// Remove stored uploads whose rows were just deleted, keeping any path another document or
+// profile still references. Call after the deleting transaction commits; if the check fails
+// the files are left in place rather than risk removing one still in use.
+pub async fn remove_unreferenced_files(db: &PgPool, paths: &[String]) {
+    if paths.is_empty() {
+        return;
+    }
+
+    let referenced = match sqlx::query_scalar!(
+        r#"
+        SELECT stored_file_path AS "path!" FROM documents WHERE stored_file_path = ANY($1)
+        UNION
+        SELECT profile_image_url AS "path!" FROM users WHERE profile_image_url = ANY($1)
+        "#,
+        paths
+    )
+    .fetch_all(db)
+    .await
+    {
+        Ok(referenced) => referenced,
+        Err(e) => {
+            eprintln!("Stored file reference check failed: {}", e);
+            return;
+        }
+    };
+
+    for path in paths {
+        if !referenced.contains(path) {
+            remove_stored_file(path).await;
+        }
+    }
+}
+
+// Delete a user with all their documents, collections and stored files. Groups they own pass
+// to the longest-standing admin, or failing that the longest-standing member or reader; only
+// groups nobody else belongs to are deleted with the account.
+pub async fn purge_user(db: &PgPool, user_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let heirs = sqlx::query!(
+        r#"
+        SELECT DISTINCT ON (h.group_id) h.group_id, h.user_id
+        FROM group_members o
+        INNER JOIN group_members h ON h.group_id = o.group_id AND h.user_id <> o.user_id
+        WHERE o.user_id = $1 AND o.role = 'owner'
+        ORDER BY h.group_id,
+            CASE h.role WHEN 'admin' THEN 0 WHEN 'member' THEN 1 ELSE 2 END,
+            h.created_at
+        "#,
+        user_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let (heir_groups, heir_users): (Vec<Uuid>, Vec<Uuid>) =
+        heirs.into_iter().map(|h| (h.group_id, h.user_id)).unzip();
+
+    // A group has one owner at a time, so the departing owner leaves before the heir takes over
+    sqlx::query!(
+        "DELETE FROM group_members WHERE user_id = $1 AND group_id = ANY($2)",
+        user_id,
+        &heir_groups
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE group_members m SET role = 'owner'
+        FROM unnest($1::UUID[], $2::UUID[]) AS h(group_id, user_id)
+        WHERE m.group_id = h.group_id AND m.user_id = h.user_id
+        "#,
+        &heir_groups,
+        &heir_users
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let owned_groups = sqlx::query_scalar!(
+        "SELECT group_id FROM group_members WHERE user_id = $1 AND role = 'owner'",
+        user_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let pdf_files = sqlx::query_scalar!(
+        r#"
+        SELECT stored_file_path AS "stored_file_path!" FROM documents
+        WHERE (user_id = $1 OR group_id = ANY($2)) AND stored_file_path IS NOT NULL
+        "#,
+        user_id,
+        &owned_groups
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // Their documents and collections cascade from the groups
+    sqlx::query!("DELETE FROM groups WHERE id = ANY($1)", &owned_groups)
+        .execute(&mut *tx)
+        .await?;
+
+    // Documents, collections and memberships cascade from users
+    let profile_image = sqlx::query_scalar!(
+        "DELETE FROM users WHERE id = $1 RETURNING profile_image_url",
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .flatten();
+
+    tx.commit().await?;
+
+    // Files go only after the rows are gone, so a failed commit never loses data
+    let mut removed_files = pdf_files;
+    removed_files.extend(profile_image);
+    remove_unreferenced_files(db, &removed_files).await;
+
+    Ok(())
+}
+
+// Delete a group with its documents, collections and stored files
+pub async fn purge_group(db: &PgPool, group_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let pdf_files = sqlx::query_scalar!(
+        r#"
+        SELECT stored_file_path AS "stored_file_path!" FROM documents
+        WHERE group_id = $1 AND stored_file_path IS NOT NULL
+        "#,
+        group_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // Documents, collections and memberships cascade from groups
+    sqlx::query!("DELETE FROM groups WHERE id = $1", group_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    remove_unreferenced_files(db, &pdf_files).await;
+
+    Ok(())
+}
+
+// Purge every account whose deletion grace period has ended
+pub async fn purge_expired_accounts(db: &PgPool) -> Result<usize, sqlx::Error> {
+    let user_ids = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE deletion_scheduled_at IS NOT NULL AND deletion_scheduled_at <= NOW()"
+    )
+    .fetch_all(db)
+    .await?;
+
+    for user_id in &user_ids {
+        purge_user(db, *user_id).await?;
+    }
+
+    Ok(user_ids.len())
+}
+
+// Uploads stored before sizes were recorded count as 0 bytes towards storage usage and quotas;
+// read their sizes from disk once. Files missing on disk stay unknown.
+pub async fn backfill_file_sizes(db: &PgPool) -> Result<usize, sqlx::Error> {
+    let documents = sqlx::query!(
+        r#"
+        SELECT id, stored_file_path AS "stored_file_path!" FROM documents
+        WHERE stored_file_path IS NOT NULL AND file_size_bytes IS NULL
+        "#
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut filled = 0;
+    for document in documents {
+        if !is_managed_upload(&document.stored_file_path) {
+            continue;
+        }
+        let Ok(metadata) = tokio::fs::metadata(&document.stored_file_path).await else {
+            continue;
+        };
+        sqlx::query!(
+            "UPDATE documents SET file_size_bytes = $1 WHERE id = $2 AND file_size_bytes IS NULL",
+            metadata.len() as i64,
+            document.id
+        )
+        .execute(db)
+        .await?;
+        filled += 1;
+    }
+
+    Ok(filled)
+}
+
+pub fn spawn_cleanup_task(state: AppState) {
+    tokio::spawn(async move {
+        match backfill_file_sizes(&state.db).await {
+            Ok(0) => {}
+            Ok(count) => println!("Recorded the size of {} stored file(s)", count),
+            Err(e) => eprintln!("File size backfill failed: {}", e),
+        }
+
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            match purge_expired_accounts(&state.db).await {
+                Ok(0) => {}
+                Ok(count) => println!("Purged {} deleted account(s)", count),
+                Err(e) => eprintln!("Account purge failed: {}", e),
+            }
+
+            match purge_expired_trash(&state.db, state.trash_retention_days).await {
+                Ok((0, 0)) => {}
+                Ok((documents, collections)) => println!(
+                    "Purged {} document(s) and {} collection(s) from the trash",
+                    documents, collections
+                ),
+                Err(e) => eprintln!("Trash purge failed: {}", e),
+            }
+        }
+    });
+}
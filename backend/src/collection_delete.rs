@@ -0,0 +1,197 @@
+// Deleting a collection with a choice of what happens to its subcollections and documents.
+// Deleted collections and documents go to the trash, see trash.rs.
+use crate::{collection_tree::lock_collection_tree, groups::Library};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteMode {
+    // Subcollections take the deleted collection's place under its parent
+    MoveChildrenUp,
+    // The collection and all of its subcollections go; documents stay in the library
+    #[default]
+    DeleteSubtree,
+    // As DeleteSubtree, and documents filed only inside the subtree are trashed too
+    DeleteDocuments,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ItemRef {
+    pub id: Uuid,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteCollectionReport {
+    pub dry_run: bool,
+    pub deleted_collections: Vec<ItemRef>,
+    pub moved_collections: Vec<ItemRef>,
+    // Documents kept in the library that are left without any collection
+    pub unfiled_documents: i64,
+    pub deleted_documents: Vec<ItemRef>,
+}
+
+// Apply the deletion, or with `dry_run` work out the same report and roll it back.
+// None when the collection does not exist or belongs to another library.
+pub async fn delete_collection(
+    db: &PgPool,
+    library: Library,
+    collection_id: Uuid,
+    mode: DeleteMode,
+    dry_run: bool,
+) -> Result<Option<DeleteCollectionReport>, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    lock_collection_tree(&mut tx, library).await?;
+
+    let Some(collection) = sqlx::query!(
+        r#"
+        SELECT id, name, parent_id FROM collections
+        WHERE id = $1 AND (user_id = $2 OR group_id = $3) AND deleted_at IS NULL
+        "#,
+        collection_id,
+        library.user_id(),
+        library.group_id()
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let mut moved_collections = Vec::new();
+    let deleted_collections = match mode {
+        DeleteMode::MoveChildrenUp => {
+            // Children keep their order, after the new siblings
+            moved_collections = sqlx::query_as!(
+                ItemRef,
+                r#"
+                WITH base AS (
+                    SELECT COALESCE(MAX(position), 0) AS position
+                    FROM collections
+                    WHERE (user_id = $1 OR group_id = $4) AND parent_id IS NOT DISTINCT FROM $3
+                        AND deleted_at IS NULL
+                ),
+                children AS (
+                    SELECT id, ROW_NUMBER() OVER (ORDER BY position, name) AS ordinality
+                    FROM collections
+                    WHERE parent_id = $2 AND deleted_at IS NULL
+                )
+                UPDATE collections c
+                SET parent_id = $3, position = base.position + children.ordinality
+                FROM children, base
+                WHERE c.id = children.id
+                RETURNING c.id, c.name
+                "#,
+                library.user_id(),
+                collection_id,
+                collection.parent_id,
+                library.group_id()
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+
+            vec![ItemRef {
+                id: collection.id,
+                name: collection.name,
+            }]
+        }
+        DeleteMode::DeleteSubtree | DeleteMode::DeleteDocuments => {
+            sqlx::query_as!(
+                ItemRef,
+                r#"
+                WITH RECURSIVE subtree AS (
+                    SELECT id, name FROM collections WHERE id = $1
+                    UNION
+                    SELECT c.id, c.name
+                    FROM collections c
+                    INNER JOIN subtree s ON c.parent_id = s.id
+                    WHERE c.deleted_at IS NULL
+                )
+                SELECT id AS "id!", name AS "name!" FROM subtree
+                "#,
+                collection_id
+            )
+            .fetch_all(&mut *tx)
+            .await?
+        }
+    };
+    let deleted_ids: Vec<Uuid> = deleted_collections.iter().map(|c| c.id).collect();
+
+    // Memberships are kept, so restoring brings documents back into their collections.
+    // Everything is stamped with the same NOW(), the transaction's start time.
+    let deleted_documents = match mode {
+        DeleteMode::DeleteDocuments => {
+            sqlx::query_as!(
+                ItemRef,
+                r#"
+                UPDATE documents d
+                SET deleted_at = NOW()
+                WHERE (d.user_id = $1 OR d.group_id = $3) AND d.deleted_at IS NULL
+                    AND EXISTS (
+                        SELECT 1 FROM document_collections dc
+                        WHERE dc.document_id = d.id AND dc.collection_id = ANY($2)
+                    )
+                    AND NOT EXISTS (
+                        SELECT 1 FROM document_collections dc
+                        INNER JOIN collections c ON c.id = dc.collection_id
+                        WHERE dc.document_id = d.id AND dc.collection_id <> ALL($2)
+                            AND c.deleted_at IS NULL
+                    )
+                RETURNING d.id, d.title AS name
+                "#,
+                library.user_id(),
+                &deleted_ids,
+                library.group_id()
+            )
+            .fetch_all(&mut *tx)
+            .await?
+        }
+        _ => Vec::new(),
+    };
+
+    let unfiled_documents = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(DISTINCT dc.document_id) AS "count!"
+        FROM document_collections dc
+        INNER JOIN documents d ON d.id = dc.document_id
+        WHERE dc.collection_id = ANY($1) AND d.deleted_at IS NULL
+            AND NOT EXISTS (
+                SELECT 1 FROM document_collections other
+                INNER JOIN collections c ON c.id = other.collection_id
+                WHERE other.document_id = dc.document_id AND other.collection_id <> ALL($1)
+                    AND c.deleted_at IS NULL
+            )
+        "#,
+        &deleted_ids
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE collections SET deleted_at = NOW()
+        WHERE id = ANY($1) AND (user_id = $2 OR group_id = $3)
+        "#,
+        &deleted_ids,
+        library.user_id(),
+        library.group_id()
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if dry_run {
+        tx.rollback().await?;
+    } else {
+        tx.commit().await?;
+    }
+
+    Ok(Some(DeleteCollectionReport {
+        dry_run,
+        deleted_collections,
+        moved_collections,
+        unfiled_documents,
+        deleted_documents,
+    }))
+}
@@ -0,0 +1,292 @@
+// Nested collection tree with document counts, and the breadcrumb path to a collection
+use crate::{
+    groups::Library, models::Collection, query_parser::parse, search::smart_memberships_query,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct CollectionNode {
+    #[serde(flatten)]
+    pub collection: Collection,
+    // Documents filed in (or, for smart collections, matching) this collection
+    pub direct_count: i64,
+    // Distinct documents in this collection and all of its subcollections
+    pub recursive_count: i64,
+    pub children: Vec<CollectionNode>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathEntry {
+    pub id: Uuid,
+    pub name: String,
+}
+
+// Documents matching each smart collection, as parallel (collection id, document id) arrays
+async fn smart_memberships(
+    db: &PgPool,
+    library: Library,
+) -> Result<(Vec<Uuid>, Vec<Uuid>), sqlx::Error> {
+    let smart = sqlx::query!(
+        r#"
+        SELECT id, query AS "query!" FROM collections
+        WHERE (user_id = $1 OR group_id = $2) AND query IS NOT NULL AND deleted_at IS NULL
+        "#,
+        library.user_id(),
+        library.group_id()
+    )
+    .fetch_all(db)
+    .await?;
+
+    // An invalid saved query matches nothing, as when listing its documents fails
+    let queries: Vec<_> = smart
+        .into_iter()
+        .filter_map(|c| parse(&c.query).ok().map(|expr| (c.id, expr)))
+        .collect();
+    if queries.is_empty() {
+        return Ok((Vec::new(), Vec::new()));
+    }
+
+    let rows: Vec<(Uuid, Uuid)> = smart_memberships_query(library, &queries)
+        .build_query_as()
+        .fetch_all(db)
+        .await?;
+    let (collection_ids, document_ids) = rows.into_iter().unzip();
+
+    Ok((collection_ids, document_ids))
+}
+
+pub async fn collection_tree(
+    db: &PgPool,
+    library: Library,
+) -> Result<Vec<CollectionNode>, sqlx::Error> {
+    let (smart_collection_ids, smart_document_ids) = smart_memberships(db, library).await?;
+
+    let rows = sqlx::query!(
+        r#"
+        WITH RECURSIVE memberships AS (
+            SELECT dc.collection_id, dc.document_id
+            FROM document_collections dc
+            INNER JOIN collections c ON c.id = dc.collection_id
+            INNER JOIN documents d ON d.id = dc.document_id
+            WHERE (c.user_id = $1 OR c.group_id = $4) AND d.deleted_at IS NULL
+            UNION
+            SELECT * FROM unnest($2::UUID[], $3::UUID[])
+        ),
+        subtree AS (
+            SELECT id AS root_id, id FROM collections
+            WHERE (user_id = $1 OR group_id = $4) AND deleted_at IS NULL
+            UNION
+            SELECT s.root_id, c.id
+            FROM collections c
+            INNER JOIN subtree s ON c.parent_id = s.id
+            WHERE c.deleted_at IS NULL
+        )
+        SELECT c.id, c.user_id, c.group_id, c.name, c.parent_id, c.query, c.position, c.created_at, c.updated_at,
+            (SELECT COUNT(*) FROM memberships m WHERE m.collection_id = c.id) AS "direct_count!",
+            (SELECT COUNT(DISTINCT m.document_id)
+                FROM subtree s
+                INNER JOIN memberships m ON m.collection_id = s.id
+                WHERE s.root_id = c.id) AS "recursive_count!"
+        FROM collections c
+        WHERE (c.user_id = $1 OR c.group_id = $4) AND c.deleted_at IS NULL
+        ORDER BY c.position ASC, c.name ASC
+        "#,
+        library.user_id(),
+        &smart_collection_ids,
+        &smart_document_ids,
+        library.group_id()
+    )
+    .fetch_all(db)
+    .await?;
+
+    // Group by parent, keeping the manual order, then nest from the roots down
+    let mut children: HashMap<Option<Uuid>, Vec<CollectionNode>> = HashMap::new();
+    for row in rows {
+        children
+            .entry(row.parent_id)
+            .or_default()
+            .push(CollectionNode {
+                collection: Collection {
+                    id: row.id,
+                    user_id: row.user_id,
+                    group_id: row.group_id,
+                    name: row.name,
+                    parent_id: row.parent_id,
+                    query: row.query,
+                    position: row.position,
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                },
+                direct_count: row.direct_count,
+                recursive_count: row.recursive_count,
+                children: Vec::new(),
+            });
+    }
+
+    fn attach(
+        node: &mut CollectionNode,
+        children: &mut HashMap<Option<Uuid>, Vec<CollectionNode>>,
+    ) {
+        node.children = children
+            .remove(&Some(node.collection.id))
+            .unwrap_or_default();
+        for child in &mut node.children {
+            attach(child, children);
+        }
+    }
+
+    let mut roots = children.remove(&None).unwrap_or_default();
+    for root in &mut roots {
+        attach(root, &mut children);
+    }
+
+    Ok(roots)
+}
+
+// Ancestors of a collection from the root down, ending with the collection itself;
+// None when it does not exist or belongs to another library
+pub async fn collection_path(
+    db: &PgPool,
+    library: Library,
+    collection_id: Uuid,
+) -> Result<Option<Vec<PathEntry>>, sqlx::Error> {
+    let path = sqlx::query_as!(
+        PathEntry,
+        r#"
+        WITH RECURSIVE ancestors AS (
+            SELECT id, name, parent_id, 0 AS depth
+            FROM collections
+            WHERE id = $1 AND (user_id = $2 OR group_id = $3) AND deleted_at IS NULL
+            UNION ALL
+            SELECT c.id, c.name, c.parent_id, a.depth + 1
+            FROM collections c
+            INNER JOIN ancestors a ON c.id = a.parent_id
+        ) CYCLE id SET is_cycle USING visited
+        SELECT id AS "id!", name AS "name!"
+        FROM ancestors
+        WHERE NOT is_cycle
+        ORDER BY depth DESC
+        "#,
+        collection_id,
+        library.user_id(),
+        library.group_id()
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(if path.is_empty() { None } else { Some(path) })
+}
+
+// Serialize structural changes to a library's tree, so e.g. two concurrent moves can't form a cycle
+pub async fn lock_collection_tree(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    library: Library,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "SELECT pg_advisory_xact_lock(hashtextextended($1::TEXT, 0))",
+        library.id().to_string()
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+// Copy a collection with all of its subcollections and their documents, as a new sibling
+// named `name`. Documents are shared, not copied. None when the collection is not in `library`.
+pub async fn duplicate_collection(
+    db: &PgPool,
+    library: Library,
+    collection_id: Uuid,
+    name: Option<&str>,
+) -> Result<Option<Collection>, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    lock_collection_tree(&mut tx, library).await?;
+
+    let Some(original) = sqlx::query!(
+        r#"
+        SELECT name, parent_id FROM collections
+        WHERE id = $1 AND (user_id = $2 OR group_id = $3) AND deleted_at IS NULL
+        "#,
+        collection_id,
+        library.user_id(),
+        library.group_id()
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let name = name
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{} (copy)", original.name));
+
+    // One statement, so the new rows can reference each other through the id mapping;
+    // data-modifying CTEs always run, whether or not the final SELECT reads them
+    let copy_id = sqlx::query_scalar!(
+        r#"
+        WITH RECURSIVE subtree AS (
+            SELECT id FROM collections WHERE id = $1
+            UNION
+            SELECT c.id
+            FROM collections c
+            INNER JOIN subtree s ON c.parent_id = s.id
+            WHERE c.deleted_at IS NULL
+        ),
+        mapping AS MATERIALIZED (
+            SELECT id AS old_id, gen_random_uuid() AS new_id FROM subtree
+        ),
+        copied_collections AS (
+            INSERT INTO collections (id, user_id, group_id, name, parent_id, query, position)
+            SELECT m.new_id, c.user_id, c.group_id,
+                CASE WHEN c.id = $1 THEN $3 ELSE c.name END,
+                CASE WHEN c.id = $1 THEN c.parent_id ELSE parent.new_id END,
+                c.query,
+                CASE WHEN c.id = $1 THEN (
+                    SELECT COALESCE(MAX(s.position), 0) + 1
+                    FROM collections s
+                    WHERE (s.user_id = $2 OR s.group_id = $4)
+                        AND s.parent_id IS NOT DISTINCT FROM c.parent_id
+                        AND s.deleted_at IS NULL
+                ) ELSE c.position END
+            FROM collections c
+            INNER JOIN mapping m ON m.old_id = c.id
+            LEFT JOIN mapping parent ON parent.old_id = c.parent_id
+        ),
+        copied_memberships AS (
+            INSERT INTO document_collections (document_id, collection_id, position)
+            SELECT dc.document_id, m.new_id, dc.position
+            FROM document_collections dc
+            INNER JOIN mapping m ON m.old_id = dc.collection_id
+            INNER JOIN documents d ON d.id = dc.document_id
+            WHERE d.deleted_at IS NULL
+        )
+        SELECT new_id AS "id!" FROM mapping WHERE old_id = $1
+        "#,
+        collection_id,
+        library.user_id(),
+        name,
+        library.group_id()
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let copy = sqlx::query_as!(
+        Collection,
+        r#"
+        SELECT id, user_id, group_id, name, parent_id, query, position, created_at, updated_at
+        FROM collections
+        WHERE id = $1
+        "#,
+        copy_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(copy))
+}
@@ -1,10 +1,60 @@
+use crate::{
+    password::PasswordPolicy,
+    rate_limit::{LockoutConfig, RateLimitConfig},
+};
+use jsonwebtoken::Algorithm;
 use sqlx::{PgPool, postgres::PgPoolOptions};
-use std::time::Duration;
+use std::{str::FromStr, time::Duration};
 
 #[derive(Clone)]
 pub struct Config {
     pub database_url: String,
     pub jwt_secret: String,
+    pub oidc_providers: Vec<OidcProviderConfig>,
+    pub auth_rate_limit: RateLimitConfig,
+    pub upload_rate_limit: RateLimitConfig,
+    pub chat_rate_limit: RateLimitConfig,
+    // Visitors of public collection links, who need no account
+    pub public_rate_limit: RateLimitConfig,
+    pub login_lockout: LockoutConfig,
+    pub default_storage_quota_bytes: Option<i64>,
+    pub account_deletion_grace_days: i64,
+    pub trash_retention_days: i32,
+    pub password_policy: PasswordPolicy,
+    pub embedding: Option<EmbeddingConfig>,
+}
+
+// One OpenID Connect identity provider, configured through OIDC_<NAME>_* variables
+#[derive(Debug, Clone)]
+pub struct OidcProviderConfig {
+    pub name: String,
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub scopes: String,
+    // ID token algorithms accepted when the signing key does not name its own
+    pub signing_algorithms: Vec<Algorithm>,
+    // Whether a verified email from this provider may sign in to an existing account with that
+    // email. Off by default: any provider that can assert the email would take over the account.
+    pub trust_email: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingProviderKind {
+    // Any server exposing POST /embeddings like OpenAI (vLLM, LM Studio, LocalAI, ...)
+    OpenAiCompatible,
+    // Ollama's native POST /api/embed
+    Ollama,
+}
+
+// Embedding model for semantic search, configured through EMBEDDING_* variables
+#[derive(Debug, Clone)]
+pub struct EmbeddingConfig {
+    pub provider: EmbeddingProviderKind,
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
 }
 
 impl Config {
@@ -16,9 +66,58 @@ impl Config {
         let jwt_secret =
             std::env::var("JWT_SECRET").expect("JWT_SECRET needs to be set in the .env file");
 
+        let oidc_providers = std::env::var("OIDC_PROVIDERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(OidcProviderConfig::from_env)
+            .collect();
+
+        let trust_forwarded_for = env_or("TRUST_PROXY_HEADERS", false);
+        let rate_limit = |key: &str, default: u32| RateLimitConfig {
+            requests_per_minute: env_or(key, default),
+            trust_forwarded_for,
+        };
+
+        let default_storage_quota_bytes = std::env::var("STORAGE_QUOTA_MB").ok().map(|mb| {
+            mb.parse::<i64>()
+                .expect("STORAGE_QUOTA_MB must be a whole number of megabytes")
+                * 1024
+                * 1024
+        });
+
+        // The hourly purge removes whatever was deleted longer ago than this, so anything below
+        // a day would empty every trash
+        let trash_retention_days: i32 = env_or("TRASH_RETENTION_DAYS", 30);
+        if trash_retention_days < 1 {
+            panic!(
+                "TRASH_RETENTION_DAYS must be at least 1, got {}",
+                trash_retention_days
+            );
+        }
+
         Self {
             database_url,
             jwt_secret,
+            oidc_providers,
+            auth_rate_limit: rate_limit("RATE_LIMIT_AUTH_PER_MINUTE", 10),
+            upload_rate_limit: rate_limit("RATE_LIMIT_UPLOAD_PER_MINUTE", 10),
+            chat_rate_limit: rate_limit("RATE_LIMIT_CHAT_PER_MINUTE", 20),
+            public_rate_limit: rate_limit("RATE_LIMIT_PUBLIC_PER_MINUTE", 60),
+            login_lockout: LockoutConfig {
+                threshold: env_or("LOGIN_LOCKOUT_THRESHOLD", 5),
+                base_seconds: env_or("LOGIN_LOCKOUT_BASE_SECONDS", 30),
+                max_seconds: env_or("LOGIN_LOCKOUT_MAX_SECONDS", 3600),
+            },
+            default_storage_quota_bytes,
+            account_deletion_grace_days: env_or("ACCOUNT_DELETION_GRACE_DAYS", 14),
+            trash_retention_days,
+            password_policy: PasswordPolicy {
+                min_length: env_or("PASSWORD_MIN_LENGTH", 8),
+                reject_common: env_or("PASSWORD_REJECT_COMMON", true),
+            },
+            embedding: EmbeddingConfig::from_env(),
         }
     }
     pub async fn create_pool(&self) -> Result<PgPool, sqlx::Error> {
@@ -29,3 +128,92 @@ impl Config {
             .await
     }
 }
+
+impl OidcProviderConfig {
+    fn from_env(name: &str) -> Self {
+        let prefix = format!("OIDC_{}", name.to_uppercase().replace('-', "_"));
+        let var = |key: &str| std::env::var(format!("{}_{}", prefix, key)).ok();
+        let required = |key: &str| {
+            var(key)
+                .unwrap_or_else(|| panic!("{}_{} needs to be set in the .env file", prefix, key))
+        };
+
+        Self {
+            name: name.to_string(),
+            issuer_url: required("ISSUER_URL").trim_end_matches('/').to_string(),
+            client_id: required("CLIENT_ID"),
+            client_secret: var("CLIENT_SECRET"),
+            redirect_url: required("REDIRECT_URL"),
+            scopes: var("SCOPES").unwrap_or_else(|| "openid email profile".to_string()),
+            // RS256 is the algorithm every OpenID provider must support
+            signing_algorithms: var("ID_TOKEN_ALGORITHMS")
+                .unwrap_or_else(|| "RS256".to_string())
+                .split(',')
+                .map(str::trim)
+                .filter(|alg| !alg.is_empty())
+                .map(|alg| match Algorithm::from_str(alg) {
+                    Ok(Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) | Err(_) => panic!(
+                        "{}_ID_TOKEN_ALGORITHMS has an invalid algorithm: {}",
+                        prefix, alg
+                    ),
+                    Ok(alg) => alg,
+                })
+                .collect(),
+            trust_email: var("TRUST_EMAIL").is_some_and(|value| {
+                value.parse().unwrap_or_else(|_| {
+                    panic!("{}_TRUST_EMAIL has an invalid value: {}", prefix, value)
+                })
+            }),
+        }
+    }
+}
+
+impl EmbeddingConfig {
+    // None when EMBEDDING_PROVIDER is unset, which disables semantic search
+    fn from_env() -> Option<Self> {
+        let var = |key: &str| std::env::var(key).ok().filter(|v| !v.trim().is_empty());
+
+        let (provider, default_url, default_model) = match var("EMBEDDING_PROVIDER")?.as_str() {
+            "openai" => (
+                EmbeddingProviderKind::OpenAiCompatible,
+                "https://api.openai.com/v1",
+                "text-embedding-3-small",
+            ),
+            "ollama" => (
+                EmbeddingProviderKind::Ollama,
+                "http://localhost:11434",
+                "nomic-embed-text",
+            ),
+            other => panic!(
+                "EMBEDDING_PROVIDER has an invalid value: {} (expected openai or ollama)",
+                other
+            ),
+        };
+
+        let api_key = var("EMBEDDING_API_KEY").or_else(|| {
+            (provider == EmbeddingProviderKind::OpenAiCompatible)
+                .then(|| var("OPENAI_API_KEY"))
+                .flatten()
+        });
+
+        Some(Self {
+            provider,
+            base_url: var("EMBEDDING_BASE_URL")
+                .unwrap_or_else(|| default_url.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            api_key,
+            model: var("EMBEDDING_MODEL").unwrap_or_else(|| default_model.to_string()),
+        })
+    }
+}
+
+// Optional variable parsed into T, falling back to `default` when unset
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    match std::env::var(key) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} has an invalid value: {}", key, value)),
+        Err(_) => default,
+    }
+}
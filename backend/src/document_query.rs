@@ -0,0 +1,277 @@
+// Filtering, sorting and keyset pagination for document listings
+use crate::models::Document;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+// Columns of `Document`, for a `documents d` row source
+pub const DOCUMENT_COLUMNS: &str = "d.id, d.user_id, d.group_id, d.title, d.authors, d.year, d.publication_type, \
+     d.journal, d.volume, d.issue, d.pages, d.publisher, d.doi, d.url, d.abstract_text, \
+     d.keywords, d.stored_file_path AS pdf_url, d.created_at, d.updated_at";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    Title,
+    Year,
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+    FirstAuthor,
+}
+
+impl SortField {
+    // Non-null sort key so keyset comparisons behave; year and author gaps sort lowest
+    fn expression(self) -> &'static str {
+        match self {
+            SortField::Title => "LOWER(d.title)",
+            SortField::Year => "COALESCE(d.year, 0)",
+            SortField::CreatedAt => "d.created_at",
+            SortField::UpdatedAt => "d.updated_at",
+            SortField::FirstAuthor => "LOWER(COALESCE(d.authors[1], ''))",
+        }
+    }
+
+    // The sort key as text for cursors; timestamps in RFC 3339 so they parse back in Rust
+    fn key_text(self) -> String {
+        match self {
+            SortField::CreatedAt | SortField::UpdatedAt => format!(
+                "to_char({} AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS.US\"Z\"')",
+                self.expression()
+            ),
+            SortField::Title | SortField::Year | SortField::FirstAuthor => {
+                format!("({})::TEXT", self.expression())
+            }
+        }
+    }
+
+    // A cursor's text key as a value of this sort's type; None when it does not parse
+    fn parse_key(self, key: &str) -> Option<SortKey> {
+        match self {
+            SortField::Title | SortField::FirstAuthor => Some(SortKey::Text(key.to_string())),
+            SortField::Year => key.parse().ok().map(SortKey::Year),
+            SortField::CreatedAt | SortField::UpdatedAt => DateTime::parse_from_rfc3339(key)
+                .ok()
+                .map(|at| SortKey::Timestamp(at.with_timezone(&Utc))),
+        }
+    }
+}
+
+enum SortKey {
+    Text(String),
+    Year(i32),
+    Timestamp(DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    fn keyword(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DocumentFilters {
+    pub year_from: Option<i32>,
+    pub year_to: Option<i32>,
+    pub publication_type: Option<String>,
+    pub journal: Option<String>,
+    pub has_pdf: Option<bool>,
+    pub collection_id: Option<Uuid>,
+}
+
+// Paging and sort parameters; filters are extracted separately from the same query string
+#[derive(Debug, Deserialize)]
+pub struct DocumentListQuery {
+    #[serde(default)]
+    pub sort: SortField,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+impl DocumentListQuery {
+    pub fn page_size(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+}
+
+// A listed document together with its sort key as text
+#[derive(Debug, FromRow)]
+pub struct SortedDocument {
+    #[sqlx(flatten)]
+    pub document: Document,
+    pub sort_key: String,
+}
+
+// Position after the last row of a page; only valid for the sort it was issued for
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cursor {
+    pub sort: SortField,
+    pub order: SortOrder,
+    pub key: String,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    // Client-supplied, so the key is checked against the sort before it reaches SQL
+    pub fn decode(
+        value: &str,
+        sort: SortField,
+        order: SortOrder,
+    ) -> Result<CursorPosition, String> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(value)
+            .map_err(|_| "Invalid cursor".to_string())?;
+        let cursor: Cursor =
+            serde_json::from_slice(&bytes).map_err(|_| "Invalid cursor".to_string())?;
+
+        if cursor.sort != sort || cursor.order != order {
+            return Err("Cursor does not match the requested sort".to_string());
+        }
+        let key = sort
+            .parse_key(&cursor.key)
+            .ok_or_else(|| "Invalid cursor".to_string())?;
+
+        Ok(CursorPosition { key, id: cursor.id })
+    }
+}
+
+// A decoded cursor with its key typed for the sort
+pub struct CursorPosition {
+    key: SortKey,
+    id: Uuid,
+}
+
+// Append `AND ...` conditions for the filters; the query must already have a WHERE clause
+pub fn push_filters(qb: &mut QueryBuilder<'_, Postgres>, filters: &DocumentFilters) {
+    if let Some(year_from) = filters.year_from {
+        qb.push(" AND d.year >= ").push_bind(year_from);
+    }
+    if let Some(year_to) = filters.year_to {
+        qb.push(" AND d.year <= ").push_bind(year_to);
+    }
+    if let Some(publication_type) = &filters.publication_type {
+        qb.push(" AND d.publication_type = ")
+            .push_bind(publication_type.clone());
+    }
+    if let Some(journal) = &filters.journal {
+        qb.push(" AND LOWER(d.journal) = LOWER(")
+            .push_bind(journal.clone())
+            .push(")");
+    }
+    match filters.has_pdf {
+        Some(true) => {
+            qb.push(" AND d.stored_file_path IS NOT NULL");
+        }
+        Some(false) => {
+            qb.push(" AND d.stored_file_path IS NULL");
+        }
+        None => {}
+    }
+    if let Some(collection_id) = filters.collection_id {
+        qb.push(
+            " AND EXISTS (SELECT 1 FROM document_collections dc \
+             WHERE dc.document_id = d.id AND dc.collection_id = ",
+        )
+        .push_bind(collection_id)
+        .push(")");
+    }
+}
+
+// Append the keyset condition for rows after `cursor`
+pub fn push_cursor(
+    qb: &mut QueryBuilder<'_, Postgres>,
+    sort: SortField,
+    order: SortOrder,
+    cursor: &CursorPosition,
+) {
+    let comparison = match order {
+        SortOrder::Asc => ">",
+        SortOrder::Desc => "<",
+    };
+
+    qb.push(format!(
+        " AND ({}, d.id) {} (",
+        sort.expression(),
+        comparison
+    ));
+    match &cursor.key {
+        SortKey::Text(key) => qb.push_bind(key.clone()),
+        SortKey::Year(key) => qb.push_bind(*key),
+        SortKey::Timestamp(key) => qb.push_bind(*key),
+    };
+    qb.push(", ").push_bind(cursor.id).push(")");
+}
+
+// Select list extra: the sort key as text, read back to build the next cursor
+pub fn sort_key_column(sort: SortField) -> String {
+    format!("{} AS sort_key", sort.key_text())
+}
+
+pub fn push_order_by(qb: &mut QueryBuilder<'_, Postgres>, sort: SortField, order: SortOrder) {
+    qb.push(format!(
+        " ORDER BY {expr} {dir}, d.id {dir}",
+        expr = sort.expression(),
+        dir = order.keyword()
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(sort: SortField, key: &str) -> String {
+        Cursor {
+            sort,
+            order: SortOrder::Desc,
+            key: key.to_string(),
+            id: Uuid::nil(),
+        }
+        .encode()
+    }
+
+    #[test]
+    fn cursor_keys_must_parse_as_the_sort_type() {
+        let year = encoded(SortField::Year, "2019");
+        assert!(Cursor::decode(&year, SortField::Year, SortOrder::Desc).is_ok());
+        let created = encoded(SortField::CreatedAt, "2025-11-06T12:00:00.123456Z");
+        assert!(Cursor::decode(&created, SortField::CreatedAt, SortOrder::Desc).is_ok());
+
+        let forged = encoded(SortField::Year, "2019; DROP TABLE documents");
+        assert!(Cursor::decode(&forged, SortField::Year, SortOrder::Desc).is_err());
+        let stale = encoded(SortField::UpdatedAt, "2025-11-06 12:00:00.123456+00");
+        assert!(Cursor::decode(&stale, SortField::UpdatedAt, SortOrder::Desc).is_err());
+    }
+
+    #[test]
+    fn cursors_only_decode_for_their_own_sort() {
+        let title = encoded(SortField::Title, "graphs");
+        assert!(Cursor::decode(&title, SortField::Title, SortOrder::Desc).is_ok());
+        assert!(Cursor::decode(&title, SortField::Title, SortOrder::Asc).is_err());
+        assert!(Cursor::decode(&title, SortField::FirstAuthor, SortOrder::Desc).is_err());
+        assert!(Cursor::decode("not a cursor", SortField::Title, SortOrder::Desc).is_err());
+    }
+}
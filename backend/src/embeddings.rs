@@ -0,0 +1,473 @@
+// Embedding providers and the background task keeping document chunk embeddings current
+use crate::{
+    config::{EmbeddingConfig, EmbeddingProviderKind},
+    state::AppState,
+};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+use uuid::Uuid;
+
+// Words per chunk of PDF text, and how many of them consecutive chunks share
+const CHUNK_WORDS: usize = 200;
+const CHUNK_OVERLAP_WORDS: usize = 40;
+
+// Bounds the cost of embedding one very long document
+const MAX_CHUNKS_PER_DOCUMENT: usize = 200;
+
+// Texts sent per provider request
+const EMBED_BATCH_SIZE: usize = 32;
+
+// Documents embedded per pass of the background task, and how often it looks for work
+const DOCUMENTS_PER_PASS: i64 = 10;
+const EMBEDDING_INTERVAL: Duration = Duration::from_secs(30);
+
+// A failed document is retried after this long, unless its text or the model changes first
+const RETRY_AFTER_HOURS: i32 = 1;
+
+pub type EmbedFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<Vec<f32>>, String>> + Send + 'a>>;
+
+pub trait EmbeddingProvider: Send + Sync {
+    // Embeddings are only ever compared with others from the same model
+    fn model(&self) -> &str;
+
+    // One vector per input text, in input order
+    fn embed<'a>(&'a self, texts: &'a [String]) -> EmbedFuture<'a>;
+}
+
+pub fn provider_from_config(config: &EmbeddingConfig) -> Arc<dyn EmbeddingProvider> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(60))
+        .build()
+        .expect("Failed to build HTTP client");
+
+    match config.provider {
+        EmbeddingProviderKind::OpenAiCompatible => Arc::new(OpenAiCompatibleProvider {
+            client,
+            base_url: config.base_url.clone(),
+            api_key: config.api_key.clone(),
+            model: config.model.clone(),
+        }),
+        EmbeddingProviderKind::Ollama => Arc::new(OllamaProvider {
+            client,
+            base_url: config.base_url.clone(),
+            model: config.model.clone(),
+        }),
+    }
+}
+
+pub struct OpenAiCompatibleProvider {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbeddingResponse {
+    data: Vec<OpenAiEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+impl EmbeddingProvider for OpenAiCompatibleProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed<'a>(&'a self, texts: &'a [String]) -> EmbedFuture<'a> {
+        Box::pin(async move {
+            let mut request = self
+                .client
+                .post(format!("{}/embeddings", self.base_url))
+                .json(&json!({ "model": self.model, "input": texts }));
+            if let Some(api_key) = &self.api_key {
+                request = request.bearer_auth(api_key);
+            }
+
+            let mut body: OpenAiEmbeddingResponse = request
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| format!("Embedding request failed: {}", e))?
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse embedding response: {}", e))?;
+
+            // Entries carry their input position and need not arrive in order
+            body.data.sort_by_key(|e| e.index);
+            Ok(body.data.into_iter().map(|e| e.embedding).collect())
+        })
+    }
+}
+
+pub struct OllamaProvider {
+    client: reqwest::Client,
+    base_url: String,
+    model: String,
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+impl EmbeddingProvider for OllamaProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed<'a>(&'a self, texts: &'a [String]) -> EmbedFuture<'a> {
+        Box::pin(async move {
+            let body: OllamaEmbedResponse = self
+                .client
+                .post(format!("{}/api/embed", self.base_url))
+                .json(&json!({ "model": self.model, "input": texts }))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| format!("Embedding request failed: {}", e))?
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse embedding response: {}", e))?;
+
+            Ok(body.embeddings)
+        })
+    }
+}
+
+// Embed any number of texts in provider-sized batches
+async fn embed_all(
+    provider: &dyn EmbeddingProvider,
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>, String> {
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(EMBED_BATCH_SIZE) {
+        let embedded = provider.embed(batch).await?;
+        if embedded.len() != batch.len() {
+            return Err(format!(
+                "Embedding provider returned {} vectors for {} texts",
+                embedded.len(),
+                batch.len()
+            ));
+        }
+        vectors.extend(embedded);
+    }
+    Ok(vectors)
+}
+
+pub async fn embed_query(
+    provider: &dyn EmbeddingProvider,
+    query: &str,
+) -> Result<Vec<f32>, String> {
+    embed_all(provider, &[query.to_string()])
+        .await?
+        .pop()
+        .ok_or_else(|| "Embedding provider returned no vector".to_string())
+}
+
+// pgvector's text representation, e.g. [0.1,-0.2]; bound as TEXT and cast to vector
+pub fn vector_literal(vector: &[f32]) -> String {
+    let values: Vec<String> = vector.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+// A string as a quoted SQL literal, for statements that can't take bind parameters
+pub fn sql_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+// Semantic search needs pgvector (e.g. the pgvector/pgvector Postgres image), so the chunk table
+// is created here once embeddings are configured instead of by a migration every deployment runs.
+// Embedded passages: title and abstract, then windows of the PDF text. The vector dimension is left
+// open so the model can change; only rows of the configured model are compared.
+pub async fn ensure_chunk_table(db: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query("CREATE EXTENSION IF NOT EXISTS vector")
+        .execute(db)
+        .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS document_chunks ( \
+            document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE, \
+            chunk_index INTEGER NOT NULL, \
+            source VARCHAR(20) NOT NULL, \
+            page_number INTEGER, \
+            content TEXT NOT NULL, \
+            model TEXT NOT NULL, \
+            embedding vector NOT NULL, \
+            PRIMARY KEY (document_id, chunk_index) \
+        )",
+    )
+    .execute(db)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_document_chunks_model ON document_chunks(model)")
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+// Chunk embeddings have no fixed dimension, so a model change needs no migration, but pgvector can
+// only index vectors of one dimension. Each model gets a partial HNSW index over its chunks cast
+// to its dimension, created once its first chunk shows the dimension. Semantic search repeats the
+// cast and the model literal so the planner can use it.
+pub async fn ensure_vector_index(db: &PgPool, model: &str) -> Result<bool, sqlx::Error> {
+    let dimensions: Option<i32> = sqlx::query_scalar(
+        "SELECT vector_dims(embedding) FROM document_chunks WHERE model = $1 LIMIT 1",
+    )
+    .bind(model)
+    .fetch_optional(db)
+    .await?;
+    let Some(dimensions) = dimensions else {
+        return Ok(false);
+    };
+
+    let digest = Sha256::digest(model.as_bytes());
+    let suffix: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    let sql = format!(
+        "CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_document_chunks_embedding_{suffix}_{dimensions} \
+        ON document_chunks USING hnsw ((embedding::vector({dimensions})) vector_cosine_ops) \
+        WHERE model = {model}",
+        model = sql_literal(model)
+    );
+    sqlx::query(&sql).execute(db).await?;
+
+    Ok(true)
+}
+
+struct Chunk {
+    source: &'static str,
+    page_number: Option<i32>,
+    content: String,
+}
+
+// Title and abstract as one chunk, then overlapping word windows over each page
+fn chunk_document(title: &str, abstract_text: Option<&str>, pages: &[(i32, String)]) -> Vec<Chunk> {
+    let metadata = match abstract_text.filter(|a| !a.trim().is_empty()) {
+        Some(abstract_text) => format!("{}\n\n{}", title, abstract_text),
+        None => title.to_string(),
+    };
+    let mut chunks = vec![Chunk {
+        source: "metadata",
+        page_number: None,
+        content: metadata,
+    }];
+
+    'pages: for (page_number, content) in pages {
+        let words: Vec<&str> = content.split_whitespace().collect();
+        let mut start = 0;
+        while start < words.len() {
+            if chunks.len() >= MAX_CHUNKS_PER_DOCUMENT {
+                break 'pages;
+            }
+            let end = (start + CHUNK_WORDS).min(words.len());
+            chunks.push(Chunk {
+                source: "page",
+                page_number: Some(*page_number),
+                content: words[start..end].join(" "),
+            });
+            if end == words.len() {
+                break;
+            }
+            start += CHUNK_WORDS - CHUNK_OVERLAP_WORDS;
+        }
+    }
+
+    chunks
+}
+
+// Replace a document's chunks with fresh embeddings and record what they were built from
+async fn embed_document(
+    db: &PgPool,
+    provider: &dyn EmbeddingProvider,
+    document_id: Uuid,
+    text_hash: &str,
+) -> Result<(), String> {
+    let db_err = |e: sqlx::Error| format!("Database error: {}", e);
+
+    let Some(document) = sqlx::query!(
+        "SELECT title, abstract_text FROM documents WHERE id = $1",
+        document_id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(db_err)?
+    else {
+        // Deleted since it was picked
+        return Ok(());
+    };
+
+    let pages: Vec<(i32, String)> = sqlx::query!(
+        "SELECT page_number, content FROM document_pages WHERE document_id = $1 ORDER BY page_number",
+        document_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(db_err)?
+    .into_iter()
+    .map(|p| (p.page_number, p.content))
+    .collect();
+
+    let chunks = chunk_document(&document.title, document.abstract_text.as_deref(), &pages);
+    let texts: Vec<String> = chunks.iter().map(|c| c.content.clone()).collect();
+    let vectors = embed_all(provider, &texts).await?;
+
+    let indexes: Vec<i32> = (0..chunks.len() as i32).collect();
+    let sources: Vec<String> = chunks.iter().map(|c| c.source.to_string()).collect();
+    let page_numbers: Vec<Option<i32>> = chunks.iter().map(|c| c.page_number).collect();
+    let embeddings: Vec<String> = vectors.iter().map(|v| vector_literal(v)).collect();
+
+    let mut tx = db.begin().await.map_err(db_err)?;
+
+    // Runtime queries: the vector type is not known to the offline query cache
+    sqlx::query("DELETE FROM document_chunks WHERE document_id = $1")
+        .bind(document_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_err)?;
+
+    sqlx::query(
+        "INSERT INTO document_chunks \
+            (document_id, chunk_index, source, page_number, content, model, embedding) \
+        SELECT $1, c.chunk_index, c.source, c.page_number, c.content, $2, c.embedding::vector \
+        FROM unnest($3::INTEGER[], $4::TEXT[], $5::INTEGER[], $6::TEXT[], $7::TEXT[]) \
+            AS c(chunk_index, source, page_number, content, embedding)",
+    )
+    .bind(document_id)
+    .bind(provider.model())
+    .bind(&indexes)
+    .bind(&sources)
+    .bind(&page_numbers)
+    .bind(&texts)
+    .bind(&embeddings)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_err)?;
+
+    record_status(
+        &mut tx,
+        document_id,
+        provider.model(),
+        text_hash,
+        chunks.len() as i32,
+        None,
+    )
+    .await
+    .map_err(db_err)?;
+
+    tx.commit().await.map_err(db_err)
+}
+
+async fn record_status(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    document_id: Uuid,
+    model: &str,
+    text_hash: &str,
+    chunk_count: i32,
+    error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO document_embedding_status
+            (document_id, model, text_hash, chunk_count, error, attempted_at)
+        VALUES ($1, $2, $3, $4, $5, NOW())
+        ON CONFLICT (document_id) DO UPDATE
+        SET model = $2, text_hash = $3, chunk_count = $4, error = $5, attempted_at = NOW()
+        "#,
+        document_id,
+        model,
+        text_hash,
+        chunk_count,
+        error
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+// Embed documents never embedded, or whose text or model changed; returns how many were tried
+pub async fn embed_stale_documents(
+    db: &PgPool,
+    provider: &dyn EmbeddingProvider,
+) -> Result<usize, sqlx::Error> {
+    // documents.text_hash covers everything chunk_document reads and is kept by triggers
+    let stale = sqlx::query!(
+        r#"
+        SELECT d.id, d.text_hash
+        FROM documents d
+        LEFT JOIN document_embedding_status s ON s.document_id = d.id
+        WHERE d.deleted_at IS NULL
+            AND (s.document_id IS NULL
+                OR s.model <> $1
+                OR s.text_hash <> d.text_hash
+                OR (s.error IS NOT NULL AND s.attempted_at < NOW() - make_interval(hours => $2)))
+        ORDER BY d.updated_at DESC
+        LIMIT $3
+        "#,
+        provider.model(),
+        RETRY_AFTER_HOURS,
+        DOCUMENTS_PER_PASS
+    )
+    .fetch_all(db)
+    .await?;
+
+    for document in &stale {
+        if let Err(e) = embed_document(db, provider, document.id, &document.text_hash).await {
+            eprintln!("Embedding document {} failed: {}", document.id, e);
+
+            // Remember the failure so the document waits before being retried
+            let mut tx = db.begin().await?;
+            record_status(
+                &mut tx,
+                document.id,
+                provider.model(),
+                &document.text_hash,
+                0,
+                Some(&e),
+            )
+            .await?;
+            tx.commit().await?;
+        }
+    }
+
+    Ok(stale.len())
+}
+
+pub fn spawn_embedding_task(state: AppState) {
+    let Some(provider) = state.embedder.clone() else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EMBEDDING_INTERVAL);
+        let mut indexed = false;
+        loop {
+            interval.tick().await;
+
+            // Work through the backlog, then wait for new changes
+            loop {
+                match embed_stale_documents(&state.db, provider.as_ref()).await {
+                    Ok(0) => break,
+                    Ok(count) => println!("Embedding pass covered {} document(s)", count),
+                    Err(e) => {
+                        eprintln!("Embedding pass failed: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            if !indexed {
+                match ensure_vector_index(&state.db, provider.model()).await {
+                    Ok(created) => indexed = created,
+                    Err(e) => eprintln!("Embedding index creation failed: {}", e),
+                }
+            }
+        }
+    });
+}
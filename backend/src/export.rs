@@ -0,0 +1,116 @@
+// Personal data export as a ZIP archive
+use crate::{
+    cleanup::is_managed_upload,
+    models::{Collection, Document, User},
+};
+use serde_json::{Value, json};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+};
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+pub struct ExportData {
+    pub user: User,
+    pub documents: Vec<Document>,
+    pub collections: Vec<Collection>,
+    pub memberships: Vec<Value>,
+}
+
+// Build the archive in an unlinked temp file and return it rewound, ready to stream.
+// Blocking: call from spawn_blocking.
+pub fn write_archive(data: &ExportData) -> Result<File, String> {
+    let path =
+        std::env::temp_dir().join(format!("scholarvault-export-{}.zip", uuid::Uuid::new_v4()));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to create export file: {}", e))?;
+
+    // The open handle keeps the data alive until the response has been streamed
+    let _ = std::fs::remove_file(&path);
+
+    let zip_err = |e: zip::result::ZipError| format!("Failed to write export archive: {}", e);
+    let io_err = |e: std::io::Error| format!("Failed to write export archive: {}", e);
+
+    let mut zip = ZipWriter::new(file);
+    let compressed = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    // PDFs and images are already compressed
+    let stored = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(true);
+
+    let add_json = |zip: &mut ZipWriter<File>, name: &str, value: &Value| {
+        zip.start_file(name, compressed).map_err(zip_err)?;
+        let bytes = serde_json::to_vec_pretty(value)
+            .map_err(|e| format!("Failed to serialize {}: {}", name, e))?;
+        zip.write_all(&bytes).map_err(io_err)
+    };
+
+    add_json(&mut zip, "profile.json", &json!(data.user))?;
+    add_json(&mut zip, "documents.json", &json!(data.documents))?;
+    add_json(
+        &mut zip,
+        "collections.json",
+        &json!({
+            "collections": data.collections,
+            "memberships": data.memberships,
+        }),
+    )?;
+
+    zip.start_file("library.bib", compressed).map_err(zip_err)?;
+    zip.write_all(crate::bibtex::documents_to_bibtex(&data.documents).as_bytes())
+        .map_err(io_err)?;
+
+    let mut missing_files = Vec::new();
+
+    let stored_files = data
+        .documents
+        .iter()
+        .filter_map(|d| d.pdf_url.as_deref().map(|p| (format!("pdfs/{}_", d.id), p)))
+        .chain(
+            data.user
+                .profile_image_url
+                .as_deref()
+                .map(|p| ("profile_image/".to_string(), p)),
+        );
+
+    for (prefix, stored_path) in stored_files {
+        if !is_managed_upload(stored_path) {
+            continue;
+        }
+
+        let Ok(bytes) = std::fs::read(stored_path) else {
+            missing_files.push(stored_path.to_string());
+            continue;
+        };
+
+        let file_name = Path::new(stored_path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        zip.start_file(format!("{}{}", prefix, file_name), stored)
+            .map_err(zip_err)?;
+        zip.write_all(&bytes).map_err(io_err)?;
+    }
+
+    add_json(
+        &mut zip,
+        "manifest.json",
+        &json!({
+            "exported_at": chrono::Utc::now(),
+            "document_count": data.documents.len(),
+            "collection_count": data.collections.len(),
+            "missing_files": missing_files,
+        }),
+    )?;
+
+    let mut file = zip.finish().map_err(zip_err)?;
+    file.seek(SeekFrom::Start(0)).map_err(io_err)?;
+
+    Ok(file)
+}
@@ -0,0 +1,147 @@
+// Facet counts for filter sidebars, over a search's matches or the whole library
+use crate::{groups::Library, query_parser::SearchExpr, search::matches_query};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool, Postgres, QueryBuilder};
+
+pub const DEFAULT_FACET_LIMIT: i64 = 20;
+pub const MAX_FACET_LIMIT: i64 = 100;
+
+#[derive(Debug, Default, Serialize)]
+pub struct DocumentFacets {
+    pub total: i64,
+    pub years: Vec<FacetCount>,
+    pub publication_types: Vec<FacetCount>,
+    pub journals: Vec<FacetCount>,
+    pub authors: Vec<FacetCount>,
+    pub keywords: Vec<FacetCount>,
+    pub collections: Vec<FacetCount>,
+}
+
+// `value` is what to filter on; collections carry their id as value and name as label
+#[derive(Debug, Serialize)]
+pub struct FacetCount {
+    pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub count: i64,
+}
+
+#[derive(FromRow)]
+struct FacetRow {
+    facet: String,
+    value: String,
+    label: Option<String>,
+    count: i64,
+}
+
+// Top values of one facet, most common first; `source` yields (value, label) per matching document
+fn push_facet(qb: &mut QueryBuilder<'static, Postgres>, facet: &str, source: &str, limit: i64) {
+    qb.push(format!(
+        "(SELECT '{facet}' AS facet, f.value, MIN(f.label) AS label, COUNT(DISTINCT f.id) AS count \
+        FROM ({source}) f WHERE f.value IS NOT NULL AND f.value <> '' \
+        GROUP BY f.value ORDER BY count DESC, f.value LIMIT "
+    ));
+    qb.push_bind(limit).push(")");
+}
+
+pub async fn document_facets(
+    db: &PgPool,
+    library: Library,
+    expr: Option<&SearchExpr>,
+    limit: i64,
+) -> Result<DocumentFacets, sqlx::Error> {
+    let limit = limit.clamp(1, MAX_FACET_LIMIT);
+
+    let mut qb = match expr {
+        Some(expr) => matches_query(library, expr, true, None),
+        None => {
+            let mut qb = QueryBuilder::new("WITH matches AS (SELECT * FROM documents d WHERE ");
+            library.push_condition(&mut qb, "d");
+            qb.push(" AND d.deleted_at IS NULL) ");
+            qb
+        }
+    };
+
+    // Every year is listed so the sidebar can draw a histogram
+    qb.push(
+        "SELECT 'total' AS facet, '' AS value, NULL::TEXT AS label, COUNT(*) AS count FROM matches \
+        UNION ALL \
+        (SELECT 'year', year::TEXT, NULL, COUNT(*) FROM matches WHERE year IS NOT NULL GROUP BY year) \
+        UNION ALL ",
+    );
+    push_facet(
+        &mut qb,
+        "publication_type",
+        "SELECT id, publication_type AS value, NULL::TEXT AS label FROM matches",
+        limit,
+    );
+    qb.push(" UNION ALL ");
+    push_facet(
+        &mut qb,
+        "journal",
+        "SELECT id, journal AS value, NULL::TEXT AS label FROM matches",
+        limit,
+    );
+    qb.push(" UNION ALL ");
+    push_facet(
+        &mut qb,
+        "author",
+        "SELECT m.id, a AS value, NULL::TEXT AS label FROM matches m, unnest(m.authors) AS a",
+        limit,
+    );
+    qb.push(" UNION ALL ");
+    // Keywords are matched case-insensitively, so they are counted that way too
+    push_facet(
+        &mut qb,
+        "keyword",
+        "SELECT m.id, LOWER(k) AS value, NULL::TEXT AS label FROM matches m, unnest(m.keywords) AS k",
+        limit,
+    );
+    qb.push(" UNION ALL ");
+    push_facet(
+        &mut qb,
+        "collection",
+        "SELECT m.id, c.id::TEXT AS value, c.name::TEXT AS label FROM matches m \
+        INNER JOIN document_collections dc ON dc.document_id = m.id \
+        INNER JOIN collections c ON c.id = dc.collection_id \
+        WHERE c.deleted_at IS NULL",
+        limit,
+    );
+
+    let rows: Vec<FacetRow> = qb.build_query_as().fetch_all(db).await?;
+
+    let mut facets = DocumentFacets::default();
+    for row in rows {
+        let count = FacetCount {
+            value: row.value,
+            label: row.label,
+            count: row.count,
+        };
+        match row.facet.as_str() {
+            "total" => facets.total = count.count,
+            "year" => facets.years.push(count),
+            "publication_type" => facets.publication_types.push(count),
+            "journal" => facets.journals.push(count),
+            "author" => facets.authors.push(count),
+            "keyword" => facets.keywords.push(count),
+            "collection" => facets.collections.push(count),
+            _ => {}
+        }
+    }
+
+    // UNION ALL does not promise to keep each branch's order
+    facets
+        .years
+        .sort_by_key(|year| std::cmp::Reverse(year.value.parse::<i32>().ok()));
+    for values in [
+        &mut facets.publication_types,
+        &mut facets.journals,
+        &mut facets.authors,
+        &mut facets.keywords,
+        &mut facets.collections,
+    ] {
+        values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    }
+
+    Ok(facets)
+}
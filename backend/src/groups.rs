@@ -0,0 +1,132 @@
+// Group libraries: who may do what in a group, and whose library an item belongs to
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+// Ordered from least to most privileged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GroupRole {
+    // Sees the library
+    Reader,
+    // Adds, edits and deletes documents and collections
+    Member,
+    // Also manages members and renames the group
+    Admin,
+    // Also appoints admins and deletes the group; charged for its storage
+    Owner,
+}
+
+impl GroupRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GroupRole::Reader => "reader",
+            GroupRole::Member => "member",
+            GroupRole::Admin => "admin",
+            GroupRole::Owner => "owner",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "reader" => Some(GroupRole::Reader),
+            "member" => Some(GroupRole::Member),
+            "admin" => Some(GroupRole::Admin),
+            "owner" => Some(GroupRole::Owner),
+            _ => None,
+        }
+    }
+}
+
+// The user's role in a group; None when they are not a member
+pub async fn group_role(
+    db: &PgPool,
+    user_id: Uuid,
+    group_id: Uuid,
+) -> Result<Option<GroupRole>, sqlx::Error> {
+    let role = sqlx::query_scalar!(
+        "SELECT role FROM group_members WHERE group_id = $1 AND user_id = $2",
+        group_id,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(role.as_deref().and_then(GroupRole::parse))
+}
+
+// Whose library a document or collection belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Library {
+    User(Uuid),
+    Group(Uuid),
+}
+
+impl Library {
+    // From a row's owner columns, of which the schema guarantees exactly one is set
+    pub fn of(user_id: Option<Uuid>, group_id: Option<Uuid>) -> Self {
+        match group_id {
+            Some(group_id) => Library::Group(group_id),
+            None => Library::User(user_id.unwrap_or_default()),
+        }
+    }
+
+    // Bound as a pair in static queries: `(user_id = $n OR group_id = $m)`
+    pub fn user_id(self) -> Option<Uuid> {
+        match self {
+            Library::User(user_id) => Some(user_id),
+            Library::Group(_) => None,
+        }
+    }
+
+    pub fn group_id(self) -> Option<Uuid> {
+        match self {
+            Library::User(_) => None,
+            Library::Group(group_id) => Some(group_id),
+        }
+    }
+
+    // Identifies the library for advisory locks
+    pub fn id(self) -> Uuid {
+        match self {
+            Library::User(id) | Library::Group(id) => id,
+        }
+    }
+
+    // Append a condition limiting `alias` to this library's rows
+    pub fn push_condition(self, qb: &mut QueryBuilder<'static, Postgres>, alias: &str) {
+        match self {
+            Library::User(user_id) => qb.push(format!("{}.user_id = ", alias)).push_bind(user_id),
+            Library::Group(group_id) => qb
+                .push(format!("{}.group_id = ", alias))
+                .push_bind(group_id),
+        };
+    }
+}
+
+// The library of a document that is not in the trash; None when there is no such document
+pub async fn document_library(
+    db: &PgPool,
+    document_id: Uuid,
+) -> Result<Option<Library>, sqlx::Error> {
+    let document = sqlx::query!(
+        "SELECT user_id, group_id FROM documents WHERE id = $1 AND deleted_at IS NULL",
+        document_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(document.map(|d| Library::of(d.user_id, d.group_id)))
+}
+
+pub async fn collection_library(
+    db: &PgPool,
+    collection_id: Uuid,
+) -> Result<Option<Library>, sqlx::Error> {
+    let collection = sqlx::query!(
+        "SELECT user_id, group_id FROM collections WHERE id = $1 AND deleted_at IS NULL",
+        collection_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(collection.map(|c| Library::of(c.user_id, c.group_id)))
+}
@@ -1,23 +1,115 @@
 use axum::{
     Json,
+    body::Body,
     extract::{Multipart, Path, Query, State},
-    http::StatusCode,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
 };
 use serde_json::{Value, json};
+use sqlx::QueryBuilder;
+use std::time::Duration;
+use tokio_util::io::ReaderStream;
 
 use crate::{
-    auth::create_jwt,
-    middleware::AuthUser,
+    auth::{
+        FIRST_PASSWORD_MAX_SIGN_IN_AGE_SECS, create_impersonation_jwt, create_jwt, signed_in_within,
+    },
+    bulk::{BulkError, BulkItemResult, BulkRequest, apply_bulk_action},
+    collection_delete::{DeleteCollectionReport, DeleteMode},
+    collection_tree::{
+        CollectionNode, PathEntry, collection_path, collection_tree, lock_collection_tree,
+    },
+    document_query::{
+        Cursor, DOCUMENT_COLUMNS, DocumentFilters, DocumentListQuery, SortedDocument, push_cursor,
+        push_filters, push_order_by, sort_key_column,
+    },
+    embeddings::embed_query,
+    facets::{DEFAULT_FACET_LIMIT, DocumentFacets},
+    groups::{GroupRole, Library, collection_library, document_library, group_role},
+    middleware::{AdminUser, AuthUser},
     models::{
-        Collection, CreateCollection, CreateDocument, CreateUser, Document, LoginRequest,
-        LoginResponse, UpdateCollection, UpdateDocument, UpdateProfile, User, UserResponse,
+        AddGroupMember, AdminUserSummary, AuditLogEntry, ChangePassword, Collection,
+        CollectionLink, CollectionShare, CreateCollection, CreateCollectionLink, CreateDocument,
+        CreateGroup, CreateShare, CreateUser, DeleteAccountRequest, Document, DocumentPage,
+        DuplicateCollection, Group, GroupMember, ImpersonateRequest, LoginRequest, LoginResponse,
+        OidcCallbackRequest, PublicCollection, PublicDocument, ReorderRequest, ShareInvitation,
+        SharedCollection, UpdateCollection, UpdateDocument, UpdateGroup, UpdateGroupMember,
+        UpdateProfile, UpdateUserQuota, UpdateUserRole, UpdateUserStatus, User, UserResponse,
+    },
+    ordering::reorder,
+    password::{hash_password, needs_rehash, verify_password},
+    query_parser::parse as parse_search_query,
+    rate_limit::too_many_requests,
+    search::{
+        DEFAULT_SEARCH_LIMIT, SearchResult, SemanticResult, attach_page_matches,
+        highlight_snippets, resolve_scope, search_documents_query,
+        semantic_search as semantic_search_query, smart_collection_query,
+        smart_collections_containing_query,
+    },
+    sharing::{Access, collection_access, document_access},
+    similar::{DEFAULT_SIMILAR_LIMIT, SimilarDocument},
+    state::AppState,
+    suggest::{DEFAULT_SUGGEST_LIMIT, MIN_SUGGEST_PREFIX_CHARS, Suggestion},
+    trash::{
+        RestoreReport, TrashContents, list_trash, purge_collection, purge_document,
+        restore_collection, restore_document, trashed_collection_library, trashed_document_library,
     },
-    state::{self, AppState},
 };
 
 #[derive(serde::Deserialize)]
 pub struct SearchQuery {
+    // Advanced query syntax, see query_parser
+    pub q: String,
+    pub limit: Option<i64>,
+    pub collection_id: Option<uuid::Uuid>,
+    #[serde(default)]
+    pub include_subcollections: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SemanticSearchQuery {
+    // Natural language, embedded as is
     pub q: String,
+    pub limit: Option<i64>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SuggestQuery {
+    pub prefix: String,
+    pub limit: Option<i64>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeleteCollectionQuery {
+    #[serde(default)]
+    pub mode: DeleteMode,
+    // Report what would be affected without changing anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SimilarQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct FacetQuery {
+    // Optional search; without it the facets cover the whole library
+    pub q: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct BibliographyQuery {
+    // "bibtex" (the default) or "text" for APA-style references
+    pub format: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct AuditLogQuery {
+    pub limit: Option<i64>,
+    pub target_user_id: Option<uuid::Uuid>,
 }
 
 #[derive(serde::Deserialize)]
@@ -41,8 +133,19 @@ pub async fn register_user(
     State(state): State<AppState>,
     Json(payload): Json<CreateUser>,
 ) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    // Enforce password rules
+    if let Err(problems) = state
+        .password_policy
+        .validate(&payload.password, &payload.email)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": problems.join(". "), "problems": problems })),
+        ));
+    }
+
     // Hash password
-    let password_hash = bcrypt::hash(&payload.password, bcrypt::DEFAULT_COST).map_err(|_| {
+    let password_hash = hash_password(&payload.password).await.map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({ "error": "Failed to hash password" })),
@@ -54,7 +157,7 @@ pub async fn register_user(
         r#"
         INSERT INTO users (email, password_hash, username)
         VALUES ($1, $2, $3)
-        RETURNING id, email, username, profile_image_url, created_at
+        RETURNING id, email, username, profile_image_url, role, created_at
         "#,
         payload.email,
         password_hash,
@@ -65,13 +168,18 @@ pub async fn register_user(
 
     match result {
         Ok(user) => {
-            let token =
-                create_jwt(&user.id.to_string(), &user.email, &state.jwt_secret).map_err(|_| {
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        Json(json!({"error": "Failed to generate token"})),
-                    )
-                })?;
+            let token = create_jwt(
+                &user.id.to_string(),
+                &user.email,
+                &user.role,
+                &state.jwt_secret,
+            )
+            .map_err(|_| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": "Failed to generate token"})),
+                )
+            })?;
 
             Ok((
                 StatusCode::CREATED,
@@ -82,6 +190,7 @@ pub async fn register_user(
                         "email": user.email,
                         "username": user.username,
                         "profile_image_url": user.profile_image_url,
+                        "role": user.role,
                     }
                 })),
             ))
@@ -102,11 +211,20 @@ pub async fn register_user(
 pub async fn login_user(
     State(state): State<AppState>,
     Json(payload): Json<LoginRequest>,
-) -> Result<(StatusCode, Json<LoginResponse>), (StatusCode, Json<Value>)> {
+) -> Result<(StatusCode, Json<LoginResponse>), Response> {
+    let db_error = |_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Database error"})),
+        )
+            .into_response()
+    };
+
     // Find user
-    let user = sqlx::query!(
+    let user = sqlx::query_as!(
+        User,
         r#"
-        SELECT id, email, password_hash, username, profile_image_url, created_at, updated_at
+        SELECT id, email, password_hash, username, profile_image_url, role, disabled_at, created_at, updated_at
         FROM users
         WHERE email = $1
         "#,
@@ -114,40 +232,85 @@ pub async fn login_user(
     )
     .fetch_optional(&state.db)
     .await
-    .map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Database error"})),
-        )
-    })?;
+    .map_err(db_error)?;
 
-    // Check if user exists
-    let user = user.ok_or((
-        StatusCode::UNAUTHORIZED,
-        Json(json!({"error": "Invalid email or password"})),
-    ))?;
+    // Unknown emails are only limited per IP; known accounts count every attempt up front
+    if let Some(user) = &user {
+        claim_login_attempt(&state, user.id).await?;
+    }
 
-    // Verify password
-    let password_matches =
-        bcrypt::verify(&payload.password, &user.password_hash).map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to verify password"})),
-            )
-        })?;
+    // Verify password (accounts created through single sign-on have no local password)
+    let verified_user = match user {
+        Some(user) => match user.password_hash.as_deref() {
+            Some(password_hash) => {
+                let password_matches = verify_password(&payload.password, password_hash)
+                    .await
+                    .map_err(|_| {
+                        (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            Json(json!({"error": "Failed to verify password"})),
+                        )
+                            .into_response()
+                    })?;
+                password_matches.then_some(user)
+            }
+            None => None,
+        },
+        None => None,
+    };
 
-    if !password_matches {
+    let Some(user) = verified_user else {
         return Err((
             StatusCode::UNAUTHORIZED,
             Json(json!({"error": "Invalid email or password"})),
-        ));
+        )
+            .into_response());
+    };
+
+    if user.disabled_at.is_some() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Account is disabled"})),
+        )
+            .into_response());
+    }
+
+    // Upgrade bcrypt (or outdated Argon2) hashes now that we know the password
+    if user.password_hash.as_deref().is_some_and(needs_rehash) {
+        match hash_password(&payload.password).await {
+            Ok(new_hash) => {
+                sqlx::query!(
+                    "UPDATE users SET password_hash = $1 WHERE id = $2",
+                    new_hash,
+                    user.id
+                )
+                .execute(&state.db)
+                .await
+                .map_err(db_error)?;
+            }
+            Err(e) => eprintln!("Password rehash failed: {}", e),
+        }
     }
+
+    // A successful login clears the failure history
+    sqlx::query!("DELETE FROM login_failures WHERE user_id = $1", user.id)
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+
     // Generate token
-    let token = create_jwt(&user.id.to_string(), &user.email, &state.jwt_secret).map_err(|_| {
+    let token = create_jwt(
+        &user.id.to_string(),
+        &user.email,
+        &user.role,
+        &state.jwt_secret,
+    )
+    .map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": "Failed to generate token"})),
         )
+            .into_response()
     })?;
 
     // Return token and user info
@@ -160,119 +323,214 @@ pub async fn login_user(
                 email: user.email,
                 username: user.username,
                 profile_image_url: user.profile_image_url,
+                role: user.role,
             },
         }),
     ))
 }
 
-pub async fn get_current_user(
-    AuthUser(claims): AuthUser,
-    State(state): State<AppState>,
-) -> Result<Json<UserResponse>, (StatusCode, Json<Value>)> {
-    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+// Internal helper: count a login attempt as failed until it succeeds, refusing it while the
+// account is locked. Counting, checking and locking happen in one statement, so concurrent
+// attempts cannot all pass the check before any of them is counted. From the threshold on,
+// each attempt locks the account for twice as long as the previous one.
+async fn claim_login_attempt(state: &AppState, user_id: uuid::Uuid) -> Result<(), Response> {
+    let db_error = |_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Invalid user ID"})),
+            Json(json!({"error": "Database error"})),
         )
-    })?;
+            .into_response()
+    };
+    let lockout = &state.login_lockout;
 
-    let user = sqlx::query_as!(
-        User,
-        r#"SELECT id, email, password_hash, username, profile_image_url, created_at, updated_at FROM users WHERE id = $1"#,
+    // Failures older than a day no longer count towards a lockout
+    let claimed = sqlx::query_scalar!(
+        r#"
+        INSERT INTO login_failures AS f (user_id, failed_count, last_failed_at, locked_until)
+        VALUES (
+            $1, 1, NOW(),
+            CASE WHEN 1 >= $2::INT THEN NOW() + make_interval(secs => LEAST($4::FLOAT8, $3::FLOAT8)) END
+        )
+        ON CONFLICT (user_id) DO UPDATE
+        SET failed_count = CASE
+                WHEN f.last_failed_at < NOW() - INTERVAL '1 day' THEN 1
+                ELSE f.failed_count + 1
+            END,
+            last_failed_at = NOW(),
+            locked_until = (
+                SELECT CASE WHEN n >= $2 THEN NOW() + make_interval(secs =>
+                    LEAST($4, $3 * power(2, LEAST(n - $2, 32)))) END
+                FROM (SELECT CASE
+                    WHEN f.last_failed_at < NOW() - INTERVAL '1 day' THEN 1
+                    ELSE f.failed_count + 1
+                END AS n) next
+            )
+        WHERE f.locked_until IS NULL OR f.locked_until <= NOW()
+        RETURNING failed_count
+        "#,
+        user_id,
+        lockout.threshold,
+        lockout.base_seconds as f64,
+        lockout.max_seconds as f64
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    if claimed.is_some() {
+        return Ok(());
+    }
+
+    let remaining_seconds = sqlx::query_scalar!(
+        r#"
+        SELECT EXTRACT(EPOCH FROM (locked_until - NOW()))::FLOAT8 AS "remaining_seconds!"
+        FROM login_failures
+        WHERE user_id = $1 AND locked_until > NOW()
+        "#,
         user_id
     )
-    .fetch_one(&state.db)
+    .fetch_optional(&state.db)
     .await
-    .map_err(|_| {
-        (
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "User not found"})),
-        )
-    })?;
+    .map_err(db_error)?
+    .unwrap_or(1.0);
 
-    Ok(Json(UserResponse {
-        id: user.id,
-        email: user.email,
-        username: user.username,
-        profile_image_url: user.profile_image_url,
-    }))
+    Err(too_many_requests(Duration::from_secs_f64(
+        remaining_seconds,
+    )))
 }
 
-pub async fn create_document(
-    AuthUser(claims): AuthUser,
-    State(state): State<AppState>,
-    Json(payload): Json<CreateDocument>,
-) -> Result<(StatusCode, Json<Document>), (StatusCode, Json<Value>)> {
-    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error":"Invalid user ID"})),
-        )
-    })?;
+pub async fn list_oidc_providers(State(state): State<AppState>) -> Json<Value> {
+    let providers: Vec<&str> = state
+        .oidc_providers
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect();
 
-    let document = create_document_internal(&state, user_id, payload).await?;
+    Json(json!({ "providers": providers }))
+}
 
-    Ok((StatusCode::CREATED, Json(document)))
+pub async fn oidc_login(
+    State(state): State<AppState>,
+    Path(provider_name): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    start_oidc_login(&state, &provider_name, None).await
 }
 
-pub async fn get_user_documents(
+// Start a login whose callback adds the identity to the signed-in account
+pub async fn link_oidc_identity(
     AuthUser(claims): AuthUser,
     State(state): State<AppState>,
-) -> Result<Json<Vec<Document>>, (StatusCode, Json<Value>)> {
+    Path(provider_name): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
-            StatusCode::BAD_REQUEST,
+            StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": "Invalid user ID"})),
         )
     })?;
 
-    let documents = sqlx::query_as!(
-        Document,
+    // A linked identity would let support staff sign in as the user without an impersonation token
+    if claims.impersonated_by.is_some() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Identities cannot be linked while impersonating"})),
+        ));
+    }
+
+    start_oidc_login(&state, &provider_name, Some(user_id)).await
+}
+
+// Internal helper: store a pending authorization request and return the provider URL
+async fn start_oidc_login(
+    state: &AppState,
+    provider_name: &str,
+    link_user_id: Option<uuid::Uuid>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let provider = state.oidc_provider(provider_name).ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Unknown identity provider"})),
+    ))?;
+
+    let metadata = crate::oidc::discover(&state.oidc_cache, provider)
+        .await
+        .map_err(|e| {
+            eprintln!("OIDC discovery error: {}", e);
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({"error": "Identity provider is unavailable"})),
+            )
+        })?;
+
+    let auth_state = crate::oidc::random_token();
+    let nonce = crate::oidc::random_token();
+    let code_verifier = crate::oidc::random_token();
+
+    let authorization_url =
+        crate::oidc::authorization_url(provider, &metadata, &auth_state, &nonce, &code_verifier)
+            .map_err(|e| {
+                eprintln!("OIDC authorization URL error: {}", e);
+                (
+                    StatusCode::BAD_GATEWAY,
+                    Json(json!({"error": "Identity provider is misconfigured"})),
+                )
+            })?;
+
+    // Drop abandoned login attempts before storing the new one
+    sqlx::query!("DELETE FROM oidc_auth_requests WHERE created_at < NOW() - INTERVAL '10 minutes'")
+        .execute(&state.db)
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Database error"})),
+            )
+        })?;
+
+    sqlx::query!(
         r#"
-            SELECT id, user_id, title, authors, year, publication_type, journal,
-                volume, issue, pages, publisher, doi, url, abstract_text,
-                keywords, pdf_url, created_at, updated_at
-            FROM documents
-            WHERE user_id = $1
-            ORDER BY created_at DESC
-            "#,
-        user_id
+        INSERT INTO oidc_auth_requests (state, provider, nonce, code_verifier, link_user_id)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        auth_state,
+        provider.name,
+        nonce,
+        code_verifier,
+        link_user_id
     )
-    .fetch_all(&state.db)
+    .execute(&state.db)
     .await
     .map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to fetch documents"})),
+            Json(json!({"error": "Failed to start login"})),
         )
     })?;
 
-    Ok(Json(documents))
+    Ok(Json(json!({
+        "authorization_url": authorization_url,
+        "state": auth_state,
+    })))
 }
 
-pub async fn get_document(
-    AuthUser(claims): AuthUser,
+pub async fn oidc_callback(
     State(state): State<AppState>,
-    Path(document_id): Path<uuid::Uuid>,
-) -> Result<Json<Document>, (StatusCode, Json<Value>)> {
-    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": "Invalid user ID"})),
-        )
-    })?;
+    Path(provider_name): Path<String>,
+    Json(payload): Json<OidcCallbackRequest>,
+) -> Result<(StatusCode, Json<LoginResponse>), (StatusCode, Json<Value>)> {
+    let provider = state.oidc_provider(&provider_name).ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Unknown identity provider"})),
+    ))?;
 
-    let document = sqlx::query_as!(
-        Document,
+    // Consume the pending request so a state value can only be used once
+    let auth_request = sqlx::query!(
         r#"
-        SELECT id, user_id, title, authors, year, publication_type, journal,
-               volume, issue, pages, publisher, doi, url, abstract_text,
-               keywords, pdf_url, created_at, updated_at
-        FROM documents
-        WHERE id = $1 AND user_id = $2
+        DELETE FROM oidc_auth_requests
+        WHERE state = $1 AND provider = $2 AND created_at > NOW() - INTERVAL '10 minutes'
+        RETURNING nonce, code_verifier, link_user_id
         "#,
-        document_id,
-        user_id
+        payload.state,
+        provider.name
     )
     .fetch_optional(&state.db)
     .await
@@ -281,194 +539,267 @@ pub async fn get_document(
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": "Database error"})),
         )
-    })?;
+    })?
+    .ok_or((
+        StatusCode::BAD_REQUEST,
+        Json(json!({"error": "Invalid or expired login state"})),
+    ))?;
 
-    document
-        .ok_or((
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Document not found"})),
-        ))
-        .map(Json)
-}
+    let metadata = crate::oidc::discover(&state.oidc_cache, provider)
+        .await
+        .map_err(|e| {
+            eprintln!("OIDC discovery error: {}", e);
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({"error": "Identity provider is unavailable"})),
+            )
+        })?;
 
-pub async fn delete_document(
-    AuthUser(claims): AuthUser,
-    State(state): State<AppState>,
-    Path(document_id): Path<uuid::Uuid>,
-) -> Result<StatusCode, (StatusCode, Json<Value>)> {
-    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+    let id_token = crate::oidc::exchange_code(
+        provider,
+        &metadata,
+        &payload.code,
+        &auth_request.code_verifier,
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("OIDC token exchange error: {}", e);
         (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": "Invalid user ID"})),
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Failed to complete login with identity provider"})),
         )
     })?;
 
-    let result = sqlx::query!(
-        r#"
-        DELETE FROM documents
-        WHERE id = $1 AND user_id = $2
-        "#,
-        document_id,
-        user_id
+    let id_claims = crate::oidc::verify_id_token(
+        &state.oidc_cache,
+        provider,
+        &metadata,
+        &id_token,
+        &auth_request.nonce,
     )
-    .execute(&state.db)
     .await
-    .map_err(|_| {
+    .map_err(|e| {
+        eprintln!("OIDC ID token error: {}", e);
         (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to delete document"})),
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid ID token"})),
         )
     })?;
 
-    if result.rows_affected() == 0 {
+    // Only a verified email may create or link an account
+    let email = match id_claims.email {
+        Some(email) if id_claims.email_verified => email,
+        _ => {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(json!({"error": "Identity provider did not return a verified email"})),
+            ));
+        }
+    };
+
+    let username = id_claims.preferred_username.or(id_claims.name);
+    let user = find_or_create_oidc_user(
+        &state,
+        provider,
+        &id_claims.sub,
+        &email,
+        username,
+        auth_request.link_user_id,
+    )
+    .await?;
+
+    if user.disabled_at.is_some() {
         return Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Document not found"})),
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Account is disabled"})),
         ));
     }
 
-    Ok(StatusCode::NO_CONTENT)
-}
-
-pub async fn update_document(
-    AuthUser(claims): AuthUser,
-    State(state): State<AppState>,
-    Path(document_id): Path<uuid::Uuid>,
-    Json(payload): Json<UpdateDocument>,
-) -> Result<Json<Document>, (StatusCode, Json<Value>)> {
-    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+    let token = create_jwt(
+        &user.id.to_string(),
+        &user.email,
+        &user.role,
+        &state.jwt_secret,
+    )
+    .map_err(|_| {
         (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": "Invalid user ID"})),
-        )
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to generate token"})),
+        )
     })?;
 
-    let existing = sqlx::query!(
-        "SELECT id FROM documents WHERE id = $1 AND user_id = $2",
-        document_id,
-        user_id
-    )
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| {
+    Ok((
+        StatusCode::OK,
+        Json(LoginResponse {
+            token,
+            user: UserResponse {
+                id: user.id,
+                email: user.email,
+                username: user.username,
+                profile_image_url: user.profile_image_url,
+                role: user.role,
+            },
+        }),
+    ))
+}
+
+// Internal helper: resolve an external identity to a user. A new identity is linked to
+// `link_user_id` when the login was started from that account. Otherwise it links to the
+// account with the same email only for providers configured with TRUST_EMAIL, and else
+// creates an account. Linking by email is opt-in rather than the default because a provider
+// asserting someone's email would otherwise take over their password account.
+async fn find_or_create_oidc_user(
+    state: &AppState,
+    provider: &crate::config::OidcProviderConfig,
+    subject: &str,
+    email: &str,
+    username: Option<String>,
+    link_user_id: Option<uuid::Uuid>,
+) -> Result<User, (StatusCode, Json<Value>)> {
+    let db_error = |_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": "Database error"})),
         )
-    })?;
+    };
 
-    if existing.is_none() {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Document not found"})),
-        ));
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+
+    // Already linked identity
+    let linked = sqlx::query_as!(
+        User,
+        r#"
+        SELECT u.id, u.email, u.password_hash, u.username, u.profile_image_url,
+               u.role, u.disabled_at, u.created_at, u.updated_at
+        FROM users u
+        INNER JOIN user_identities ui ON ui.user_id = u.id
+        WHERE ui.provider = $1 AND ui.subject = $2
+        "#,
+        provider.name,
+        subject
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    if let Some(user) = linked {
+        if link_user_id.is_some_and(|id| id != user.id) {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(json!({"error": "This identity is already linked to another account"})),
+            ));
+        }
+        return Ok(user);
     }
 
-    let updated_document = sqlx::query_as!(
-        Document,
+    let user = match link_user_id {
+        Some(user_id) => sqlx::query_as!(
+            User,
+            r#"SELECT id, email, password_hash, username, profile_image_url, role, disabled_at, created_at, updated_at FROM users WHERE id = $1"#,
+            user_id
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(db_error)?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "User not found"})),
+        ))?,
+        None => {
+            let mut existing = sqlx::query_as!(
+                User,
+                r#"SELECT id, email, password_hash, username, profile_image_url, role, disabled_at, created_at, updated_at FROM users WHERE LOWER(email) = LOWER($1)"#,
+                email
+            )
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(db_error)?;
+
+            if existing.len() == 1 && provider.trust_email {
+                existing.remove(0)
+            } else if !existing.is_empty() {
+                return Err((
+                    StatusCode::CONFLICT,
+                    Json(json!({
+                        "error": "An account with this email already exists. Sign in to it and link this provider from your account settings."
+                    })),
+                ));
+            } else {
+                sqlx::query_as!(
+                    User,
+                    r#"
+                    INSERT INTO users (email, password_hash, username)
+                    VALUES ($1, NULL, $2)
+                    RETURNING id, email, password_hash, username, profile_image_url, role, disabled_at, created_at, updated_at
+                    "#,
+                    email,
+                    username
+                )
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(db_error)?
+            }
+        }
+    };
+
+    sqlx::query!(
         r#"
-        UPDATE documents
-        SET 
-            title = COALESCE($1, title),
-            authors = COALESCE($2, authors),
-            year = COALESCE($3, year),
-            publication_type = COALESCE($4, publication_type),
-            journal = COALESCE($5, journal),
-            volume = COALESCE($6, volume),
-            issue = COALESCE($7, issue),
-            pages = COALESCE($8, pages),
-            publisher = COALESCE($9, publisher),
-            doi = COALESCE($10, doi),
-            url = COALESCE($11, url),
-            abstract_text = COALESCE($12, abstract_text),
-            keywords = COALESCE($13, keywords),
-            pdf_url = COALESCE($14, pdf_url),
-            updated_at = NOW()
-        WHERE id = $15 AND user_id = $16
-        RETURNING id, user_id, title, authors, year, publication_type, journal,
-                  volume, issue, pages, publisher, doi, url, abstract_text,
-                  keywords, pdf_url, created_at, updated_at
+        INSERT INTO user_identities (user_id, provider, subject, email)
+        VALUES ($1, $2, $3, $4)
         "#,
-        payload.title,
-        payload.authors.as_deref(),
-        payload.year,
-        payload.publication_type,
-        payload.journal,
-        payload.volume,
-        payload.issue,
-        payload.pages,
-        payload.publisher,
-        payload.doi,
-        payload.url,
-        payload.abstract_text,
-        payload.keywords.as_deref(),
-        payload.pdf_url,
-        document_id,
-        user_id
+        user.id,
+        provider.name,
+        subject,
+        email
     )
-    .fetch_one(&state.db)
+    .execute(&mut *tx)
     .await
-    .map_err(|_| {
+    .map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)?;
+
+    Ok(user)
+}
+
+pub async fn get_current_user(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<UserResponse>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to update document"})),
+            Json(json!({"error": "Invalid user ID"})),
         )
     })?;
 
-    Ok(Json(updated_document))
-}
-
-// Internal helper function for document creation
-async fn create_document_internal(
-    state: &AppState,
-    user_id: uuid::Uuid,
-    payload: CreateDocument,
-) -> Result<Document, (StatusCode, Json<Value>)> {
-    let document = sqlx::query_as!(
-        Document,
-        r#"
-        INSERT INTO documents (
-            user_id, title, authors, year, publication_type, journal,
-            volume, issue, pages, publisher, doi, url, abstract_text, keywords, pdf_url
-        )
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
-        RETURNING id, user_id, title, authors, year, publication_type, journal,
-                  volume, issue, pages, publisher, doi, url, abstract_text,
-                  keywords, pdf_url, created_at, updated_at
-        "#,
-        user_id,
-        payload.title,
-        payload.authors.as_deref(),
-        payload.year,
-        payload.publication_type,
-        payload.journal,
-        payload.volume,
-        payload.issue,
-        payload.pages,
-        payload.publisher,
-        payload.doi,
-        payload.url,
-        payload.abstract_text,
-        payload.keywords.as_deref(),
-        payload.pdf_url
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, email, password_hash, username, profile_image_url, role, disabled_at, created_at, updated_at FROM users WHERE id = $1"#,
+        user_id
     )
     .fetch_one(&state.db)
     .await
     .map_err(|_| {
         (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to create document"})),
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "User not found"})),
         )
     })?;
 
-    Ok(document)
+    Ok(Json(UserResponse {
+        id: user.id,
+        email: user.email,
+        username: user.username,
+        profile_image_url: user.profile_image_url,
+        role: user.role,
+    }))
 }
 
-pub async fn upload_pdf(
+pub async fn request_account_deletion(
     AuthUser(claims): AuthUser,
     State(state): State<AppState>,
-    mut multipart: axum::extract::Multipart,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    Json(payload): Json<DeleteAccountRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -476,110 +807,3243 @@ pub async fn upload_pdf(
         )
     })?;
 
-    // Extract the file field from multipart
-    let mut file_name: Option<String> = None;
-    let mut file_path: Option<String> = None;
+    // Support staff acting as the user must not delete the account
+    if claims.impersonated_by.is_some() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Accounts cannot be deleted while impersonating"})),
+        ));
+    }
 
-    while let Some(field) = multipart.next_field().await.map_err(|e| {
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, email, password_hash, username, profile_image_url, role, disabled_at, created_at, updated_at FROM users WHERE id = $1"#,
+        user_id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
         (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": format!("Failed to read multipart field: {}", e)})),
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "User not found"})),
         )
-    })? {
-        let name = field.name().unwrap_or("").to_string();
-
-        if name == "file" {
-            let original_filename = field.file_name().unwrap_or("unknown.pdf").to_string();
-
-            // Validate file extension
-            if !original_filename.to_lowercase().ends_with(".pdf") {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": "Only PDF files are allowed"})),
-                ));
-            }
+    })?;
 
-            // Generate unique filename
-            let file_id = uuid::Uuid::new_v4();
-            let stored_filename = format!("{}_{}", file_id, original_filename);
-            let upload_path = format!("uploads/{}", stored_filename);
+    // Confirm with the password, or the email for single sign-on accounts
+    let confirmed = match user.password_hash.as_deref() {
+        Some(password_hash) => {
+            let password = payload.password.as_deref().unwrap_or("");
+            verify_password(password, password_hash)
+                .await
+                .map_err(|_| {
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({"error": "Failed to verify password"})),
+                    )
+                })?
+        }
+        None => payload.confirm_email.as_deref() == Some(user.email.as_str()),
+    };
 
-            // Read file bytes
-            let data = field.bytes().await.map_err(|e| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": format!("Failed to read file data: {}", e)})),
-                )
-            })?;
+    if !confirmed {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Confirmation does not match"})),
+        ));
+    }
 
-            // Write to disk
-            tokio::fs::write(&upload_path, &data).await.map_err(|e| {
+    if state.account_deletion_grace_days <= 0 {
+        crate::cleanup::purge_user(&state.db, user_id)
+            .await
+            .map_err(|_| {
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({"error": format!("Failed to save file: {}", e)})),
+                    Json(json!({"error": "Failed to delete account"})),
                 )
             })?;
 
-            file_name = Some(original_filename);
-            file_path = Some(upload_path);
-            break;
-        }
+        return Ok(Json(json!({ "deleted": true })));
     }
 
-    let file_path = file_path.ok_or_else(|| {
+    let scheduled = sqlx::query!(
+        r#"
+        UPDATE users
+        SET deletion_scheduled_at = NOW() + make_interval(days => $1), updated_at = NOW()
+        WHERE id = $2
+        RETURNING deletion_scheduled_at AS "deletion_scheduled_at!"
+        "#,
+        state.account_deletion_grace_days as i32,
+        user_id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
         (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": "No file provided"})),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to schedule account deletion"})),
         )
     })?;
 
-    let file_name = file_name.unwrap_or_else(|| "unknown.pdf".to_string());
+    Ok(Json(json!({
+        "deleted": false,
+        "deletion_scheduled_at": scheduled.deletion_scheduled_at,
+    })))
+}
 
-    // TODO: AI metadata extraction
+pub async fn cancel_account_deletion(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
 
-    let mut metadata = match crate::metadata::extract_metadata_from_pdf(&file_path).await {
-        Ok(metadata) => {
-            println!("Metadata extraction successful!");
-            metadata
-        }
-        Err(e) => {
-            eprintln!(
-                "Metadata extraction failed: {}. Using filename as fallback.",
-                e
-            );
-            CreateDocument {
-                title: file_name.clone(),
-                authors: None,
-                year: None,
-                publication_type: None,
-                journal: None,
-                volume: None,
-                issue: None,
-                pages: None,
-                publisher: None,
-                doi: None,
-                url: None,
-                abstract_text: None,
+    let result = sqlx::query!(
+        r#"
+        UPDATE users SET deletion_scheduled_at = NULL, updated_at = NOW()
+        WHERE id = $1 AND deletion_scheduled_at IS NOT NULL
+        "#,
+        user_id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to cancel account deletion"})),
+        )
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "No account deletion is scheduled"})),
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn export_user_data(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let db_error = |_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to collect export data"})),
+        )
+    };
+
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, email, password_hash, username, profile_image_url, role, disabled_at, created_at, updated_at FROM users WHERE id = $1"#,
+        user_id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    let documents = sqlx::query_as!(
+        Document,
+        r#"
+        SELECT id, user_id, group_id, title, authors, year, publication_type, journal,
+               volume, issue, pages, publisher, doi, url, abstract_text,
+               keywords, stored_file_path AS pdf_url, created_at, updated_at
+        FROM documents
+        WHERE user_id = $1
+        ORDER BY created_at ASC
+        "#,
+        user_id
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    let collections = sqlx::query_as!(
+        Collection,
+        r#"
+        SELECT id, user_id, group_id, name, parent_id, query, position, created_at, updated_at
+        FROM collections
+        WHERE user_id = $1
+        ORDER BY created_at ASC
+        "#,
+        user_id
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    let memberships = sqlx::query!(
+        r#"
+        SELECT dc.document_id, dc.collection_id, dc.added_at, dc.position
+        FROM document_collections dc
+        INNER JOIN collections c ON c.id = dc.collection_id
+        WHERE c.user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?
+    .into_iter()
+    .map(|m| {
+        json!({
+            "document_id": m.document_id,
+            "collection_id": m.collection_id,
+            "added_at": m.added_at,
+            "position": m.position,
+        })
+    })
+    .collect();
+
+    let data = crate::export::ExportData {
+        user,
+        documents,
+        collections,
+        memberships,
+    };
+
+    let archive = tokio::task::spawn_blocking(move || crate::export::write_archive(&data))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result)
+        .map_err(|e| {
+            eprintln!("Export error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to build export archive"})),
+            )
+        })?;
+
+    let file_name = format!(
+        "scholarvault-export-{}.zip",
+        chrono::Utc::now().format("%Y%m%d")
+    );
+    let body = Body::from_stream(ReaderStream::new(tokio::fs::File::from_std(archive)));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+pub async fn create_document(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Json(payload): Json<CreateDocument>,
+) -> Result<(StatusCode, Json<Document>), (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error":"Invalid user ID"})),
+        )
+    })?;
+
+    let document = create_document_internal(&state, Library::User(user_id), payload, None).await?;
+
+    Ok((StatusCode::CREATED, Json(document)))
+}
+
+pub async fn get_user_documents(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Query(filters): Query<DocumentFilters>,
+    Query(params): Query<DocumentListQuery>,
+) -> Result<Json<DocumentPage>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    list_documents(&state, Library::User(user_id), &filters, &params)
+        .await
+        .map(Json)
+}
+
+// Internal helper: one page of a library's documents
+async fn list_documents(
+    state: &AppState,
+    library: Library,
+    filters: &DocumentFilters,
+    params: &DocumentListQuery,
+) -> Result<DocumentPage, (StatusCode, Json<Value>)> {
+    let cursor = params
+        .cursor
+        .as_deref()
+        .map(|c| Cursor::decode(c, params.sort, params.order))
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+    let page_size = params.page_size();
+
+    let db_error = |_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to fetch documents"})),
+        )
+    };
+
+    let mut count_query =
+        QueryBuilder::new("SELECT COUNT(*) FROM documents d WHERE d.deleted_at IS NULL AND ");
+    library.push_condition(&mut count_query, "d");
+    push_filters(&mut count_query, filters);
+    let total: i64 = count_query
+        .build_query_scalar()
+        .fetch_one(&state.db)
+        .await
+        .map_err(db_error)?;
+
+    let mut list_query = QueryBuilder::new(format!(
+        "SELECT {}, {} FROM documents d WHERE d.deleted_at IS NULL AND ",
+        DOCUMENT_COLUMNS,
+        sort_key_column(params.sort)
+    ));
+    library.push_condition(&mut list_query, "d");
+    push_filters(&mut list_query, filters);
+    if let Some(cursor) = &cursor {
+        push_cursor(&mut list_query, params.sort, params.order, cursor);
+    }
+    push_order_by(&mut list_query, params.sort, params.order);
+    // One extra row tells whether another page follows
+    list_query.push(" LIMIT ").push_bind(page_size + 1);
+
+    let mut rows: Vec<SortedDocument> = list_query
+        .build_query_as()
+        .fetch_all(&state.db)
+        .await
+        .map_err(db_error)?;
+
+    let has_more = rows.len() as i64 > page_size;
+    rows.truncate(page_size as usize);
+
+    let next_cursor = rows.last().filter(|_| has_more).map(|row| {
+        Cursor {
+            sort: params.sort,
+            order: params.order,
+            key: row.sort_key.clone(),
+            id: row.document.id,
+        }
+        .encode()
+    });
+
+    Ok(DocumentPage {
+        documents: rows.into_iter().map(|row| row.document).collect(),
+        total,
+        next_cursor,
+    })
+}
+
+pub async fn get_document(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<uuid::Uuid>,
+) -> Result<Json<Document>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    require_document_access(&state, user_id, document_id, Access::Viewer).await?;
+
+    let document = sqlx::query_as!(
+        Document,
+        r#"
+        SELECT id, user_id, group_id, title, authors, year, publication_type, journal,
+               volume, issue, pages, publisher, doi, url, abstract_text,
+               keywords, stored_file_path AS pdf_url, created_at, updated_at
+        FROM documents
+        WHERE id = $1
+        "#,
+        document_id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Database error"})),
+        )
+    })?;
+
+    document
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Document not found"})),
+        ))
+        .map(Json)
+}
+
+pub async fn delete_document(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<uuid::Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    // Owners, and members of the document's group
+    require_document_access(&state, user_id, document_id, Access::Owner).await?;
+
+    // Into the trash; the file stays until the document is purged
+    let result = sqlx::query!(
+        r#"
+        UPDATE documents SET deleted_at = NOW()
+        WHERE id = $1 AND deleted_at IS NULL
+        "#,
+        document_id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to delete document"})),
+        )
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Document not found"})),
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn bulk_update_documents(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Json(payload): Json<BulkRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    bulk_update_library(&state, Library::User(user_id), payload)
+        .await
+        .map(Json)
+}
+
+pub async fn bulk_update_group_documents(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(group_id): Path<uuid::Uuid>,
+    Json(payload): Json<BulkRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    require_group_role(&state, user_id, group_id, GroupRole::Member).await?;
+
+    bulk_update_library(&state, Library::Group(group_id), payload)
+        .await
+        .map(Json)
+}
+
+// Internal helper: apply a bulk action to documents of one library
+async fn bulk_update_library(
+    state: &AppState,
+    library: Library,
+    payload: BulkRequest,
+) -> Result<Value, (StatusCode, Json<Value>)> {
+    let results: Vec<BulkItemResult> =
+        apply_bulk_action(&state.db, library, payload.document_ids, payload.action)
+            .await
+            .map_err(|e| match e {
+                BulkError::Invalid(message) => {
+                    (StatusCode::BAD_REQUEST, Json(json!({"error": message})))
+                }
+                BulkError::CollectionNotFound => (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({"error": "Collection not found"})),
+                ),
+                BulkError::Database(e) => {
+                    eprintln!("Bulk document operation error: {}", e);
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({"error": "Failed to update documents"})),
+                    )
+                }
+            })?;
+
+    Ok(json!({ "results": results }))
+}
+
+pub async fn update_document(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<uuid::Uuid>,
+    Json(payload): Json<UpdateDocument>,
+) -> Result<Json<Document>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    // Editors of a shared collection may edit the documents in it
+    require_document_access(&state, user_id, document_id, Access::Editor).await?;
+
+    let updated_document = sqlx::query_as!(
+        Document,
+        r#"
+        UPDATE documents
+        SET 
+            title = COALESCE($1, title),
+            authors = COALESCE($2, authors),
+            year = COALESCE($3, year),
+            publication_type = COALESCE($4, publication_type),
+            journal = COALESCE($5, journal),
+            volume = COALESCE($6, volume),
+            issue = COALESCE($7, issue),
+            pages = COALESCE($8, pages),
+            publisher = COALESCE($9, publisher),
+            doi = COALESCE($10, doi),
+            url = COALESCE($11, url),
+            abstract_text = COALESCE($12, abstract_text),
+            keywords = COALESCE($13, keywords),
+            updated_at = NOW()
+        WHERE id = $14
+        RETURNING id, user_id, group_id, title, authors, year, publication_type, journal,
+                  volume, issue, pages, publisher, doi, url, abstract_text,
+                  keywords, stored_file_path AS pdf_url, created_at, updated_at
+        "#,
+        payload.title,
+        payload.authors.as_deref(),
+        payload.year,
+        payload.publication_type,
+        payload.journal,
+        payload.volume,
+        payload.issue,
+        payload.pages,
+        payload.publisher,
+        payload.doi,
+        payload.url,
+        payload.abstract_text,
+        payload.keywords.as_deref(),
+        document_id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to update document"})),
+        )
+    })?;
+
+    Ok(Json(updated_document))
+}
+
+// Internal helper function for document creation, in a user's or a group's library
+// `stored_file_path` is the upload written by this server, never a client-supplied path
+async fn create_document_internal(
+    state: &AppState,
+    library: Library,
+    payload: CreateDocument,
+    stored_file_path: Option<&str>,
+) -> Result<Document, (StatusCode, Json<Value>)> {
+    let document = sqlx::query_as!(
+        Document,
+        r#"
+        INSERT INTO documents (
+            user_id, group_id, title, authors, year, publication_type, journal,
+            volume, issue, pages, publisher, doi, url, abstract_text, keywords,
+            stored_file_path
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+        RETURNING id, user_id, group_id, title, authors, year, publication_type, journal,
+                  volume, issue, pages, publisher, doi, url, abstract_text,
+                  keywords, stored_file_path AS pdf_url, created_at, updated_at
+        "#,
+        library.user_id(),
+        library.group_id(),
+        payload.title,
+        payload.authors.as_deref(),
+        payload.year,
+        payload.publication_type,
+        payload.journal,
+        payload.volume,
+        payload.issue,
+        payload.pages,
+        payload.publisher,
+        payload.doi,
+        payload.url,
+        payload.abstract_text,
+        payload.keywords.as_deref(),
+        stored_file_path
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to create document"})),
+        )
+    })?;
+
+    Ok(document)
+}
+
+pub async fn upload_pdf(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    multipart: axum::extract::Multipart,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    store_uploaded_pdf(&state, Library::User(user_id), user_id, multipart).await
+}
+
+// Internal helper: store an uploaded PDF as a new document of `library`, counting it
+// against the storage quota of `quota_user_id`
+async fn store_uploaded_pdf(
+    state: &AppState,
+    library: Library,
+    quota_user_id: uuid::Uuid,
+    mut multipart: axum::extract::Multipart,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    // Extract the file field from multipart
+    let mut file_name: Option<String> = None;
+    let mut file_path: Option<String> = None;
+    let mut file_size: Option<i64> = None;
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("Failed to read multipart field: {}", e)})),
+        )
+    })? {
+        let name = field.name().unwrap_or("").to_string();
+
+        if name == "file" {
+            let original_filename = field.file_name().unwrap_or("unknown.pdf").to_string();
+
+            // Validate file extension
+            if !original_filename.to_lowercase().ends_with(".pdf") {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"error": "Only PDF files are allowed"})),
+                ));
+            }
+
+            // Generate unique filename
+            let file_id = uuid::Uuid::new_v4();
+            let stored_filename = format!("{}_{}", file_id, original_filename);
+            let upload_path = format!("uploads/{}", stored_filename);
+
+            // Read file bytes
+            let data = field.bytes().await.map_err(|e| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"error": format!("Failed to read file data: {}", e)})),
+                )
+            })?;
+
+            check_storage_quota(state, quota_user_id, data.len() as i64).await?;
+
+            // Write to disk
+            tokio::fs::write(&upload_path, &data).await.map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": format!("Failed to save file: {}", e)})),
+                )
+            })?;
+
+            file_name = Some(original_filename);
+            file_path = Some(upload_path);
+            file_size = Some(data.len() as i64);
+            break;
+        }
+    }
+
+    let file_path = file_path.ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "No file provided"})),
+        )
+    })?;
+
+    let file_name = file_name.unwrap_or_else(|| "unknown.pdf".to_string());
+
+    // TODO: AI metadata extraction
+
+    // One extraction serves both the metadata and the page index
+    let extracted = crate::pdf_text::extract_pages(&file_path).await;
+    let metadata = match &extracted {
+        Ok(pages) => crate::metadata::extract_metadata_from_text(&pages.join("\n")).await,
+        Err(e) => Err(e.clone()),
+    };
+    let metadata = match metadata {
+        Ok(metadata) => {
+            println!("Metadata extraction successful!");
+            metadata
+        }
+        Err(e) => {
+            eprintln!(
+                "Metadata extraction failed: {}. Using filename as fallback.",
+                e
+            );
+            CreateDocument {
+                title: file_name.clone(),
+                authors: None,
+                year: None,
+                publication_type: None,
+                journal: None,
+                volume: None,
+                issue: None,
+                pages: None,
+                publisher: None,
+                doi: None,
+                url: None,
+                abstract_text: None,
                 keywords: None,
-                pdf_url: None,
             }
         }
     };
 
-    // Set the PDF path
-    metadata.pdf_url = Some(file_path);
+    let document = create_document_internal(state, library, metadata, Some(&file_path)).await?;
+
+    // Index the text for content search and chat; the upload stands even if this fails
+    if let Err(e) = crate::pdf_text::store_extracted(&state.db, document.id, &extracted).await {
+        eprintln!("PDF text indexing failed: {}", e);
+    }
+
+    // Record the stored size for storage usage and quotas
+    sqlx::query!(
+        "UPDATE documents SET file_size_bytes = $1 WHERE id = $2",
+        file_size,
+        document.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to record file size"})),
+        )
+    })?;
+
+    Ok((StatusCode::CREATED, Json(json!(document))))
+}
+
+// Internal helper: reject an upload that would push the user past their storage quota,
+// which covers their own files and those of the groups they own. Trashed files still count
+// until they are purged.
+async fn check_storage_quota(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    upload_bytes: i64,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    let usage = sqlx::query!(
+        r#"
+        SELECT COALESCE(u.storage_quota_bytes, $2) AS quota_bytes,
+               COALESCE((
+                   SELECT SUM(d.file_size_bytes) FROM documents d
+                   WHERE d.user_id = u.id OR d.group_id IN (
+                       SELECT gm.group_id FROM group_members gm
+                       WHERE gm.user_id = u.id AND gm.role = 'owner'
+                   )
+               ), 0)::BIGINT AS "used_bytes!"
+        FROM users u
+        WHERE u.id = $1
+        "#,
+        user_id,
+        state.default_storage_quota_bytes
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Database error"})),
+        )
+    })?;
+
+    if let Some(quota_bytes) = usage.quota_bytes
+        && usage.used_bytes + upload_bytes > quota_bytes
+    {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(json!({
+                "error": "Storage quota exceeded",
+                "used_bytes": usage.used_bytes,
+                "quota_bytes": quota_bytes,
+            })),
+        ));
+    }
+
+    Ok(())
+}
+
+pub async fn upload_profile_image(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<UserResponse>, (StatusCode, Json<Value>)> {
+    // Parse user ID from JWT claims
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    // Extract the file field from multipart
+    let mut file_data: Option<Vec<u8>> = None;
+    let mut file_extension: Option<String> = None;
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("Failed to read multipart field: {}", e)})),
+        )
+    })? {
+        let name = field.name().unwrap_or("").to_string();
+
+        if name == "file" {
+            let original_filename = field.file_name().unwrap_or("unknown").to_string();
+
+            // Validate file extension (jpg, jpeg, png, webp)
+            let ext = original_filename
+                .split('.')
+                .next_back()
+                .unwrap_or("")
+                .to_lowercase();
+
+            if !["jpg", "jpeg", "png", "webp"].contains(&ext.as_str()) {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"error": "Only JPG, PNG, and WebP images are allowed"})),
+                ));
+            }
+
+            // Read file bytes
+            let data = field.bytes().await.map_err(|e| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"error": format!("Failed to read file data: {}", e)})),
+                )
+            })?;
+
+            // Validate file size (5MB max)
+            if data.len() > 5 * 1024 * 1024 {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"error": "Image must be smaller than 5MB"})),
+                ));
+            }
+
+            file_data = Some(data.to_vec());
+            file_extension = Some(ext);
+            break;
+        }
+    }
+
+    let file_data = file_data.ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "No file provided"})),
+        )
+    })?;
+
+    let file_extension = file_extension.unwrap();
+
+    // Create uploads/profile_images directory if it doesn't exist
+    tokio::fs::create_dir_all("uploads/profile_images")
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed to create upload directory: {}", e)})),
+            )
+        })?;
+
+    // Delete old profile image if it exists
+    let old_user = sqlx::query_as!(
+        User,
+        r#"SELECT id, email, password_hash, username, profile_image_url, role, disabled_at, created_at, updated_at FROM users WHERE id = $1"#,
+        user_id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "User not found"})),
+        )
+    })?;
+
+    if let Some(old_image_url) = old_user.profile_image_url {
+        // Delete old file from disk (ignore errors if file doesn't exist)
+        let _ = tokio::fs::remove_file(&old_image_url).await;
+    }
+
+    // Generate unique filename
+    let file_id = uuid::Uuid::new_v4();
+    let stored_filename = format!("{}_{}.{}", user_id, file_id, file_extension);
+    let upload_path = format!("uploads/profile_images/{}", stored_filename);
+
+    // Write to disk
+    tokio::fs::write(&upload_path, &file_data)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed to save file: {}", e)})),
+            )
+        })?;
+
+    // Update user's profile_image_url in database
+    let updated_user = sqlx::query_as!(
+        User,
+        r#"UPDATE users SET profile_image_url = $1, updated_at = NOW() WHERE id = $2 RETURNING id, email, password_hash, username, profile_image_url, role, disabled_at, created_at, updated_at"#,
+        upload_path,
+        user_id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to update user profile"})),
+        )
+    })?;
+
+    Ok(Json(UserResponse {
+        id: updated_user.id,
+        email: updated_user.email,
+        username: updated_user.username,
+        profile_image_url: updated_user.profile_image_url,
+        role: updated_user.role,
+    }))
+}
+
+pub async fn delete_profile_image(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<UserResponse>, (StatusCode, Json<Value>)> {
+    // Parse user ID from JWT claims
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    // Get current user to find their profile image
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, email, password_hash, username, profile_image_url, role, disabled_at, created_at, updated_at FROM users WHERE id = $1"#,
+        user_id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "User not found"})),
+        )
+    })?;
+
+    // Delete file from disk if it exists
+    if let Some(image_url) = user.profile_image_url {
+        // Ignore error if file doesn't exist
+        let _ = tokio::fs::remove_file(&image_url).await;
+    }
+
+    // Update database to set profile_image_url to NULL
+    let updated_user = sqlx::query_as!(
+        User,
+        r#"UPDATE users SET profile_image_url = NULL, updated_at = NOW() WHERE id = $1 RETURNING id, email, password_hash, username, profile_image_url, role, disabled_at, created_at, updated_at"#,
+        user_id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to update user profile"})),
+        )
+    })?;
+
+    Ok(Json(UserResponse {
+        id: updated_user.id,
+        email: updated_user.email,
+        username: updated_user.username,
+        profile_image_url: updated_user.profile_image_url,
+        role: updated_user.role,
+    }))
+}
+
+pub async fn update_profile(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateProfile>,
+) -> Result<Json<UserResponse>, (StatusCode, Json<Value>)> {
+    // Parse user ID from JWT claims
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    // Update username in database
+    let updated_user = sqlx::query_as!(
+        User,
+        r#"UPDATE users SET username = $1, updated_at = NOW() WHERE id = $2 RETURNING id, email, password_hash, username, profile_image_url, role, disabled_at, created_at, updated_at"#,
+        payload.username,
+        user_id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to update profile"})),
+        )
+    })?;
+
+    Ok(Json(UserResponse {
+        id: updated_user.id,
+        email: updated_user.email,
+        username: updated_user.username,
+        profile_image_url: updated_user.profile_image_url,
+        role: updated_user.role,
+    }))
+}
+
+pub async fn change_password(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Json(payload): Json<ChangePassword>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    // Support staff acting as the user must not plant a password they could sign in with later
+    if claims.impersonated_by.is_some() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Passwords cannot be changed while impersonating"})),
+        ));
+    }
+
+    let user = sqlx::query!(
+        "SELECT email, password_hash FROM users WHERE id = $1",
+        user_id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Database error"})),
+        )
+    })?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "User not found"})),
+    ))?;
+
+    if let Some(password_hash) = user.password_hash.as_deref() {
+        let current_password = payload.current_password.as_deref().unwrap_or("");
+        let confirmed = verify_password(current_password, password_hash)
+            .await
+            .map_err(|_| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": "Failed to verify password"})),
+                )
+            })?;
+        if !confirmed {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"error": "Current password is incorrect"})),
+            ));
+        }
+    } else {
+        // Single sign-on accounts without a password may set their first one, but only right
+        // after signing in, so a token stolen later cannot become a permanent password
+        if !signed_in_within(&claims, FIRST_PASSWORD_MAX_SIGN_IN_AGE_SECS) {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"error": "Sign in again before setting a password"})),
+            ));
+        }
+    }
+
+    if let Err(problems) = state
+        .password_policy
+        .validate(&payload.new_password, &user.email)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": problems.join(". "), "problems": problems })),
+        ));
+    }
+
+    let password_hash = hash_password(&payload.new_password).await.map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to hash password" })),
+        )
+    })?;
+
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1, updated_at = NOW() WHERE id = $2",
+        password_hash,
+        user_id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to change password"})),
+        )
+    })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_user_collections(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Collection>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let collections = sqlx::query_as!(
+        Collection,
+        r#"
+        SELECT id, user_id, group_id, name, parent_id, query, position, created_at, updated_at
+        FROM collections
+        WHERE user_id = $1 AND deleted_at IS NULL
+        ORDER BY position ASC, name ASC
+        "#,
+        user_id
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to fetch collections"})),
+        )
+    })?;
+
+    Ok(Json(collections))
+}
+
+pub async fn get_collection_tree(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<CollectionNode>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    library_tree(&state, Library::User(user_id)).await.map(Json)
+}
+
+pub async fn get_group_collection_tree(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<Vec<CollectionNode>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    require_group_role(&state, user_id, group_id, GroupRole::Reader).await?;
+
+    library_tree(&state, Library::Group(group_id))
+        .await
+        .map(Json)
+}
+
+// Internal helper: a library's collections nested by parent, with document counts
+async fn library_tree(
+    state: &AppState,
+    library: Library,
+) -> Result<Vec<CollectionNode>, (StatusCode, Json<Value>)> {
+    collection_tree(&state.db, library).await.map_err(|e| {
+        eprintln!("Collection tree error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to fetch collections"})),
+        )
+    })
+}
+
+pub async fn get_collection_path(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(collection_id): Path<uuid::Uuid>,
+) -> Result<Json<Vec<PathEntry>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Collection path error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to fetch collection path"})),
+        )
+    };
+
+    let not_found = "Collection not found";
+    let library = collection_library(&state.db, collection_id)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": not_found}))))?;
+    require_library_role(&state, user_id, library, GroupRole::Reader, not_found).await?;
+
+    let path = collection_path(&state.db, library, collection_id)
+        .await
+        .map_err(db_error)?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Collection not found"})),
+        ))?;
+
+    Ok(Json(path))
+}
+
+pub async fn create_collection(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Json(payload): Json<CreateCollection>,
+) -> Result<(StatusCode, Json<Collection>), (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let collection = create_collection_in(&state, Library::User(user_id), payload).await?;
+
+    Ok((StatusCode::CREATED, Json(collection)))
+}
+
+// Internal helper: create a collection in a user's or a group's library
+async fn create_collection_in(
+    state: &AppState,
+    library: Library,
+    payload: CreateCollection,
+) -> Result<Collection, (StatusCode, Json<Value>)> {
+    // If parent_id is provided, verify it belongs to the same library
+    if let Some(parent_id) = payload.parent_id {
+        let parent_exists = sqlx::query!(
+            r#"
+            SELECT id FROM collections
+            WHERE id = $1 AND (user_id = $2 OR group_id = $3) AND deleted_at IS NULL
+            "#,
+            parent_id,
+            library.user_id(),
+            library.group_id()
+        )
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Database error"})),
+            )
+        })?;
+
+        if parent_exists.is_none() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Parent collection not found"})),
+            ));
+        }
+    }
+
+    let query = payload.query.as_deref().map(str::trim);
+    if let Some(query) = query {
+        validate_smart_query(query)?;
+    }
+
+    sqlx::query_as!(
+        Collection,
+        r#"
+        INSERT INTO collections (user_id, group_id, name, parent_id, query, position)
+        VALUES ($1, $2, $3, $4, $5, (
+            SELECT COALESCE(MAX(position), 0) + 1
+            FROM collections
+            WHERE (user_id = $1 OR group_id = $2) AND parent_id IS NOT DISTINCT FROM $4
+        ))
+        RETURNING id, user_id, group_id, name, parent_id, query, position, created_at, updated_at
+        "#,
+        library.user_id(),
+        library.group_id(),
+        payload.name,
+        payload.parent_id,
+        query
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to create collection"})),
+        )
+    })
+}
+
+pub async fn update_collection(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(collection_id): Path<uuid::Uuid>,
+    Json(payload): Json<UpdateCollection>,
+) -> Result<Json<Collection>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    // The owner, or a member of the collection's group
+    let existing = accessible_collection(&state, user_id, collection_id, Access::Owner).await?;
+    let library = existing.library();
+
+    // A regular collection's documents were added by hand, so it cannot become smart
+    let query = payload.query.as_deref().map(str::trim);
+    if let Some(query) = query {
+        if existing.query.is_none() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Only smart collections have a query"})),
+            ));
+        }
+        validate_smart_query(query)?;
+    }
+
+    let db_error = |_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Database error"})),
+        )
+    };
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+
+    if let Some(Some(new_parent_id)) = payload.parent_id {
+        // Can't set parent to itself
+        if new_parent_id == collection_id {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Collection cannot be its own parent"})),
+            ));
+        }
+
+        lock_collection_tree(&mut tx, library)
+            .await
+            .map_err(db_error)?;
+
+        // Verify parent exists and belongs to the same library
+        let parent_exists = sqlx::query!(
+            r#"
+            SELECT id FROM collections
+            WHERE id = $1 AND (user_id = $2 OR group_id = $3) AND deleted_at IS NULL
+            "#,
+            new_parent_id,
+            library.user_id(),
+            library.group_id()
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        if parent_exists.is_none() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Parent collection not found"})),
+            ));
+        }
+
+        // Moving under one of its own descendants would detach the subtree into a cycle
+        let creates_cycle = sqlx::query_scalar!(
+            r#"
+            WITH RECURSIVE ancestors AS (
+                SELECT id, parent_id FROM collections WHERE id = $1
+                UNION
+                SELECT c.id, c.parent_id
+                FROM collections c
+                INNER JOIN ancestors a ON c.id = a.parent_id
+            )
+            SELECT EXISTS (SELECT 1 FROM ancestors WHERE id = $2) AS "exists!"
+            "#,
+            new_parent_id,
+            collection_id
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        if creates_cycle {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Collection cannot be moved into one of its subcollections"})),
+            ));
+        }
+    }
+
+    let updated_collection = sqlx::query_as!(
+        Collection,
+        r#"
+        UPDATE collections
+        SET name = COALESCE($1, name),
+            parent_id = CASE WHEN $2 THEN $3 ELSE parent_id END,
+            -- A moved collection goes after its new siblings
+            position = CASE WHEN $2 AND parent_id IS DISTINCT FROM $3 THEN (
+                SELECT COALESCE(MAX(s.position), 0) + 1
+                FROM collections s
+                WHERE (s.user_id = $6 OR s.group_id = $7) AND s.parent_id IS NOT DISTINCT FROM $3
+            ) ELSE position END,
+            query = COALESCE($4, query),
+            updated_at = NOW()
+        WHERE id = $5
+        RETURNING id, user_id, group_id, name, parent_id, query, position, created_at, updated_at
+        "#,
+        payload.name,
+        payload.parent_id.is_some(),
+        payload.parent_id.flatten(),
+        query,
+        collection_id,
+        library.user_id(),
+        library.group_id()
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to update collection"})),
+        )
+    })?;
+
+    tx.commit().await.map_err(db_error)?;
+
+    Ok(Json(updated_collection))
+}
+
+pub async fn duplicate_collection(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(collection_id): Path<uuid::Uuid>,
+    Json(payload): Json<DuplicateCollection>,
+) -> Result<(StatusCode, Json<Collection>), (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let name = payload.name.as_deref().map(str::trim);
+    if name.is_some_and(str::is_empty) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Collection name cannot be empty"})),
+        ));
+    }
+
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Duplicate collection error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to duplicate collection"})),
+        )
+    };
+
+    let not_found = "Collection not found";
+    let library = collection_library(&state.db, collection_id)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": not_found}))))?;
+    require_library_role(&state, user_id, library, GroupRole::Member, not_found).await?;
+
+    let copy =
+        crate::collection_tree::duplicate_collection(&state.db, library, collection_id, name)
+            .await
+            .map_err(db_error)?
+            .ok_or((
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Collection not found"})),
+            ))?;
+
+    Ok((StatusCode::CREATED, Json(copy)))
+}
+
+pub async fn reorder_collections(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Json(payload): Json<ReorderRequest>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Reorder collections error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to reorder collections"})),
+        )
+    };
+
+    // Every collection must be in the library of the first
+    let not_found = "Collection not found";
+    let library = match payload.ids.first() {
+        Some(id) => collection_library(&state.db, *id).await.map_err(db_error)?,
+        None => None,
+    }
+    .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": not_found}))))?;
+    require_library_role(&state, user_id, library, GroupRole::Member, not_found).await?;
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+
+    // Collections are ordered among their siblings, so all of them must share a parent
+    let parents = sqlx::query!(
+        r#"
+        SELECT DISTINCT parent_id FROM collections
+        WHERE id = ANY($1) AND (user_id = $2 OR group_id = $3) AND deleted_at IS NULL
+        "#,
+        &payload.ids,
+        library.user_id(),
+        library.group_id()
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    let parent_id = match parents.as_slice() {
+        [] => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Collection not found"})),
+            ));
+        }
+        [parent] => parent.parent_id,
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(
+                    json!({"error": "Only collections with the same parent can be reordered together"}),
+                ),
+            ));
+        }
+    };
+
+    let siblings: Vec<(uuid::Uuid, f64)> = sqlx::query!(
+        r#"
+        SELECT id, position FROM collections
+        WHERE (user_id = $1 OR group_id = $3) AND parent_id IS NOT DISTINCT FROM $2
+            AND deleted_at IS NULL
+        FOR UPDATE
+        "#,
+        library.user_id(),
+        parent_id,
+        library.group_id()
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(db_error)?
+    .into_iter()
+    .map(|c| (c.id, c.position))
+    .collect();
+
+    let positions = reorder(&siblings, &payload.ids, payload.after)
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+    let (ids, positions): (Vec<uuid::Uuid>, Vec<f64>) = positions.into_iter().unzip();
+
+    sqlx::query!(
+        r#"
+        UPDATE collections c SET position = u.position
+        FROM unnest($1::UUID[], $2::FLOAT8[]) AS u(id, position)
+        WHERE c.id = u.id AND (c.user_id = $3 OR c.group_id = $4)
+        "#,
+        &ids,
+        &positions,
+        library.user_id(),
+        library.group_id()
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn delete_collection(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(collection_id): Path<uuid::Uuid>,
+    Query(params): Query<DeleteCollectionQuery>,
+) -> Result<Json<DeleteCollectionReport>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    // The owner, or a member of the collection's group
+    let collection = accessible_collection(&state, user_id, collection_id, Access::Owner).await?;
+
+    let report = crate::collection_delete::delete_collection(
+        &state.db,
+        collection.library(),
+        collection_id,
+        params.mode,
+        params.dry_run,
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Delete collection error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to delete collection"})),
+        )
+    })?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Collection not found"})),
+    ))?;
+
+    Ok(Json(report))
+}
+
+pub async fn add_document_to_collection(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path((collection_id, document_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let collection = accessible_collection(&state, user_id, collection_id, Access::Editor).await?;
+
+    if collection.query.is_some() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Smart collection documents come from its query"})),
+        ));
+    }
+
+    // The document must be visible to the user, and collections only hold their own library's documents
+    require_document_access(&state, user_id, document_id, Access::Viewer).await?;
+    let document_owner = sqlx::query!(
+        "SELECT user_id, group_id FROM documents WHERE id = $1",
+        document_id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Database error"})),
+        )
+    })?;
+
+    if Library::of(document_owner.user_id, document_owner.group_id) != collection.library() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Only documents from the collection's own library can be added"})),
+        ));
+    }
+
+    // Insert into junction table (ignore if already exists); new additions come first
+    sqlx::query!(
+        r#"
+        INSERT INTO document_collections (document_id, collection_id, position)
+        VALUES ($1, $2, (
+            SELECT COALESCE(MIN(position), 1) - 1
+            FROM document_collections
+            WHERE collection_id = $2
+        ))
+        ON CONFLICT DO NOTHING
+        "#,
+        document_id,
+        collection_id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to add document to collection"})),
+        )
+    })?;
+
+    Ok(StatusCode::CREATED)
+}
+
+pub async fn remove_document_from_collection(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path((collection_id, document_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let collection = accessible_collection(&state, user_id, collection_id, Access::Editor).await?;
+
+    if collection.query.is_some() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Smart collection documents come from its query"})),
+        ));
+    }
+
+    let result = sqlx::query!(
+        "DELETE FROM document_collections WHERE document_id = $1 AND collection_id = $2",
+        document_id,
+        collection_id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to remove document from collection"})),
+        )
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Document not in collection"})),
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_collection_documents(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(collection_id): Path<uuid::Uuid>,
+) -> Result<Json<Vec<Document>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let collection = accessible_collection(&state, user_id, collection_id, Access::Viewer).await?;
+    let documents = load_collection_documents(&state, &collection).await?;
+
+    Ok(Json(documents))
+}
+
+pub async fn export_collection(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(collection_id): Path<uuid::Uuid>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let collection = accessible_collection(&state, user_id, collection_id, Access::Viewer).await?;
+    let documents = load_collection_documents(&state, &collection).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-bibtex".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"{}.bib\"",
+                    safe_file_name(&collection.name)
+                ),
+            ),
+        ],
+        crate::bibtex::documents_to_bibtex(&documents),
+    )
+        .into_response())
+}
+
+// Internal helper: a name usable in a Content-Disposition header
+fn safe_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// Internal helper: fetch a collection of the user, or 404
+async fn find_collection(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    collection_id: uuid::Uuid,
+) -> Result<Collection, (StatusCode, Json<Value>)> {
+    sqlx::query_as!(
+        Collection,
+        r#"
+        SELECT id, user_id, group_id, name, parent_id, query, position, created_at, updated_at
+        FROM collections
+        WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+        "#,
+        collection_id,
+        user_id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Database error"})),
+        )
+    })?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Collection not found"})),
+    ))
+}
+
+// Internal helper: a collection the user owns or has been given at least `required` access to.
+// 404 when they can't see it at all, 403 when they can see it but not do this.
+async fn accessible_collection(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    collection_id: uuid::Uuid,
+    required: Access,
+) -> Result<Collection, (StatusCode, Json<Value>)> {
+    let db_error = |_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Database error"})),
+        )
+    };
+
+    match collection_access(&state.db, user_id, collection_id)
+        .await
+        .map_err(db_error)?
+    {
+        None => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Collection not found"})),
+            ));
+        }
+        Some(access) if access < required => {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(json!({"error": if access == Access::Viewer {
+                    "You have read-only access to this collection"
+                } else {
+                    "Only the collection's owner can do this"
+                }})),
+            ));
+        }
+        Some(_) => {}
+    }
+
+    sqlx::query_as!(
+        Collection,
+        r#"
+        SELECT id, user_id, group_id, name, parent_id, query, position, created_at, updated_at
+        FROM collections
+        WHERE id = $1
+        "#,
+        collection_id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)
+}
+
+// Internal helper: as accessible_collection, for a document
+async fn require_document_access(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    document_id: uuid::Uuid,
+    required: Access,
+) -> Result<Access, (StatusCode, Json<Value>)> {
+    let access = document_access(&state.db, user_id, document_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Document access error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Database error"})),
+            )
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Document not found"})),
+        ))?;
+
+    if access < required {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": if access == Access::Viewer {
+                "You have read-only access to this document"
+            } else {
+                "Only the document's owner can do this"
+            }})),
+        ));
+    }
+    Ok(access)
+}
+
+pub async fn reorder_collection_documents(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(collection_id): Path<uuid::Uuid>,
+    Json(payload): Json<ReorderRequest>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let collection = accessible_collection(&state, user_id, collection_id, Access::Editor).await?;
+    if collection.query.is_some() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(
+                json!({"error": "Smart collection documents follow their search and cannot be reordered"}),
+            ),
+        ));
+    }
+
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Reorder documents error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to reorder documents"})),
+        )
+    };
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+
+    let members: Vec<(uuid::Uuid, f64)> = sqlx::query!(
+        r#"
+        SELECT dc.document_id, dc.position FROM document_collections dc
+        INNER JOIN documents d ON d.id = dc.document_id
+        WHERE dc.collection_id = $1 AND d.deleted_at IS NULL
+        FOR UPDATE OF dc
+        "#,
+        collection_id
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(db_error)?
+    .into_iter()
+    .map(|m| (m.document_id, m.position))
+    .collect();
+
+    let positions = reorder(&members, &payload.ids, payload.after)
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+    let (ids, positions): (Vec<uuid::Uuid>, Vec<f64>) = positions.into_iter().unzip();
+
+    sqlx::query!(
+        r#"
+        UPDATE document_collections dc SET position = u.position
+        FROM unnest($1::UUID[], $2::FLOAT8[]) AS u(document_id, position)
+        WHERE dc.collection_id = $3 AND dc.document_id = u.document_id
+        "#,
+        &ids,
+        &positions,
+        collection_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Internal helper: a collection's documents, computed from the query for smart collections
+async fn load_collection_documents(
+    state: &AppState,
+    collection: &Collection,
+) -> Result<Vec<Document>, (StatusCode, Json<Value>)> {
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Collection documents error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to fetch documents"})),
+        )
+    };
+
+    if let Some(query) = &collection.query {
+        let expr = parse_search_query(query).map_err(|e| {
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(json!({"error": format!("Smart collection query is invalid: {}", e.message)})),
+            )
+        })?;
+
+        return smart_collection_query(collection.library(), &expr)
+            .build_query_as()
+            .fetch_all(&state.db)
+            .await
+            .map_err(db_error);
+    }
+
+    sqlx::query_as!(
+        Document,
+        r#"
+        SELECT d.id, d.user_id, d.group_id, d.title, d.authors, d.year, d.publication_type,
+               d.journal, d.volume, d.issue, d.pages, d.publisher, d.doi, d.url,
+               d.abstract_text, d.keywords, d.stored_file_path AS pdf_url, d.created_at, d.updated_at
+        FROM documents d
+        INNER JOIN document_collections dc ON d.id = dc.document_id
+        WHERE dc.collection_id = $1 AND (d.user_id = $2 OR d.group_id = $3)
+            AND d.deleted_at IS NULL
+        ORDER BY dc.position ASC, d.created_at DESC
+        "#,
+        collection.id,
+        collection.user_id,
+        collection.group_id
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)
+}
+
+// Internal helper: a smart collection's query must parse
+fn validate_smart_query(query: &str) -> Result<(), (StatusCode, Json<Value>)> {
+    parse_search_query(query).map(|_| ()).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": e.message, "position": e.position})),
+        )
+    })
+}
+
+pub async fn search_documents(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Query(params): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchResult>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    search_library(&state, Library::User(user_id), &params)
+        .await
+        .map(Json)
+}
+
+pub async fn search_group_documents(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(group_id): Path<uuid::Uuid>,
+    Query(params): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchResult>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    require_group_role(&state, user_id, group_id, GroupRole::Reader).await?;
+
+    search_library(&state, Library::Group(group_id), &params)
+        .await
+        .map(Json)
+}
+
+// Internal helper: search one library's documents
+async fn search_library(
+    state: &AppState,
+    library: Library,
+    params: &SearchQuery,
+) -> Result<Vec<SearchResult>, (StatusCode, Json<Value>)> {
+    let expr = parse_search_query(&params.q).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": e.message, "position": e.position})),
+        )
+    })?;
+    let limit = params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+
+    let search_error = |e: sqlx::Error| {
+        eprintln!("Search query error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to search documents"})),
+        )
+    };
+
+    let scope = match params.collection_id {
+        Some(collection_id) => Some(
+            resolve_scope(
+                &state.db,
+                library,
+                collection_id,
+                params.include_subcollections,
+            )
+            .await
+            .map_err(search_error)?
+            .ok_or((
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Collection not found"})),
+            ))?,
+        ),
+        None => None,
+    };
+
+    let mut results: Vec<SearchResult> =
+        search_documents_query(library, &expr, scope.as_ref(), limit)
+            .build_query_as()
+            .fetch_all(&state.db)
+            .await
+            .map_err(search_error)?;
+
+    highlight_snippets(&mut results);
+    attach_page_matches(&state.db, &expr, &mut results)
+        .await
+        .map_err(search_error)?;
+
+    Ok(results)
+}
+
+pub async fn semantic_search(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Query(params): Query<SemanticSearchQuery>,
+) -> Result<Json<Vec<SemanticResult>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    semantic_search_library(&state, Library::User(user_id), &params)
+        .await
+        .map(Json)
+}
+
+pub async fn semantic_search_group_documents(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(group_id): Path<uuid::Uuid>,
+    Query(params): Query<SemanticSearchQuery>,
+) -> Result<Json<Vec<SemanticResult>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    require_group_role(&state, user_id, group_id, GroupRole::Reader).await?;
+
+    semantic_search_library(&state, Library::Group(group_id), &params)
+        .await
+        .map(Json)
+}
+
+// Internal helper: one library's documents closest in meaning to the query
+async fn semantic_search_library(
+    state: &AppState,
+    library: Library,
+    params: &SemanticSearchQuery,
+) -> Result<Vec<SemanticResult>, (StatusCode, Json<Value>)> {
+    let Some(embedder) = state.embedder.as_ref() else {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({"error": "Semantic search is not configured"})),
+        ));
+    };
+
+    let query = params.q.trim();
+    if query.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Search query is empty"})),
+        ));
+    }
+
+    let vector = embed_query(embedder.as_ref(), query).await.map_err(|e| {
+        eprintln!("Query embedding error: {}", e);
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(json!({"error": "Failed to embed the search query"})),
+        )
+    })?;
+
+    let results = semantic_search_query(
+        &state.db,
+        library,
+        embedder.model(),
+        &vector,
+        params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Semantic search error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to search documents"})),
+        )
+    })?;
+
+    Ok(results)
+}
+
+pub async fn suggest(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Query(params): Query<SuggestQuery>,
+) -> Result<Json<Vec<Suggestion>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    suggest_in_library(&state, Library::User(user_id), &params)
+        .await
+        .map(Json)
+}
+
+pub async fn suggest_in_group(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(group_id): Path<uuid::Uuid>,
+    Query(params): Query<SuggestQuery>,
+) -> Result<Json<Vec<Suggestion>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    require_group_role(&state, user_id, group_id, GroupRole::Reader).await?;
+
+    suggest_in_library(&state, Library::Group(group_id), &params)
+        .await
+        .map(Json)
+}
+
+// Internal helper: completions from one library
+async fn suggest_in_library(
+    state: &AppState,
+    library: Library,
+    params: &SuggestQuery,
+) -> Result<Vec<Suggestion>, (StatusCode, Json<Value>)> {
+    let prefix = params.prefix.trim();
+    if prefix.chars().count() < MIN_SUGGEST_PREFIX_CHARS {
+        return Ok(Vec::new());
+    }
+
+    let suggestions = crate::suggest::suggest(
+        &state.db,
+        library,
+        prefix,
+        params.limit.unwrap_or(DEFAULT_SUGGEST_LIMIT),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Suggestion query error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to fetch suggestions"})),
+        )
+    })?;
+
+    Ok(suggestions)
+}
+
+pub async fn document_facets(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Query(params): Query<FacetQuery>,
+) -> Result<Json<DocumentFacets>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    library_facets(&state, Library::User(user_id), &params)
+        .await
+        .map(Json)
+}
+
+pub async fn group_document_facets(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(group_id): Path<uuid::Uuid>,
+    Query(params): Query<FacetQuery>,
+) -> Result<Json<DocumentFacets>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    require_group_role(&state, user_id, group_id, GroupRole::Reader).await?;
+
+    library_facets(&state, Library::Group(group_id), &params)
+        .await
+        .map(Json)
+}
+
+// Internal helper: facets of one library's documents, or of those matching the search
+async fn library_facets(
+    state: &AppState,
+    library: Library,
+    params: &FacetQuery,
+) -> Result<DocumentFacets, (StatusCode, Json<Value>)> {
+    let expr = params
+        .q
+        .as_deref()
+        .filter(|q| !q.trim().is_empty())
+        .map(parse_search_query)
+        .transpose()
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": e.message, "position": e.position})),
+            )
+        })?;
+
+    let facets = crate::facets::document_facets(
+        &state.db,
+        library,
+        expr.as_ref(),
+        params.limit.unwrap_or(DEFAULT_FACET_LIMIT),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Facet query error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to compute facets"})),
+        )
+    })?;
+
+    Ok(facets)
+}
+
+pub async fn get_similar_documents(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<uuid::Uuid>,
+    Query(params): Query<SimilarQuery>,
+) -> Result<Json<Vec<SimilarDocument>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Similar documents error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to find similar documents"})),
+        )
+    };
+
+    // Only members of the document's library see what else it holds
+    let not_found = "Document not found";
+    let library = document_library(&state.db, document_id)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": not_found}))))?;
+    require_library_role(&state, user_id, library, GroupRole::Reader, not_found).await?;
+
+    let similar = crate::similar::similar_documents(
+        &state.db,
+        library,
+        document_id,
+        state.embedder.as_ref().map(|e| e.model()),
+        params.limit.unwrap_or(DEFAULT_SIMILAR_LIMIT),
+    )
+    .await
+    .map_err(db_error)?;
+
+    Ok(Json(similar))
+}
+
+pub async fn chat_with_document(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<String>,
+    Json(payload): Json<ChatRequest>,
+) -> Result<Json<ChatResponse>, (StatusCode, Json<Value>)> {
+    // Parse Document ID
+    let doc_id = uuid::Uuid::parse_str(&document_id).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid document ID"})),
+        )
+    })?;
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    // Fetch doc from database and verify the user can see it
+    require_document_access(&state, user_id, doc_id, Access::Viewer).await?;
+    let document = sqlx::query_as!(
+        Document,
+        r#"
+        SELECT id, user_id, group_id, title, authors, year, publication_type, journal,
+            volume, issue, pages, publisher, doi, url, abstract_text,
+            keywords, stored_file_path AS pdf_url, created_at, updated_at
+        FROM documents
+        WHERE id = $1
+        "#,
+        doc_id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        eprintln!("Database error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to fetch document"})),
+        )
+    })?
+    .ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Document not found"})),
+        )
+    })?;
+
+    let pdf_url = document.pdf_url.ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Document has no PDF"})),
+        )
+    })?;
+
+    // Use the stored text; only documents the startup indexing has not reached yet are extracted
+    let stored_text = crate::pdf_text::load_text(&state.db, doc_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Database error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to load PDF text"})),
+            )
+        })?;
+
+    let pdf_text = match stored_text {
+        Some(text) => text,
+        None => crate::pdf_text::index_pdf(&state.db, doc_id, &pdf_url)
+            .await
+            .map_err(|e| {
+                eprintln!("PDF extraction error: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": "Failed to extract PDF text"})),
+                )
+            })?
+            .join("\n"),
+    };
+
+    // Call OpenAI chat
+    let ai_response =
+        crate::metadata::chat_with_paper(&document.title, &pdf_text, &payload.message)
+            .await
+            .map_err(|e| {
+                eprintln!("OpenAI chat error: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": "Failed to chat with document"})),
+                )
+            })?;
+
+    Ok(Json(ChatResponse {
+        response: ai_response,
+    }))
+}
+
+pub async fn list_collection_shares(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(collection_id): Path<uuid::Uuid>,
+) -> Result<Json<Vec<CollectionShare>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    // Only the owner manages who a collection is shared with
+    find_collection(&state, user_id, collection_id).await?;
+
+    let shares = sqlx::query_as!(
+        CollectionShare,
+        r#"
+        SELECT id, collection_id, email, user_id, role, include_subcollections,
+               invite_token, created_at, accepted_at
+        FROM collection_shares
+        WHERE collection_id = $1
+        ORDER BY created_at ASC
+        "#,
+        collection_id
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to fetch shares"})),
+        )
+    })?;
+
+    Ok(Json(shares))
+}
+
+pub async fn share_collection(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(collection_id): Path<uuid::Uuid>,
+    Json(payload): Json<CreateShare>,
+) -> Result<(StatusCode, Json<CollectionShare>), (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    find_collection(&state, user_id, collection_id).await?;
+
+    if payload.role != "viewer" && payload.role != "editor" {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Role must be 'viewer' or 'editor'"})),
+        ));
+    }
+
+    let email = payload.email.trim().to_lowercase();
+    if !email.contains('@') {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid email address"})),
+        ));
+    }
+    if email.eq_ignore_ascii_case(&claims.email) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "You cannot share a collection with yourself"})),
+        ));
+    }
+
+    // Sharing again with the same address updates the existing share and keeps its token.
+    // There is no mail delivery, so the owner sends the invitation token on themselves.
+    let share = sqlx::query_as!(
+        CollectionShare,
+        r#"
+        INSERT INTO collection_shares
+            (collection_id, email, role, include_subcollections, invited_by, invite_token)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (collection_id, email) DO UPDATE
+        SET role = $3, include_subcollections = $4
+        RETURNING id, collection_id, email, user_id, role, include_subcollections,
+                  invite_token, created_at, accepted_at
+        "#,
+        collection_id,
+        email,
+        payload.role,
+        payload.include_subcollections.unwrap_or(true),
+        user_id,
+        crate::oidc::random_token()
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to share collection"})),
+        )
+    })?;
+
+    Ok((StatusCode::CREATED, Json(share)))
+}
+
+// Revoke a share as the collection owner, or leave it as the user who accepted it
+pub async fn delete_share(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(share_id): Path<uuid::Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM collection_shares s
+        USING collections c
+        WHERE s.id = $1 AND c.id = s.collection_id AND (c.user_id = $2 OR s.user_id = $2)
+        "#,
+        share_id,
+        user_id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to delete share"})),
+        )
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Share not found"})),
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// What an invitation token grants, so the invitee can review it before accepting
+pub async fn get_share_invitation(
+    AuthUser(_claims): AuthUser,
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Json<ShareInvitation>, (StatusCode, Json<Value>)> {
+    let invitation = sqlx::query_as!(
+        ShareInvitation,
+        r#"
+        SELECT s.id, s.collection_id, c.name AS collection_name, s.role,
+               s.include_subcollections, u.email AS invited_by, s.created_at
+        FROM collection_shares s
+        INNER JOIN collections c ON c.id = s.collection_id
+        INNER JOIN users u ON u.id = s.invited_by
+        WHERE s.invite_token = $1 AND c.deleted_at IS NULL
+        "#,
+        token
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to fetch invitation"})),
+        )
+    })?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Invitation not found"})),
+    ))?;
+
+    Ok(Json(invitation))
+}
+
+pub async fn accept_share(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Json<CollectionShare>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    // Holding the token is what proves the invitation reached its recipient. The share is bound
+    // to the account that accepts it and the token can't be used again.
+    let share = sqlx::query_as!(
+        CollectionShare,
+        r#"
+        UPDATE collection_shares s
+        SET user_id = $2, accepted_at = NOW(), invite_token = NULL
+        FROM collections c
+        WHERE s.invite_token = $1 AND c.id = s.collection_id
+            AND c.deleted_at IS NULL AND c.user_id IS DISTINCT FROM $2
+        RETURNING s.id, s.collection_id, s.email, s.user_id, s.role, s.include_subcollections,
+                  s.invite_token, s.created_at, s.accepted_at
+        "#,
+        token,
+        user_id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to accept invitation"})),
+        )
+    })?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Invitation not found"})),
+    ))?;
+
+    Ok(Json(share))
+}
+
+// Every collection shared with the user, including subcollections of shared subtrees
+pub async fn get_shared_collections(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<SharedCollection>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT c.id, c.user_id, c.group_id, c.name, c.parent_id, c.query, c.position,
+               c.created_at, c.updated_at, s.can_edit AS "can_edit!",
+               u.email AS owner_email, u.username AS owner_username
+        FROM shared_collections($1) s
+        INNER JOIN collections c ON c.id = s.collection_id
+        INNER JOIN users u ON u.id = c.user_id
+        WHERE c.user_id <> $1
+        ORDER BY u.email, c.position, c.name
+        "#,
+        user_id
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to fetch shared collections"})),
+        )
+    })?;
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|row| SharedCollection {
+                collection: Collection {
+                    id: row.id,
+                    user_id: row.user_id,
+                    group_id: row.group_id,
+                    name: row.name,
+                    parent_id: row.parent_id,
+                    query: row.query,
+                    position: row.position,
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                },
+                can_edit: row.can_edit,
+                owner_email: row.owner_email,
+                owner_username: row.owner_username,
+            })
+            .collect(),
+    ))
+}
+
+pub async fn list_collection_links(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(collection_id): Path<uuid::Uuid>,
+) -> Result<Json<Vec<CollectionLink>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    accessible_collection(&state, user_id, collection_id, Access::Owner).await?;
+
+    let links = sqlx::query_as!(
+        CollectionLink,
+        r#"
+        SELECT id, collection_id, token, allow_downloads, expires_at, created_at
+        FROM collection_links
+        WHERE collection_id = $1
+        ORDER BY created_at ASC
+        "#,
+        collection_id
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to fetch links"})),
+        )
+    })?;
+
+    Ok(Json(links))
+}
+
+pub async fn create_collection_link(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(collection_id): Path<uuid::Uuid>,
+    Json(payload): Json<CreateCollectionLink>,
+) -> Result<(StatusCode, Json<CollectionLink>), (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    // Publishing is up to whoever controls the collection, not to viewers or editors
+    accessible_collection(&state, user_id, collection_id, Access::Owner).await?;
+
+    if payload
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= chrono::Utc::now())
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Expiry must be in the future"})),
+        ));
+    }
+
+    let link = sqlx::query_as!(
+        CollectionLink,
+        r#"
+        INSERT INTO collection_links (collection_id, token, allow_downloads, expires_at, created_by)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, collection_id, token, allow_downloads, expires_at, created_at
+        "#,
+        collection_id,
+        crate::oidc::random_token(),
+        payload.allow_downloads.unwrap_or(false),
+        payload.expires_at,
+        user_id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to create link"})),
+        )
+    })?;
+
+    Ok((StatusCode::CREATED, Json(link)))
+}
+
+// Revoke a link; visitors holding it get a 404 from then on
+pub async fn delete_collection_link(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path((collection_id, link_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    accessible_collection(&state, user_id, collection_id, Access::Owner).await?;
+
+    let result = sqlx::query!(
+        "DELETE FROM collection_links WHERE id = $1 AND collection_id = $2",
+        link_id,
+        collection_id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to delete link"})),
+        )
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Link not found"})),
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Internal helper: the collection behind a public link token and whether downloads are allowed.
+// Unknown, revoked and expired links all get the same 404.
+async fn find_public_link(
+    state: &AppState,
+    token: &str,
+) -> Result<(Collection, bool, Option<chrono::DateTime<chrono::Utc>>), (StatusCode, Json<Value>)> {
+    let link = sqlx::query!(
+        r#"
+        SELECT c.id, c.user_id, c.group_id, c.name, c.parent_id, c.query, c.position,
+               c.created_at, c.updated_at, l.allow_downloads, l.expires_at
+        FROM collection_links l
+        INNER JOIN collections c ON c.id = l.collection_id
+        WHERE l.token = $1 AND (l.expires_at IS NULL OR l.expires_at > NOW())
+            AND c.deleted_at IS NULL
+        "#,
+        token
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Database error"})),
+        )
+    })?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "This link is invalid or has expired"})),
+    ))?;
+
+    let collection = Collection {
+        id: link.id,
+        user_id: link.user_id,
+        group_id: link.group_id,
+        name: link.name,
+        parent_id: link.parent_id,
+        query: link.query,
+        position: link.position,
+        created_at: link.created_at,
+        updated_at: link.updated_at,
+    };
+    Ok((collection, link.allow_downloads, link.expires_at))
+}
+
+// Public: a collection's name and document metadata, for visitors holding a link
+pub async fn get_public_collection(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Json<PublicCollection>, (StatusCode, Json<Value>)> {
+    let (collection, allow_downloads, expires_at) = find_public_link(&state, &token).await?;
+    let documents = load_collection_documents(&state, &collection).await?;
+
+    Ok(Json(PublicCollection {
+        name: collection.name,
+        allow_downloads,
+        expires_at,
+        documents: documents
+            .into_iter()
+            .map(|d| PublicDocument::new(d, allow_downloads))
+            .collect(),
+    }))
+}
+
+// Public: the collection's bibliography as BibTeX or plain-text references
+pub async fn get_public_bibliography(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    Query(params): Query<BibliographyQuery>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let (collection, _, _) = find_public_link(&state, &token).await?;
+    let documents = load_collection_documents(&state, &collection).await?;
+    let file_name = safe_file_name(&collection.name);
+
+    let (content_type, disposition, body) = match params.format.as_deref().unwrap_or("bibtex") {
+        "bibtex" => (
+            "application/x-bibtex",
+            format!("attachment; filename=\"{}.bib\"", file_name),
+            crate::bibtex::documents_to_bibtex(&documents),
+        ),
+        "text" => (
+            "text/plain; charset=utf-8",
+            format!("inline; filename=\"{}.txt\"", file_name),
+            crate::citation::documents_to_references(&documents),
+        ),
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Format must be 'bibtex' or 'text'"})),
+            ));
+        }
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+// Public: a document's PDF, when the link allows downloads and the document is in the collection
+pub async fn download_public_pdf(
+    State(state): State<AppState>,
+    Path((token, document_id)): Path<(String, uuid::Uuid)>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let (collection, allow_downloads, _) = find_public_link(&state, &token).await?;
+    if !allow_downloads {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Downloads are not enabled for this link"})),
+        ));
+    }
+
+    let db_error = |e: sqlx::Error| {
+        eprintln!("Public PDF error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Database error"})),
+        )
+    };
+
+    // Membership of this one document, as load_collection_documents would list it
+    let contained = match &collection.query {
+        // An invalid saved query matches nothing
+        Some(query) => match parse_search_query(query) {
+            Ok(expr) => smart_collections_containing_query(
+                collection.library(),
+                &[(collection.id, expr)],
+                document_id,
+            )
+            .build_query_scalar::<uuid::Uuid>()
+            .fetch_optional(&state.db)
+            .await
+            .map_err(db_error)?
+            .is_some(),
+            Err(_) => false,
+        },
+        None => sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM document_collections dc
+                INNER JOIN documents d ON d.id = dc.document_id
+                WHERE dc.collection_id = $1 AND dc.document_id = $2
+                    AND (d.user_id = $3 OR d.group_id = $4) AND d.deleted_at IS NULL
+            ) AS "exists!"
+            "#,
+            collection.id,
+            document_id,
+            collection.user_id,
+            collection.group_id
+        )
+        .fetch_one(&state.db)
+        .await
+        .map_err(db_error)?,
+    };
+    let document = if contained {
+        sqlx::query!(
+            "SELECT title, stored_file_path FROM documents WHERE id = $1",
+            document_id
+        )
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+    } else {
+        None
+    }
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Document not found"})),
+    ))?;
+
+    let not_available = || {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "This document has no PDF"})),
+        )
+    };
+    let pdf_path = document
+        .stored_file_path
+        .filter(|path| crate::cleanup::is_managed_upload(path))
+        .ok_or_else(not_available)?;
+    let file = tokio::fs::File::open(&pdf_path)
+        .await
+        .map_err(|_| not_available())?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "inline; filename=\"{}.pdf\"",
+                    safe_file_name(&document.title)
+                ),
+            ),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+// Internal helper: the user may act in this library: it is their own, or they have at least
+// `required` in its group. 404 otherwise, as for the items themselves.
+async fn require_library_role(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    library: Library,
+    required: GroupRole,
+    not_found: &str,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    match library {
+        Library::User(owner_id) if owner_id == user_id => Ok(()),
+        Library::Group(group_id) => {
+            require_group_role(state, user_id, group_id, required).await?;
+            Ok(())
+        }
+        Library::User(_) => Err((StatusCode::NOT_FOUND, Json(json!({"error": not_found})))),
+    }
+}
+
+fn trash_db_error(e: sqlx::Error) -> (StatusCode, Json<Value>) {
+    eprintln!("Trash error: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"error": "Database error"})),
+    )
+}
+
+pub async fn get_trash(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<TrashContents>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
+
+    let trash = list_trash(
+        &state.db,
+        Library::User(user_id),
+        state.trash_retention_days,
+    )
+    .await
+    .map_err(trash_db_error)?;
+
+    Ok(Json(trash))
+}
+
+pub async fn empty_trash(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
 
-    let document = create_document_internal(&state, user_id, metadata).await?;
+    crate::trash::empty_trash(&state.db, Library::User(user_id))
+        .await
+        .map_err(trash_db_error)?;
 
-    Ok((StatusCode::CREATED, Json(json!(document))))
+    Ok(StatusCode::NO_CONTENT)
 }
 
-pub async fn upload_profile_image(
+pub async fn restore_trashed_document(
     AuthUser(claims): AuthUser,
     State(state): State<AppState>,
-    mut multipart: Multipart,
-) -> Result<Json<UserResponse>, (StatusCode, Json<Value>)> {
-    // Parse user ID from JWT claims
+    Path(document_id): Path<uuid::Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -587,140 +4051,177 @@ pub async fn upload_profile_image(
         )
     })?;
 
-    // Extract the file field from multipart
-    let mut file_data: Option<Vec<u8>> = None;
-    let mut file_extension: Option<String> = None;
-
-    while let Some(field) = multipart.next_field().await.map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": format!("Failed to read multipart field: {}", e)})),
-        )
-    })? {
-        let name = field.name().unwrap_or("").to_string();
-
-        if name == "file" {
-            let original_filename = field.file_name().unwrap_or("unknown").to_string();
+    let not_found = "Document not found in the trash";
+    let library = trashed_document_library(&state.db, document_id)
+        .await
+        .map_err(trash_db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": not_found}))))?;
+    require_library_role(&state, user_id, library, GroupRole::Member, not_found).await?;
 
-            // Validate file extension (jpg, jpeg, png, webp)
-            let ext = original_filename
-                .split('.')
-                .last()
-                .unwrap_or("")
-                .to_lowercase();
+    if !restore_document(&state.db, document_id)
+        .await
+        .map_err(trash_db_error)?
+    {
+        return Err((StatusCode::NOT_FOUND, Json(json!({"error": not_found}))));
+    }
 
-            if !["jpg", "jpeg", "png", "webp"].contains(&ext.as_str()) {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": "Only JPG, PNG, and WebP images are allowed"})),
-                ));
-            }
+    Ok(StatusCode::NO_CONTENT)
+}
 
-            // Read file bytes
-            let data = field.bytes().await.map_err(|e| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": format!("Failed to read file data: {}", e)})),
-                )
-            })?;
+pub async fn purge_trashed_document(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<uuid::Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
+        )
+    })?;
 
-            // Validate file size (5MB max)
-            if data.len() > 5 * 1024 * 1024 {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": "Image must be smaller than 5MB"})),
-                ));
-            }
+    let not_found = "Document not found in the trash";
+    let library = trashed_document_library(&state.db, document_id)
+        .await
+        .map_err(trash_db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": not_found}))))?;
+    require_library_role(&state, user_id, library, GroupRole::Member, not_found).await?;
 
-            file_data = Some(data.to_vec());
-            file_extension = Some(ext);
-            break;
-        }
+    if !purge_document(&state.db, document_id)
+        .await
+        .map_err(trash_db_error)?
+    {
+        return Err((StatusCode::NOT_FOUND, Json(json!({"error": not_found}))));
     }
 
-    let file_data = file_data.ok_or_else(|| {
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn restore_trashed_collection(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(collection_id): Path<uuid::Uuid>,
+) -> Result<Json<RestoreReport>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": "No file provided"})),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
         )
     })?;
 
-    let file_extension = file_extension.unwrap();
+    let not_found = "Collection not found in the trash";
+    let library = trashed_collection_library(&state.db, collection_id)
+        .await
+        .map_err(trash_db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": not_found}))))?;
+    require_library_role(&state, user_id, library, GroupRole::Member, not_found).await?;
 
-    // Create uploads/profile_images directory if it doesn't exist
-    tokio::fs::create_dir_all("uploads/profile_images")
+    let report = restore_collection(&state.db, library, collection_id)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": format!("Failed to create upload directory: {}", e)})),
-            )
-        })?;
+        .map_err(trash_db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": not_found}))))?;
 
-    // Delete old profile image if it exists
-    let old_user = sqlx::query_as!(
-        User,
-        r#"SELECT id, email, password_hash, username, profile_image_url, created_at, updated_at FROM users WHERE id = $1"#,
-        user_id
-    )
-    .fetch_one(&state.db)
-    .await
-    .map_err(|_| {
+    Ok(Json(report))
+}
+
+pub async fn purge_trashed_collection(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(collection_id): Path<uuid::Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "User not found"})),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
         )
     })?;
 
-    if let Some(old_image_url) = old_user.profile_image_url {
-        // Delete old file from disk (ignore errors if file doesn't exist)
-        let _ = tokio::fs::remove_file(&old_image_url).await;
+    let not_found = "Collection not found in the trash";
+    let library = trashed_collection_library(&state.db, collection_id)
+        .await
+        .map_err(trash_db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": not_found}))))?;
+    require_library_role(&state, user_id, library, GroupRole::Member, not_found).await?;
+
+    if !purge_collection(&state.db, collection_id)
+        .await
+        .map_err(trash_db_error)?
+    {
+        return Err((StatusCode::NOT_FOUND, Json(json!({"error": not_found}))));
     }
 
-    // Generate unique filename
-    let file_id = uuid::Uuid::new_v4();
-    let stored_filename = format!("{}_{}.{}", user_id, file_id, file_extension);
-    let upload_path = format!("uploads/profile_images/{}", stored_filename);
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    // Write to disk
-    tokio::fs::write(&upload_path, &file_data)
+// Internal helper: the user's role in a group, which must be at least `required`.
+// 404 for non-members, so group ids can't be probed.
+async fn require_group_role(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    group_id: uuid::Uuid,
+    required: GroupRole,
+) -> Result<GroupRole, (StatusCode, Json<Value>)> {
+    let role = group_role(&state.db, user_id, group_id)
         .await
-        .map_err(|e| {
+        .map_err(|_| {
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": format!("Failed to save file: {}", e)})),
+                Json(json!({"error": "Database error"})),
             )
-        })?;
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Group not found"})),
+        ))?;
 
-    // Update user's profile_image_url in database
-    let updated_user = sqlx::query_as!(
-        User,
-        r#"UPDATE users SET profile_image_url = $1, updated_at = NOW() WHERE id = $2 RETURNING id, email, password_hash, username, profile_image_url, created_at, updated_at"#,
-        upload_path,
-        user_id
+    if role < required {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(
+                json!({"error": format!("This requires the {} role in the group", required.as_str())}),
+            ),
+        ));
+    }
+    Ok(role)
+}
+
+// Internal helper: a group as seen by one of its members
+async fn find_group(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    group_id: uuid::Uuid,
+) -> Result<Group, (StatusCode, Json<Value>)> {
+    sqlx::query_as!(
+        Group,
+        r#"
+        SELECT g.id, g.name, gm.role,
+               (SELECT COUNT(*) FROM group_members m WHERE m.group_id = g.id) AS "member_count!",
+               g.created_at, g.updated_at
+        FROM groups g
+        INNER JOIN group_members gm ON gm.group_id = g.id AND gm.user_id = $1
+        WHERE g.id = $2
+        "#,
+        user_id,
+        group_id
     )
-    .fetch_one(&state.db)
+    .fetch_optional(&state.db)
     .await
     .map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to update user profile"})),
+            Json(json!({"error": "Database error"})),
         )
-    })?;
-
-    Ok(Json(UserResponse {
-        id: updated_user.id,
-        email: updated_user.email,
-        username: updated_user.username,
-        profile_image_url: updated_user.profile_image_url,
-    }))
+    })?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Group not found"})),
+    ))
 }
 
-pub async fn delete_profile_image(
+pub async fn get_user_groups(
     AuthUser(claims): AuthUser,
     State(state): State<AppState>,
-) -> Result<Json<UserResponse>, (StatusCode, Json<Value>)> {
-    // Parse user ID from JWT claims
+) -> Result<Json<Vec<Group>>, (StatusCode, Json<Value>)> {
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -728,56 +4229,99 @@ pub async fn delete_profile_image(
         )
     })?;
 
-    // Get current user to find their profile image
-    let user = sqlx::query_as!(
-        User,
-        r#"SELECT id, email, password_hash, username, profile_image_url, created_at, updated_at FROM users WHERE id = $1"#,
+    let groups = sqlx::query_as!(
+        Group,
+        r#"
+        SELECT g.id, g.name, gm.role,
+               (SELECT COUNT(*) FROM group_members m WHERE m.group_id = g.id) AS "member_count!",
+               g.created_at, g.updated_at
+        FROM groups g
+        INNER JOIN group_members gm ON gm.group_id = g.id AND gm.user_id = $1
+        ORDER BY g.name ASC
+        "#,
         user_id
     )
-    .fetch_one(&state.db)
+    .fetch_all(&state.db)
     .await
     .map_err(|_| {
         (
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "User not found"})),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to fetch groups"})),
         )
     })?;
 
-    // Delete file from disk if it exists
-    if let Some(image_url) = user.profile_image_url {
-        // Ignore error if file doesn't exist
-        let _ = tokio::fs::remove_file(&image_url).await;
-    }
+    Ok(Json(groups))
+}
 
-    // Update database to set profile_image_url to NULL
-    let updated_user = sqlx::query_as!(
-        User,
-        r#"UPDATE users SET profile_image_url = NULL, updated_at = NOW() WHERE id = $1 RETURNING id, email, password_hash, username, profile_image_url, created_at, updated_at"#,
-        user_id
-    )
-    .fetch_one(&state.db)
-    .await
-    .map_err(|_| {
+pub async fn create_group(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Json(payload): Json<CreateGroup>,
+) -> Result<(StatusCode, Json<Group>), (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to update user profile"})),
+            Json(json!({"error": "Invalid user ID"})),
         )
     })?;
 
-    Ok(Json(UserResponse {
-        id: updated_user.id,
-        email: updated_user.email,
-        username: updated_user.username,
-        profile_image_url: updated_user.profile_image_url,
-    }))
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Group name cannot be empty"})),
+        ));
+    }
+
+    let db_error = |_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to create group"})),
+        )
+    };
+
+    // The creator owns the group
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+
+    let group = sqlx::query!(
+        "INSERT INTO groups (name) VALUES ($1) RETURNING id, name, created_at, updated_at",
+        name
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    sqlx::query!(
+        "INSERT INTO group_members (group_id, user_id, role) VALUES ($1, $2, $3)",
+        group.id,
+        user_id,
+        GroupRole::Owner.as_str()
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(Group {
+            id: group.id,
+            name: group.name,
+            role: GroupRole::Owner.as_str().to_string(),
+            member_count: 1,
+            created_at: group.created_at,
+            updated_at: group.updated_at,
+        }),
+    ))
 }
 
-pub async fn update_profile(
+pub async fn update_group(
     AuthUser(claims): AuthUser,
     State(state): State<AppState>,
-    Json(payload): Json<UpdateProfile>,
-) -> Result<Json<UserResponse>, (StatusCode, Json<Value>)> {
-    // Parse user ID from JWT claims
+    Path(group_id): Path<uuid::Uuid>,
+    Json(payload): Json<UpdateGroup>,
+) -> Result<Json<Group>, (StatusCode, Json<Value>)> {
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -785,34 +4329,66 @@ pub async fn update_profile(
         )
     })?;
 
-    // Update username in database
-    let updated_user = sqlx::query_as!(
-        User,
-        r#"UPDATE users SET username = $1, updated_at = NOW() WHERE id = $2 RETURNING id, email, password_hash, username, profile_image_url, created_at, updated_at"#,
-        payload.username,
-        user_id
+    require_group_role(&state, user_id, group_id, GroupRole::Admin).await?;
+
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Group name cannot be empty"})),
+        ));
+    }
+
+    sqlx::query!(
+        "UPDATE groups SET name = $1, updated_at = NOW() WHERE id = $2",
+        name,
+        group_id
     )
-    .fetch_one(&state.db)
+    .execute(&state.db)
     .await
     .map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to update profile"})),
+            Json(json!({"error": "Failed to update group"})),
+        )
+    })?;
+
+    find_group(&state, user_id, group_id).await.map(Json)
+}
+
+// Deletes the group's library too: documents, collections and stored files
+pub async fn delete_group(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
         )
     })?;
 
-    Ok(Json(UserResponse {
-        id: updated_user.id,
-        email: updated_user.email,
-        username: updated_user.username,
-        profile_image_url: updated_user.profile_image_url,
-    }))
+    require_group_role(&state, user_id, group_id, GroupRole::Owner).await?;
+
+    crate::cleanup::purge_group(&state.db, group_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Delete group error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to delete group"})),
+            )
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
-pub async fn get_user_collections(
+pub async fn get_group_members(
     AuthUser(claims): AuthUser,
     State(state): State<AppState>,
-) -> Result<Json<Vec<Collection>>, (StatusCode, Json<Value>)> {
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<Vec<GroupMember>>, (StatusCode, Json<Value>)> {
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -820,33 +4396,54 @@ pub async fn get_user_collections(
         )
     })?;
 
-    let collections = sqlx::query_as!(
-        Collection,
+    require_group_role(&state, user_id, group_id, GroupRole::Reader).await?;
+
+    let members = sqlx::query_as!(
+        GroupMember,
         r#"
-        SELECT id, user_id, name, parent_id, created_at, updated_at
-        FROM collections
-        WHERE user_id = $1
-        ORDER BY name ASC
+        SELECT gm.user_id, u.email, u.username, gm.role, gm.created_at
+        FROM group_members gm
+        INNER JOIN users u ON u.id = gm.user_id
+        WHERE gm.group_id = $1
+        ORDER BY array_position(ARRAY['owner', 'admin', 'member', 'reader'], gm.role::TEXT), u.email
         "#,
-        user_id
+        group_id
     )
     .fetch_all(&state.db)
     .await
     .map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to fetch collections"})),
+            Json(json!({"error": "Failed to fetch members"})),
         )
     })?;
 
-    Ok(Json(collections))
+    Ok(Json(members))
 }
 
-pub async fn create_collection(
+// Internal helper: a role that `actor` may hand out. Ownership only changes hands through
+// update_group_member, and only the owner appoints admins.
+fn assignable_role(role: &str, actor: GroupRole) -> Result<GroupRole, (StatusCode, Json<Value>)> {
+    let role = GroupRole::parse(role).ok_or((
+        StatusCode::BAD_REQUEST,
+        Json(json!({"error": "Role must be one of owner, admin, member, reader"})),
+    ))?;
+
+    if role == GroupRole::Admin && actor < GroupRole::Owner {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Only the group's owner can appoint admins"})),
+        ));
+    }
+    Ok(role)
+}
+
+pub async fn add_group_member(
     AuthUser(claims): AuthUser,
     State(state): State<AppState>,
-    Json(payload): Json<CreateCollection>,
-) -> Result<(StatusCode, Json<Collection>), (StatusCode, Json<Value>)> {
+    Path(group_id): Path<uuid::Uuid>,
+    Json(payload): Json<AddGroupMember>,
+) -> Result<(StatusCode, Json<GroupMember>), (StatusCode, Json<Value>)> {
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -854,59 +4451,71 @@ pub async fn create_collection(
         )
     })?;
 
-    // If parent_id is provided, verify it belongs to this user
-    if let Some(parent_id) = payload.parent_id {
-        let parent_exists = sqlx::query!(
-            "SELECT id FROM collections WHERE id = $1 AND user_id = $2",
-            parent_id,
-            user_id
+    let actor = require_group_role(&state, user_id, group_id, GroupRole::Admin).await?;
+    let role = assignable_role(&payload.role, actor)?;
+    if role == GroupRole::Owner {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Add the user first, then transfer ownership to them"})),
+        ));
+    }
+
+    let db_error = |_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to add member"})),
         )
-        .fetch_optional(&state.db)
-        .await
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Database error"})),
-            )
-        })?;
+    };
 
-        if parent_exists.is_none() {
-            return Err((
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": "Parent collection not found"})),
-            ));
-        }
-    }
+    let user = sqlx::query!(
+        "SELECT id, email, username FROM users WHERE LOWER(email) = LOWER($1)",
+        payload.email.trim()
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "No user with that email"})),
+    ))?;
 
-    let collection = sqlx::query_as!(
-        Collection,
+    let created_at = sqlx::query_scalar!(
         r#"
-        INSERT INTO collections (user_id, name, parent_id)
+        INSERT INTO group_members (group_id, user_id, role)
         VALUES ($1, $2, $3)
-        RETURNING id, user_id, name, parent_id, created_at, updated_at
+        ON CONFLICT DO NOTHING
+        RETURNING created_at
         "#,
-        user_id,
-        payload.name,
-        payload.parent_id
+        group_id,
+        user.id,
+        role.as_str()
     )
-    .fetch_one(&state.db)
+    .fetch_optional(&state.db)
     .await
-    .map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to create collection"})),
-        )
-    })?;
+    .map_err(db_error)?
+    .ok_or((
+        StatusCode::CONFLICT,
+        Json(json!({"error": "User is already a member of this group"})),
+    ))?;
 
-    Ok((StatusCode::CREATED, Json(collection)))
+    Ok((
+        StatusCode::CREATED,
+        Json(GroupMember {
+            user_id: user.id,
+            email: user.email,
+            username: user.username,
+            role: role.as_str().to_string(),
+            created_at,
+        }),
+    ))
 }
 
-pub async fn update_collection(
+pub async fn update_group_member(
     AuthUser(claims): AuthUser,
     State(state): State<AppState>,
-    Path(collection_id): Path<uuid::Uuid>,
-    Json(payload): Json<UpdateCollection>,
-) -> Result<Json<Collection>, (StatusCode, Json<Value>)> {
+    Path((group_id, member_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+    Json(payload): Json<UpdateGroupMember>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -914,93 +4523,144 @@ pub async fn update_collection(
         )
     })?;
 
-    // Verify collection belongs to user
-    let existing = sqlx::query!(
-        "SELECT id FROM collections WHERE id = $1 AND user_id = $2",
-        collection_id,
-        user_id
-    )
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| {
+    let actor = require_group_role(&state, user_id, group_id, GroupRole::Admin).await?;
+    let role = assignable_role(&payload.role, actor)?;
+
+    let db_error = |_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Database error"})),
+            Json(json!({"error": "Failed to update member"})),
         )
-    })?;
+    };
 
-    if existing.is_none() {
-        return Err((
+    let current = group_role(&state.db, member_id, group_id)
+        .await
+        .map_err(db_error)?
+        .ok_or((
             StatusCode::NOT_FOUND,
-            Json(json!({"error": "Collection not found"})),
+            Json(json!({"error": "Member not found"})),
+        ))?;
+
+    if current == GroupRole::Owner {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "The owner's role changes only by transferring ownership"})),
+        ));
+    }
+    if current == GroupRole::Admin && actor < GroupRole::Owner {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Only the group's owner can change an admin's role"})),
         ));
     }
 
-    // If new parent_id provided, verify it's not creating a cycle
-    if let Some(new_parent_id) = payload.parent_id {
-        // Can't set parent to itself
-        if new_parent_id == collection_id {
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+
+    if role == GroupRole::Owner {
+        if actor < GroupRole::Owner {
             return Err((
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": "Collection cannot be its own parent"})),
+                StatusCode::FORBIDDEN,
+                Json(json!({"error": "Only the group's owner can transfer ownership"})),
             ));
         }
+        // There is one owner at a time; the previous one stays on as an admin
+        sqlx::query!(
+            "UPDATE group_members SET role = $3 WHERE group_id = $1 AND user_id = $2",
+            group_id,
+            user_id,
+            GroupRole::Admin.as_str()
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    }
 
-        // Verify parent exists and belongs to user
-        let parent_exists = sqlx::query!(
-            "SELECT id FROM collections WHERE id = $1 AND user_id = $2",
-            new_parent_id,
-            user_id
+    sqlx::query!(
+        "UPDATE group_members SET role = $3 WHERE group_id = $1 AND user_id = $2",
+        group_id,
+        member_id,
+        role.as_str()
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Remove a member as an admin, or leave the group
+pub async fn remove_group_member(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path((group_id, member_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Invalid user ID"})),
         )
-        .fetch_optional(&state.db)
+    })?;
+
+    let actor = require_group_role(&state, user_id, group_id, GroupRole::Reader).await?;
+
+    let db_error = |_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to remove member"})),
+        )
+    };
+
+    let current = group_role(&state.db, member_id, group_id)
         .await
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Database error"})),
-            )
-        })?;
+        .map_err(db_error)?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Member not found"})),
+        ))?;
 
-        if parent_exists.is_none() {
+    if current == GroupRole::Owner {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "The owner must transfer ownership or delete the group instead"})),
+        ));
+    }
+    if member_id != user_id {
+        let required = if current == GroupRole::Admin {
+            GroupRole::Owner
+        } else {
+            GroupRole::Admin
+        };
+        if actor < required {
             return Err((
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": "Parent collection not found"})),
+                StatusCode::FORBIDDEN,
+                Json(
+                    json!({"error": format!("This requires the {} role in the group", required.as_str())}),
+                ),
             ));
         }
     }
 
-    let updated_collection = sqlx::query_as!(
-        Collection,
-        r#"
-        UPDATE collections
-        SET name = COALESCE($1, name),
-            parent_id = COALESCE($2, parent_id),
-            updated_at = NOW()
-        WHERE id = $3 AND user_id = $4
-        RETURNING id, user_id, name, parent_id, created_at, updated_at
-        "#,
-        payload.name,
-        payload.parent_id,
-        collection_id,
-        user_id
+    sqlx::query!(
+        "DELETE FROM group_members WHERE group_id = $1 AND user_id = $2",
+        group_id,
+        member_id
     )
-    .fetch_one(&state.db)
+    .execute(&state.db)
     .await
-    .map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to update collection"})),
-        )
-    })?;
+    .map_err(db_error)?;
 
-    Ok(Json(updated_collection))
+    Ok(StatusCode::NO_CONTENT)
 }
 
-pub async fn delete_collection(
+pub async fn get_group_documents(
     AuthUser(claims): AuthUser,
     State(state): State<AppState>,
-    Path(collection_id): Path<uuid::Uuid>,
-) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    Path(group_id): Path<uuid::Uuid>,
+    Query(filters): Query<DocumentFilters>,
+    Query(params): Query<DocumentListQuery>,
+) -> Result<Json<DocumentPage>, (StatusCode, Json<Value>)> {
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -1008,35 +4668,40 @@ pub async fn delete_collection(
         )
     })?;
 
-    let result = sqlx::query!(
-        "DELETE FROM collections WHERE id = $1 AND user_id = $2",
-        collection_id,
-        user_id
-    )
-    .execute(&state.db)
-    .await
-    .map_err(|_| {
+    require_group_role(&state, user_id, group_id, GroupRole::Reader).await?;
+
+    list_documents(&state, Library::Group(group_id), &filters, &params)
+        .await
+        .map(Json)
+}
+
+pub async fn create_group_document(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(group_id): Path<uuid::Uuid>,
+    Json(payload): Json<CreateDocument>,
+) -> Result<(StatusCode, Json<Document>), (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to delete collection"})),
+            Json(json!({"error": "Invalid user ID"})),
         )
     })?;
 
-    if result.rows_affected() == 0 {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Collection not found"})),
-        ));
-    }
+    require_group_role(&state, user_id, group_id, GroupRole::Member).await?;
 
-    Ok(StatusCode::NO_CONTENT)
+    let document =
+        create_document_internal(&state, Library::Group(group_id), payload, None).await?;
+
+    Ok((StatusCode::CREATED, Json(document)))
 }
 
-pub async fn add_document_to_collection(
+pub async fn upload_group_pdf(
     AuthUser(claims): AuthUser,
     State(state): State<AppState>,
-    Path((collection_id, document_id)): Path<(uuid::Uuid, uuid::Uuid)>,
-) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    Path(group_id): Path<uuid::Uuid>,
+    multipart: axum::extract::Multipart,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -1044,13 +4709,14 @@ pub async fn add_document_to_collection(
         )
     })?;
 
-    // Verify both collection and document belong to user
-    let collection_check = sqlx::query!(
-        "SELECT id FROM collections WHERE id = $1 AND user_id = $2",
-        collection_id,
-        user_id
+    require_group_role(&state, user_id, group_id, GroupRole::Member).await?;
+
+    // The group's files count against its owner's quota
+    let owner_id = sqlx::query_scalar!(
+        "SELECT user_id FROM group_members WHERE group_id = $1 AND role = 'owner'",
+        group_id
     )
-    .fetch_optional(&state.db)
+    .fetch_one(&state.db)
     .await
     .map_err(|_| {
         (
@@ -1059,61 +4725,51 @@ pub async fn add_document_to_collection(
         )
     })?;
 
-    if collection_check.is_none() {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Collection not found"})),
-        ));
-    }
+    store_uploaded_pdf(&state, Library::Group(group_id), owner_id, multipart).await
+}
 
-    let document_check = sqlx::query!(
-        "SELECT id FROM documents WHERE id = $1 AND user_id = $2",
-        document_id,
-        user_id
-    )
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| {
+pub async fn get_group_collections(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<Vec<Collection>>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Database error"})),
+            Json(json!({"error": "Invalid user ID"})),
         )
     })?;
 
-    if document_check.is_none() {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Document not found"})),
-        ));
-    }
+    require_group_role(&state, user_id, group_id, GroupRole::Reader).await?;
 
-    // Insert into junction table (ignore if already exists)
-    sqlx::query!(
+    let collections = sqlx::query_as!(
+        Collection,
         r#"
-        INSERT INTO document_collections (document_id, collection_id)
-        VALUES ($1, $2)
-        ON CONFLICT DO NOTHING
+        SELECT id, user_id, group_id, name, parent_id, query, position, created_at, updated_at
+        FROM collections
+        WHERE group_id = $1 AND deleted_at IS NULL
+        ORDER BY position ASC, name ASC
         "#,
-        document_id,
-        collection_id
+        group_id
     )
-    .execute(&state.db)
+    .fetch_all(&state.db)
     .await
     .map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to add document to collection"})),
+            Json(json!({"error": "Failed to fetch collections"})),
         )
     })?;
 
-    Ok(StatusCode::CREATED)
+    Ok(Json(collections))
 }
 
-pub async fn remove_document_from_collection(
+pub async fn create_group_collection(
     AuthUser(claims): AuthUser,
     State(state): State<AppState>,
-    Path((collection_id, document_id)): Path<(uuid::Uuid, uuid::Uuid)>,
-) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    Path(group_id): Path<uuid::Uuid>,
+    Json(payload): Json<CreateCollection>,
+) -> Result<(StatusCode, Json<Collection>), (StatusCode, Json<Value>)> {
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -1121,57 +4777,44 @@ pub async fn remove_document_from_collection(
         )
     })?;
 
-    // Verify collection belongs to user
-    let collection_check = sqlx::query!(
-        "SELECT id FROM collections WHERE id = $1 AND user_id = $2",
-        collection_id,
-        user_id
-    )
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| {
+    require_group_role(&state, user_id, group_id, GroupRole::Member).await?;
+
+    let collection = create_collection_in(&state, Library::Group(group_id), payload).await?;
+
+    Ok((StatusCode::CREATED, Json(collection)))
+}
+
+pub async fn get_group_trash(
+    AuthUser(claims): AuthUser,
+    State(state): State<AppState>,
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<Json<TrashContents>, (StatusCode, Json<Value>)> {
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Database error"})),
+            Json(json!({"error": "Invalid user ID"})),
         )
     })?;
 
-    if collection_check.is_none() {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Collection not found"})),
-        ));
-    }
+    // Readers can't delete, so they have no use for the trash
+    require_group_role(&state, user_id, group_id, GroupRole::Member).await?;
 
-    let result = sqlx::query!(
-        "DELETE FROM document_collections WHERE document_id = $1 AND collection_id = $2",
-        document_id,
-        collection_id
+    let trash = list_trash(
+        &state.db,
+        Library::Group(group_id),
+        state.trash_retention_days,
     )
-    .execute(&state.db)
     .await
-    .map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to remove document from collection"})),
-        )
-    })?;
-
-    if result.rows_affected() == 0 {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Document not in collection"})),
-        ));
-    }
+    .map_err(trash_db_error)?;
 
-    Ok(StatusCode::NO_CONTENT)
+    Ok(Json(trash))
 }
 
-pub async fn get_collection_documents(
+pub async fn empty_group_trash(
     AuthUser(claims): AuthUser,
     State(state): State<AppState>,
-    Path(collection_id): Path<uuid::Uuid>,
-) -> Result<Json<Vec<Document>>, (StatusCode, Json<Value>)> {
+    Path(group_id): Path<uuid::Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -1179,186 +4822,428 @@ pub async fn get_collection_documents(
         )
     })?;
 
-    // Verify collection belongs to user
-    let collection_check = sqlx::query!(
-        "SELECT id FROM collections WHERE id = $1 AND user_id = $2",
-        collection_id,
-        user_id
+    // Members purge items one at a time; clearing everyone's deletions at once is for admins
+    require_group_role(&state, user_id, group_id, GroupRole::Admin).await?;
+
+    crate::trash::empty_trash(&state.db, Library::Group(group_id))
+        .await
+        .map_err(trash_db_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn admin_list_users(
+    AdminUser(_claims): AdminUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AdminUserSummary>>, (StatusCode, Json<Value>)> {
+    let users = sqlx::query_as!(
+        AdminUserSummary,
+        r#"
+        SELECT u.id, u.email, u.username, u.role, u.disabled_at, u.storage_quota_bytes,
+               COUNT(d.id) AS "document_count!",
+               COALESCE(SUM(d.file_size_bytes), 0)::BIGINT AS "storage_used_bytes!",
+               u.created_at
+        FROM users u
+        -- Owners are charged for their groups' documents, as in quota checks
+        LEFT JOIN documents d ON d.user_id = u.id OR d.group_id IN (
+            SELECT gm.group_id FROM group_members gm
+            WHERE gm.user_id = u.id AND gm.role = 'owner'
+        )
+        GROUP BY u.id
+        ORDER BY u.created_at ASC
+        "#
     )
-    .fetch_optional(&state.db)
+    .fetch_all(&state.db)
     .await
     .map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Database error"})),
+            Json(json!({"error": "Failed to fetch users"})),
         )
     })?;
 
-    if collection_check.is_none() {
+    Ok(Json(users))
+}
+
+pub async fn admin_set_user_status(
+    AdminUser(claims): AdminUser,
+    State(state): State<AppState>,
+    Path(target_id): Path<uuid::Uuid>,
+    Json(payload): Json<UpdateUserStatus>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let admin_id = parse_admin_id(&claims)?;
+
+    if target_id == admin_id && payload.disabled {
         return Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Collection not found"})),
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "You cannot disable your own account"})),
         ));
     }
 
-    let documents = sqlx::query_as!(
-        Document,
+    let mut tx = state.db.begin().await.map_err(admin_db_error)?;
+
+    let updated = sqlx::query!(
         r#"
-        SELECT d.id, d.user_id, d.title, d.authors, d.year, d.publication_type, 
-               d.journal, d.volume, d.issue, d.pages, d.publisher, d.doi, d.url,
-               d.abstract_text, d.keywords, d.pdf_url, d.created_at, d.updated_at
-        FROM documents d
-        INNER JOIN document_collections dc ON d.id = dc.document_id
-        WHERE dc.collection_id = $1 AND d.user_id = $2
-        ORDER BY d.created_at DESC
+        UPDATE users
+        SET disabled_at = CASE WHEN $1 THEN COALESCE(disabled_at, NOW()) ELSE NULL END,
+            updated_at = NOW()
+        WHERE id = $2
+        RETURNING id, disabled_at
         "#,
-        collection_id,
-        user_id
+        payload.disabled,
+        target_id
     )
-    .fetch_all(&state.db)
+    .fetch_optional(&mut *tx)
     .await
-    .map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to fetch documents"})),
-        )
-    })?;
+    .map_err(admin_db_error)?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "User not found"})),
+    ))?;
 
-    Ok(Json(documents))
+    let action = if payload.disabled {
+        "disable_user"
+    } else {
+        "enable_user"
+    };
+    record_admin_action(&mut tx, admin_id, action, target_id, json!({})).await?;
+
+    tx.commit().await.map_err(admin_db_error)?;
+
+    Ok(Json(json!({
+        "id": updated.id,
+        "disabled_at": updated.disabled_at,
+    })))
 }
 
-// Add this handler function after get_user_documents
-pub async fn search_documents(
-    AuthUser(claims): AuthUser,
+pub async fn admin_set_user_role(
+    AdminUser(claims): AdminUser,
     State(state): State<AppState>,
-    Query(params): Query<SearchQuery>,
-) -> Result<Json<Vec<Document>>, (StatusCode, Json<Value>)> {
-    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
-        (
+    Path(target_id): Path<uuid::Uuid>,
+    Json(payload): Json<UpdateUserRole>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let admin_id = parse_admin_id(&claims)?;
+
+    if !["user", "admin"].contains(&payload.role.as_str()) {
+        return Err((
             StatusCode::BAD_REQUEST,
-            Json(json!({"error": "Invalid user ID"})),
-        )
-    })?;
+            Json(json!({"error": "Role must be 'user' or 'admin'"})),
+        ));
+    }
+
+    if target_id == admin_id && payload.role != "admin" {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "You cannot remove your own admin role"})),
+        ));
+    }
 
-    let search_pattern = params.q.clone(); // No need for % wildcards with fuzzy search
+    let mut tx = state.db.begin().await.map_err(admin_db_error)?;
 
-    let documents = sqlx::query_as!(
-    Document,
-    r#"
-    SELECT id, user_id, title, authors, year, publication_type, journal,
-        volume, issue, pages, publisher, doi, url, abstract_text,
-        keywords, pdf_url, created_at, updated_at
-    FROM documents
-    WHERE user_id = $1
-    AND (
-        word_similarity($2, COALESCE(title, '')) > 0.3
-        OR word_similarity($2, COALESCE(abstract_text, '')) > 0.3
-        OR word_similarity($2, COALESCE(journal, '')) > 0.3
-        OR EXISTS (SELECT 1 FROM unnest(authors) AS author WHERE word_similarity($2, author) > 0.3)
-        OR EXISTS (SELECT 1 FROM unnest(keywords) AS keyword WHERE word_similarity($2, keyword) > 0.3)
-    )
-    ORDER BY 
-        GREATEST(
-            word_similarity($2, COALESCE(title, '')),
-            word_similarity($2, COALESCE(abstract_text, '')),
-            word_similarity($2, COALESCE(journal, ''))
-        ) DESC,
-        created_at DESC
-    "#,
-    user_id,
-    search_pattern
-)
-.fetch_all(&state.db)
-.await
-.map_err(|e| {
-    eprintln!("Search query error: {}", e);
-    (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Json(json!({"error": "Failed to search documents"})),
+    let updated = sqlx::query!(
+        "UPDATE users SET role = $1, updated_at = NOW() WHERE id = $2 RETURNING id, role",
+        payload.role,
+        target_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(admin_db_error)?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "User not found"})),
+    ))?;
+
+    record_admin_action(
+        &mut tx,
+        admin_id,
+        "set_role",
+        target_id,
+        json!({ "role": payload.role }),
     )
-})?;
+    .await?;
 
-    Ok(Json(documents))
+    tx.commit().await.map_err(admin_db_error)?;
+
+    Ok(Json(json!({ "id": updated.id, "role": updated.role })))
 }
 
-pub async fn chat_with_document(
-    AuthUser(claims): AuthUser,
+pub async fn admin_set_user_quota(
+    AdminUser(claims): AdminUser,
     State(state): State<AppState>,
-    Path(document_id): Path<String>,
-    Json(payload): Json<ChatRequest>,
-) -> Result<Json<ChatResponse>, (StatusCode, Json<Value>)> {
-    // Parse Document ID
-    let doc_id = uuid::Uuid::parse_str(&document_id).map_err(|_| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": "Invalid document ID"})),
-        )
-    })?;
-    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
-        (
+    Path(target_id): Path<uuid::Uuid>,
+    Json(payload): Json<UpdateUserQuota>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let admin_id = parse_admin_id(&claims)?;
+
+    if payload.storage_quota_bytes < 0 {
+        return Err((
             StatusCode::BAD_REQUEST,
-            Json(json!({"error": "Invalid user ID"})),
-        )
-    })?;
+            Json(json!({"error": "Quota cannot be negative"})),
+        ));
+    }
 
-    // Fetch doc from database and verify ownership
-    let document = sqlx::query_as!(
-        Document,
+    update_user_quota(
+        &state,
+        admin_id,
+        target_id,
+        Some(payload.storage_quota_bytes),
+    )
+    .await
+}
+
+pub async fn admin_reset_user_quota(
+    AdminUser(claims): AdminUser,
+    State(state): State<AppState>,
+    Path(target_id): Path<uuid::Uuid>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let admin_id = parse_admin_id(&claims)?;
+
+    update_user_quota(&state, admin_id, target_id, None).await
+}
+
+// Internal helper: set or clear (back to the instance default) a user's storage quota
+async fn update_user_quota(
+    state: &AppState,
+    admin_id: uuid::Uuid,
+    target_id: uuid::Uuid,
+    storage_quota_bytes: Option<i64>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let mut tx = state.db.begin().await.map_err(admin_db_error)?;
+
+    let updated = sqlx::query!(
         r#"
-        SELECT id, user_id, title, authors, year, publication_type, journal,
-            volume, issue, pages, publisher, doi, url, abstract_text,
-            keywords, pdf_url, created_at, updated_at
+        UPDATE users SET storage_quota_bytes = $1, updated_at = NOW()
+        WHERE id = $2
+        RETURNING id, storage_quota_bytes
+        "#,
+        storage_quota_bytes,
+        target_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(admin_db_error)?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "User not found"})),
+    ))?;
+
+    let action = if storage_quota_bytes.is_some() {
+        "set_quota"
+    } else {
+        "reset_quota"
+    };
+    record_admin_action(
+        &mut tx,
+        admin_id,
+        action,
+        target_id,
+        json!({ "storage_quota_bytes": storage_quota_bytes }),
+    )
+    .await?;
+
+    tx.commit().await.map_err(admin_db_error)?;
+
+    Ok(Json(json!({
+        "id": updated.id,
+        "storage_quota_bytes": updated.storage_quota_bytes,
+        "effective_quota_bytes": updated.storage_quota_bytes.or(state.default_storage_quota_bytes),
+    })))
+}
+
+pub async fn admin_storage_usage(
+    AdminUser(_claims): AdminUser,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let totals = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "document_count!",
+               COUNT(stored_file_path) AS "pdf_count!",
+               COALESCE(SUM(file_size_bytes), 0)::BIGINT AS "storage_used_bytes!"
         FROM documents
-        WHERE id = $1 AND user_id = $2
+        "#
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(admin_db_error)?;
+
+    let per_user = sqlx::query!(
+        r#"
+        SELECT u.id, u.email,
+               COALESCE(SUM(d.file_size_bytes), 0)::BIGINT AS "storage_used_bytes!",
+               COALESCE(u.storage_quota_bytes, $1) AS quota_bytes
+        FROM users u
+        -- Owners are charged for their groups' documents, as in quota checks
+        LEFT JOIN documents d ON d.user_id = u.id OR d.group_id IN (
+            SELECT gm.group_id FROM group_members gm
+            WHERE gm.user_id = u.id AND gm.role = 'owner'
+        )
+        GROUP BY u.id
+        ORDER BY 3 DESC
         "#,
-        doc_id,
-        user_id
+        state.default_storage_quota_bytes
     )
-    .fetch_optional(&state.db)
+    .fetch_all(&state.db)
     .await
-    .map_err(|e| {
-        eprintln!("Database error: {}", e);
+    .map_err(admin_db_error)?;
+
+    let users: Vec<Value> = per_user
+        .into_iter()
+        .map(|u| {
+            json!({
+                "id": u.id,
+                "email": u.email,
+                "storage_used_bytes": u.storage_used_bytes,
+                "quota_bytes": u.quota_bytes,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "document_count": totals.document_count,
+        "pdf_count": totals.pdf_count,
+        "storage_used_bytes": totals.storage_used_bytes,
+        "default_quota_bytes": state.default_storage_quota_bytes,
+        "users": users,
+    })))
+}
+
+pub async fn admin_impersonate_user(
+    AdminUser(claims): AdminUser,
+    State(state): State<AppState>,
+    Path(target_id): Path<uuid::Uuid>,
+    Json(payload): Json<ImpersonateRequest>,
+) -> Result<Json<LoginResponse>, (StatusCode, Json<Value>)> {
+    let admin_id = parse_admin_id(&claims)?;
+
+    if payload.reason.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "A reason is required to impersonate a user"})),
+        ));
+    }
+
+    if target_id == admin_id {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "You cannot impersonate yourself"})),
+        ));
+    }
+
+    let mut tx = state.db.begin().await.map_err(admin_db_error)?;
+
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, email, password_hash, username, profile_image_url, role, disabled_at, created_at, updated_at FROM users WHERE id = $1"#,
+        target_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(admin_db_error)?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "User not found"})),
+    ))?;
+
+    record_admin_action(
+        &mut tx,
+        admin_id,
+        "impersonate_user",
+        target_id,
+        json!({ "reason": payload.reason }),
+    )
+    .await?;
+
+    tx.commit().await.map_err(admin_db_error)?;
+
+    let token = create_impersonation_jwt(
+        &user.id.to_string(),
+        &user.email,
+        &user.role,
+        &admin_id.to_string(),
+        &state.jwt_secret,
+    )
+    .map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to fetch document"})),
-        )
-    })?
-    .ok_or_else(|| {
-        (
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Document not found"})),
+            Json(json!({"error": "Failed to generate token"})),
         )
     })?;
 
-    let pdf_url = document.pdf_url.ok_or_else(|| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": "Document has no PDF"})),
-        )
-    })?;
+    Ok(Json(LoginResponse {
+        token,
+        user: UserResponse {
+            id: user.id,
+            email: user.email,
+            username: user.username,
+            profile_image_url: user.profile_image_url,
+            role: user.role,
+        },
+    }))
+}
+
+pub async fn admin_audit_log(
+    AdminUser(_claims): AdminUser,
+    State(state): State<AppState>,
+    Query(params): Query<AuditLogQuery>,
+) -> Result<Json<Vec<AuditLogEntry>>, (StatusCode, Json<Value>)> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+
+    let entries = sqlx::query_as!(
+        AuditLogEntry,
+        r#"
+        SELECT id, admin_id, action, target_user_id, details, created_at
+        FROM admin_audit_log
+        WHERE $1::UUID IS NULL OR target_user_id = $1
+        ORDER BY created_at DESC
+        LIMIT $2
+        "#,
+        params.target_user_id,
+        limit
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(admin_db_error)?;
+
+    Ok(Json(entries))
+}
 
-    // Extract full PDF text
-    let pdf_text = crate::metadata::extract_full_pdf_text(&pdf_url).map_err(|e| {
-        eprintln!("PDF extraction error: {}", e);
+fn parse_admin_id(claims: &crate::models::Claims) -> Result<uuid::Uuid, (StatusCode, Json<Value>)> {
+    uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": "Failed to extract PDF text"})),
+            Json(json!({"error": "Invalid user ID"})),
         )
-    })?;
+    })
+}
 
-    // Call OpenAI chat
-    let ai_response =
-        crate::metadata::chat_with_paper(&document.title, &pdf_text, &payload.message)
-            .await
-            .map_err(|e| {
-                eprintln!("OpenAI chat error: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({"error": "Failed to chat with document"})),
-                )
-            })?;
+fn admin_db_error(_: sqlx::Error) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"error": "Database error"})),
+    )
+}
 
-    Ok(Json(ChatResponse {
-        response: ai_response,
-    }))
+// Internal helper: append an entry to the admin audit trail
+async fn record_admin_action(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    admin_id: uuid::Uuid,
+    action: &str,
+    target_user_id: uuid::Uuid,
+    details: Value,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_audit_log (admin_id, action, target_user_id, details)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        admin_id,
+        action,
+        target_user_id,
+        details
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(admin_db_error)?;
+
+    Ok(())
 }
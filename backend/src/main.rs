@@ -1,17 +1,40 @@
 mod auth;
+mod bibtex;
+mod bulk;
+mod citation;
+mod cleanup;
+mod collection_delete;
+mod collection_tree;
 mod config;
+mod document_query;
+mod embeddings;
+mod export;
+mod facets;
+mod groups;
 mod handlers;
 mod metadata;
 mod middleware;
 mod models;
+mod oidc;
+mod ordering;
+mod password;
+mod pdf_text;
+mod query_parser;
+mod rate_limit;
 mod routes;
+mod search;
+mod sharing;
+mod similar;
 mod state;
+mod suggest;
+mod trash;
 
 use config::Config;
 use routes::create_routes;
 use state::AppState;
 
 use axum::extract::DefaultBodyLimit;
+use std::net::SocketAddr;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
 
@@ -24,13 +47,42 @@ async fn main() {
         .expect("Failed to create a database pool");
     println!("Connected the the database: OK");
 
-    let app_state = AppState::new(pool, config.jwt_secret);
+    // `scholarvault-backend grant-admin <email>` promotes an existing account and exits
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let [command, email] = args.as_slice()
+        && command == "grant-admin"
+    {
+        match grant_admin(&pool, email).await {
+            Ok(true) => println!("{} is now an admin", email),
+            Ok(false) => {
+                eprintln!("No single account has the email {}", email);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Failed to grant admin: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let app_state = AppState::new(pool, config);
+
+    if app_state.embedder.is_some() {
+        embeddings::ensure_chunk_table(&app_state.db)
+            .await
+            .expect("Semantic search needs the pgvector extension in Postgres");
+    }
 
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
         .allow_headers(Any);
 
+    cleanup::spawn_cleanup_task(app_state.clone());
+    embeddings::spawn_embedding_task(app_state.clone());
+    pdf_text::spawn_indexing_task(app_state.clone());
+
     let app = create_routes(app_state)
         .nest_service("/uploads", ServeDir::new("uploads"))
         .layer(DefaultBodyLimit::max(100 * 1024 * 1024)) // 100MB for multipart
@@ -39,5 +91,46 @@ async fn main() {
     println!("Server running on http://0.0.0.0:3000");
     println!("Health check: http://0.0.0.0:3000/health");
 
-    axum::serve(listener, app).await.unwrap();
+    // Client addresses are needed by the rate limiter
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
+}
+
+// Make the account with this email (case-insensitive) an admin. Only an account that already
+// exists is promoted, so nobody can claim the role by registering a configured address later.
+async fn grant_admin(db: &sqlx::PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let user_ids = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE LOWER(email) = LOWER($1) FOR UPDATE",
+        email.trim()
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let [user_id] = user_ids.as_slice() else {
+        return Ok(false);
+    };
+
+    sqlx::query!("UPDATE users SET role = 'admin' WHERE id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    // No admin acted; the details record that it came from the command line
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_audit_log (admin_id, action, target_user_id, details)
+        VALUES (NULL, 'set_role', $1, $2)
+        "#,
+        user_id,
+        serde_json::json!({ "role": "admin", "via": "grant-admin" })
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
 }
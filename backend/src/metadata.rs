@@ -43,6 +43,7 @@ struct CrossRefMessage {
     #[serde(rename = "container-title")]
     container_title: Option<Vec<String>>,
     #[serde(rename = "DOI")]
+    #[allow(dead_code)]
     doi: Option<String>,
     #[serde(rename = "abstract")]
     abstract_text: Option<String>,
@@ -68,9 +69,13 @@ struct CrossRefDate {
     date_parts: Option<Vec<Vec<i32>>>,
 }
 
-pub async fn extract_metadata_from_pdf(pdf_path: &str) -> Result<CreateDocument, String> {
-    // Extract text from PDF
-    let pdf_text = extract_text_from_pdf(pdf_path)?;
+// Metadata from the text of an uploaded PDF, which is extracted once for this and the page index
+pub async fn extract_metadata_from_text(text: &str) -> Result<CreateDocument, String> {
+    // Take first 4000 characters to avoid token limits
+    let pdf_text = match text.char_indices().nth(4000) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    };
 
     let mut final_metadata = CreateDocument {
         title: String::new(), // Will be filled, required field
@@ -86,11 +91,10 @@ pub async fn extract_metadata_from_pdf(pdf_path: &str) -> Result<CreateDocument,
         url: None,
         abstract_text: None,
         keywords: None,
-        pdf_url: None, // Set later in handlers.rs
     };
 
     // Try finding doi
-    if let Some(doi) = extract_doi_from_text(&pdf_text) {
+    if let Some(doi) = extract_doi_from_text(pdf_text) {
         println!("Found DOI: {}", doi);
         // try lookup
         match lookup_doi_metadata(&doi).await {
@@ -100,7 +104,7 @@ pub async fn extract_metadata_from_pdf(pdf_path: &str) -> Result<CreateDocument,
                 final_metadata = crossref_metadata;
 
                 // Check what's missing
-                let missing_fields = vec![
+                let _missing_fields = vec![
                     if final_metadata.title.is_empty() {
                         Some("title")
                     } else {
@@ -247,29 +251,14 @@ pub async fn extract_metadata_from_pdf(pdf_path: &str) -> Result<CreateDocument,
     Ok(final_metadata)
 }
 
-fn extract_text_from_pdf(pdf_path: &str) -> Result<String, String> {
-    let bytes = std::fs::read(pdf_path).map_err(|e| format!("Failed to read PDF: {}", e))?;
-
-    let text = pdf_extract::extract_text_from_mem(&bytes)
-        .map_err(|e| format!("Failed to extract text from PDF: {}", e))?;
-
-    // Take first 4000 characters to avoid token limits
-    let truncated = if text.len() > 4000 {
-        &text[..4000]
-    } else {
-        &text
-    };
-
-    Ok(truncated.to_string())
-}
-
-pub fn extract_full_pdf_text(pdf_path: &str) -> Result<String, String> {
+// Text of every page, in page order
+pub fn extract_pdf_pages(pdf_path: &str) -> Result<Vec<String>, String> {
     let bytes = std::fs::read(pdf_path).map_err(|e| format!("Failed to read PDF: {}", e))?;
 
-    let text = pdf_extract::extract_text_from_mem(&bytes)
+    let pages = pdf_extract::extract_text_from_mem_by_pages(&bytes)
         .map_err(|e| format!("Failed to extract text from PDF: {}", e))?;
 
-    Ok(text)
+    Ok(pages)
 }
 
 fn extract_doi_from_text(text: &str) -> Option<String> {
@@ -333,10 +322,10 @@ async fn lookup_doi_metadata(doi: &str) -> Result<CreateDocument, String> {
 
     Ok(CreateDocument {
         title: title.unwrap_or_default(),
-        authors: authors,
-        year: year,
+        authors,
+        year,
         publication_type: msg.publication_type,
-        journal: journal,
+        journal,
         volume: msg.volume,
         issue: msg.issue,
         pages: msg.page,
@@ -345,10 +334,10 @@ async fn lookup_doi_metadata(doi: &str) -> Result<CreateDocument, String> {
         url: msg.url,
         abstract_text: msg.abstract_text,
         keywords: None, // CrossRef doesn't provide keywords
-        pdf_url: None,
     })
 }
 
+#[allow(dead_code)] // AI extraction is commented out for front-end development
 async fn analyze_with_openai(pdf_text: &str) -> Result<CreateDocument, String> {
     let api_key = env::var("OPENAI_API_KEY").map_err(|_| "OPENAI_API_KEY not set".to_string())?;
 
@@ -1,6 +1,6 @@
 use axum::{
     Json, RequestPartsExt,
-    extract::{FromRequestParts, State},
+    extract::FromRequestParts,
     http::{StatusCode, request::Parts},
 };
 use axum_extra::{
@@ -32,13 +32,69 @@ impl FromRequestParts<AppState> for AuthUser {
             })?;
 
         // Verify the token
-        let claims = verify_jwt(bearer.token(), &state.jwt_secret).map_err(|_| {
+        let mut claims = verify_jwt(bearer.token(), &state.jwt_secret).map_err(|_| {
             (
                 StatusCode::UNAUTHORIZED,
                 Json(json!({"error": "Invalid or expired token"})),
             )
         })?;
 
+        // Disabled accounts lose access immediately, not when their token expires
+        let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
+            (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"error": "Invalid or expired token"})),
+            )
+        })?;
+
+        let account = sqlx::query!("SELECT role, disabled_at FROM users WHERE id = $1", user_id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(|_| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": "Database error"})),
+                )
+            })?
+            .ok_or((
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"error": "Invalid or expired token"})),
+            ))?;
+
+        if account.disabled_at.is_some() {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(json!({"error": "Account is disabled"})),
+            ));
+        }
+
+        // The stored role wins over the token's, so promotions and demotions apply immediately
+        claims.role = account.role;
+
         Ok(AuthUser(claims))
     }
 }
+
+pub struct AdminUser(pub Claims);
+
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = (StatusCode, Json<Value>);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthUser(claims) = AuthUser::from_request_parts(parts, state).await?;
+
+        // `role` was reloaded from the database by AuthUser.
+        // Impersonation tokens never carry admin rights.
+        if !claims.is_admin() || claims.impersonated_by.is_some() {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(json!({"error": "Admin access required"})),
+            ));
+        }
+
+        Ok(AdminUser(claims))
+    }
+}
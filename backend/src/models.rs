@@ -1,5 +1,6 @@
+use crate::groups::Library;
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
@@ -8,9 +9,11 @@ pub struct User {
     pub id: Uuid,
     pub email: String,
     #[serde(skip_serializing)] //never send the has to the client
-    pub password_hash: String,
+    pub password_hash: Option<String>, // None for accounts created through single sign-on
     pub username: Option<String>,
     pub profile_image_url: Option<String>,
+    pub role: String, // "user" or "admin"
+    pub disabled_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -27,6 +30,12 @@ pub struct UpdateProfile {
     pub username: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ChangePassword {
+    pub current_password: Option<String>, // not needed by single sign-on accounts without one
+    pub new_password: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LoginRequest {
     pub email: String,
@@ -39,26 +48,51 @@ pub struct LoginResponse {
     pub user: UserResponse,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackRequest {
+    pub code: String,
+    pub state: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct UserResponse {
     pub id: Uuid,
     pub email: String,
     pub username: Option<String>,
     pub profile_image_url: Option<String>,
+    pub role: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, //subject (user id)
     pub email: String,
+    #[serde(default = "default_role")] // tokens issued before roles existed
+    pub role: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonated_by: Option<String>, // admin user id when issued for support
+    #[serde(default)] // tokens issued before iat was recorded count as old
+    pub iat: usize, //issued at, i.e. when the user signed in
     pub exp: usize, //expiration time
 }
 
+fn default_role() -> String {
+    "user".to_string()
+}
+
+impl Claims {
+    pub fn is_admin(&self) -> bool {
+        self.role == "admin"
+    }
+}
+
 // Document models
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
 pub struct Document {
     pub id: Uuid,
-    pub user_id: Uuid,
+    // Exactly one of user_id and group_id is set: the library the document belongs to
+    pub user_id: Option<Uuid>,
+    pub group_id: Option<Uuid>,
     pub title: String,
     pub authors: Option<Vec<String>>,
     pub year: Option<i32>,
@@ -72,6 +106,7 @@ pub struct Document {
     pub url: Option<String>,
     pub abstract_text: Option<String>, // 'abstract' is a Rust keyword, so we use abstract_text
     pub keywords: Option<Vec<String>>,
+    // The uploaded PDF under /uploads, read from stored_file_path; only the server sets it
     pub pdf_url: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -92,7 +127,6 @@ pub struct CreateDocument {
     pub url: Option<String>,
     pub abstract_text: Option<String>,
     pub keywords: Option<Vec<String>>,
-    pub pdf_url: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -110,28 +144,297 @@ pub struct UpdateDocument {
     pub url: Option<String>,
     pub abstract_text: Option<String>,
     pub keywords: Option<Vec<String>>,
-    pub pdf_url: Option<String>,
 }
 
 // Collection models
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
 pub struct Collection {
     pub id: Uuid,
-    pub user_id: Uuid,
+    // Exactly one of user_id and group_id is set, as for documents
+    pub user_id: Option<Uuid>,
+    pub group_id: Option<Uuid>,
     pub name: String,
     pub parent_id: Option<Uuid>,
+    // Set for smart collections, whose documents are the matches of this search query
+    pub query: Option<String>,
+    // Order among its siblings
+    pub position: f64,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+impl Collection {
+    pub fn library(&self) -> Library {
+        Library::of(self.user_id, self.group_id)
+    }
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct TrashedDocument {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub document: Document,
+    pub deleted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct TrashedCollection {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub collection: Collection,
+    pub deleted_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateCollection {
     pub name: String,
     pub parent_id: Option<Uuid>,
+    pub query: Option<String>,
+}
+
+// Tells a missing field (None) apart from an explicit null (Some(None))
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateCollection {
     pub name: Option<String>,
-    pub parent_id: Option<Uuid>,
+    // null moves the collection to the root; omitted leaves it where it is
+    #[serde(default, deserialize_with = "nullable")]
+    pub parent_id: Option<Option<Uuid>>,
+    pub query: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct CollectionShare {
+    pub id: Uuid,
+    pub collection_id: Uuid,
+    pub email: String,
+    // Set once the invitation is accepted
+    pub user_id: Option<Uuid>,
+    pub role: String, // "viewer" or "editor"
+    pub include_subcollections: bool,
+    // One-time token the owner sends to the invitee; cleared once the invitation is accepted
+    pub invite_token: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateShare {
+    pub email: String,
+    pub role: String,
+    // Defaults to sharing the whole subtree
+    pub include_subcollections: Option<bool>,
+}
+
+// A pending invitation, as seen by the invited user
+#[derive(Debug, Serialize, FromRow)]
+pub struct ShareInvitation {
+    pub id: Uuid,
+    pub collection_id: Uuid,
+    pub collection_name: String,
+    pub role: String,
+    pub include_subcollections: bool,
+    pub invited_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// A collection another user shared, directly or as part of a shared subtree
+#[derive(Debug, Serialize)]
+pub struct SharedCollection {
+    #[serde(flatten)]
+    pub collection: Collection,
+    pub can_edit: bool,
+    pub owner_email: String,
+    pub owner_username: Option<String>,
+}
+
+// A public read-only link to a collection; the token is the only credential needed to use it
+#[derive(Debug, Serialize, FromRow)]
+pub struct CollectionLink {
+    pub id: Uuid,
+    pub collection_id: Uuid,
+    pub token: String,
+    pub allow_downloads: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCollectionLink {
+    // Defaults to metadata only
+    pub allow_downloads: Option<bool>,
+    // Never expires when omitted
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+// A document as shown to visitors of a public link: no owner, and no stored file path
+#[derive(Debug, Serialize)]
+pub struct PublicDocument {
+    pub id: Uuid,
+    pub title: String,
+    pub authors: Option<Vec<String>>,
+    pub year: Option<i32>,
+    pub publication_type: Option<String>,
+    pub journal: Option<String>,
+    pub volume: Option<String>,
+    pub issue: Option<String>,
+    pub pages: Option<String>,
+    pub publisher: Option<String>,
+    pub doi: Option<String>,
+    pub url: Option<String>,
+    pub abstract_text: Option<String>,
+    pub keywords: Option<Vec<String>>,
+    // Whether the PDF can be downloaded through the link
+    pub has_pdf: bool,
+}
+
+impl PublicDocument {
+    pub fn new(document: Document, allow_downloads: bool) -> Self {
+        PublicDocument {
+            has_pdf: allow_downloads && document.pdf_url.is_some(),
+            id: document.id,
+            title: document.title,
+            authors: document.authors,
+            year: document.year,
+            publication_type: document.publication_type,
+            journal: document.journal,
+            volume: document.volume,
+            issue: document.issue,
+            pages: document.pages,
+            publisher: document.publisher,
+            doi: document.doi,
+            url: document.url,
+            abstract_text: document.abstract_text,
+            keywords: document.keywords,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublicCollection {
+    pub name: String,
+    pub allow_downloads: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub documents: Vec<PublicDocument>,
+}
+
+// Group models
+// A group the user belongs to, with their role in it
+#[derive(Debug, Serialize, FromRow)]
+pub struct Group {
+    pub id: Uuid,
+    pub name: String,
+    pub role: String, // "owner", "admin", "member" or "reader"
+    pub member_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateGroup {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateGroup {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct GroupMember {
+    pub user_id: Uuid,
+    pub email: String,
+    pub username: Option<String>,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddGroupMember {
+    // Of a registered user
+    pub email: String,
+    pub role: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateGroupMember {
+    pub role: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DuplicateCollection {
+    // Defaults to the original name with " (copy)"
+    pub name: Option<String>,
+}
+
+// Moves `ids`, in this order, to right after `after`, or to the start when it is null.
+// Listing every item gives a full ordering.
+#[derive(Debug, Deserialize)]
+pub struct ReorderRequest {
+    pub ids: Vec<Uuid>,
+    pub after: Option<Uuid>,
+}
+
+// Admin models
+#[derive(Debug, Serialize, FromRow)]
+pub struct AdminUserSummary {
+    pub id: Uuid,
+    pub email: String,
+    pub username: Option<String>,
+    pub role: String,
+    pub disabled_at: Option<DateTime<Utc>>,
+    pub storage_quota_bytes: Option<i64>, // per-user override, None means the instance default
+    pub document_count: i64,
+    pub storage_used_bytes: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUserStatus {
+    pub disabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUserRole {
+    pub role: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUserQuota {
+    pub storage_quota_bytes: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImpersonateRequest {
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct AuditLogEntry {
+    pub id: Uuid,
+    pub admin_id: Option<Uuid>,
+    pub action: String,
+    pub target_user_id: Option<Uuid>,
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccountRequest {
+    pub password: Option<String>,
+    pub confirm_email: Option<String>, // for single sign-on accounts without a password
+}
+
+#[derive(Debug, Serialize)]
+pub struct DocumentPage {
+    pub documents: Vec<Document>,
+    pub total: i64,
+    pub next_cursor: Option<String>,
 }
@@ -0,0 +1,494 @@
+// OpenID Connect authorization-code + PKCE helpers
+use crate::config::OidcProviderConfig;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    jwk::{Jwk, JwkSet},
+};
+use rand::RngCore;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// How long discovery documents and key sets are reused before being fetched again
+const CACHE_TTL: Duration = Duration::from_secs(3600);
+
+// Values with the time they were fetched
+type CacheMap<T> = Mutex<HashMap<String, (Instant, Arc<T>)>>;
+
+// Provider metadata and signing keys, keyed by issuer and JWKS URL
+#[derive(Clone, Default)]
+pub struct OidcCache {
+    metadata: Arc<CacheMap<ProviderMetadata>>,
+    jwks: Arc<CacheMap<JwkSet>>,
+}
+
+fn cached<T>(map: &CacheMap<T>, key: &str) -> Option<Arc<T>> {
+    map.lock()
+        .unwrap()
+        .get(key)
+        .filter(|(fetched, _)| fetched.elapsed() < CACHE_TTL)
+        .map(|(_, value)| value.clone())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub nonce: Option<String>,
+    pub preferred_username: Option<String>,
+    pub name: Option<String>,
+}
+
+// The provider's discovery document, fetched at most once per CACHE_TTL
+pub async fn discover(
+    cache: &OidcCache,
+    provider: &OidcProviderConfig,
+) -> Result<Arc<ProviderMetadata>, String> {
+    if let Some(metadata) = cached(&cache.metadata, &provider.issuer_url) {
+        return Ok(metadata);
+    }
+
+    let url = format!("{}/.well-known/openid-configuration", provider.issuer_url);
+
+    let response = reqwest::get(&url)
+        .await
+        .map_err(|e| format!("OIDC discovery request failed: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!(
+            "OIDC discovery returned status: {}",
+            response.status()
+        ));
+    }
+
+    let metadata: ProviderMetadata = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse OIDC discovery document: {}", e))?;
+
+    if metadata.issuer.trim_end_matches('/') != provider.issuer_url {
+        return Err(format!(
+            "OIDC issuer mismatch: expected {}, got {}",
+            provider.issuer_url, metadata.issuer
+        ));
+    }
+
+    let metadata = Arc::new(metadata);
+    cache.metadata.lock().unwrap().insert(
+        provider.issuer_url.clone(),
+        (Instant::now(), metadata.clone()),
+    );
+    Ok(metadata)
+}
+
+async fn fetch_jwks(cache: &OidcCache, jwks_uri: &str) -> Result<Arc<JwkSet>, String> {
+    let jwks: JwkSet = reqwest::get(jwks_uri)
+        .await
+        .map_err(|e| format!("OIDC JWKS request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse OIDC JWKS: {}", e))?;
+
+    let jwks = Arc::new(jwks);
+    cache
+        .jwks
+        .lock()
+        .unwrap()
+        .insert(jwks_uri.to_string(), (Instant::now(), jwks.clone()));
+    Ok(jwks)
+}
+
+// The key a token was signed with; an unknown key id refetches the set once,
+// since providers rotate keys ahead of the cache expiring
+async fn signing_key(cache: &OidcCache, jwks_uri: &str, kid: Option<&str>) -> Result<Jwk, String> {
+    let find = |jwks: &JwkSet| match kid {
+        Some(kid) => jwks.find(kid).cloned(),
+        None => jwks.keys.first().cloned(),
+    };
+
+    if let Some(jwk) = cached(&cache.jwks, jwks_uri).and_then(|jwks| find(&jwks)) {
+        return Ok(jwk);
+    }
+
+    let jwks = fetch_jwks(cache, jwks_uri).await?;
+    find(&jwks).ok_or_else(|| "No matching signing key in OIDC JWKS".to_string())
+}
+
+// Algorithms a token from this key may use: the key's own `alg` when it names one,
+// otherwise the provider's configured list. Never whatever the token header claims.
+fn allowed_algorithms(provider: &OidcProviderConfig, jwk: &Jwk) -> Result<Vec<Algorithm>, String> {
+    match jwk.common.key_algorithm {
+        Some(key_algorithm) => Algorithm::from_str(&key_algorithm.to_string())
+            .map(|alg| vec![alg])
+            .map_err(|_| format!("OIDC signing key has unusable algorithm {}", key_algorithm)),
+        None => Ok(provider.signing_algorithms.clone()),
+    }
+}
+
+// Random URL-safe string used for state, nonce and the PKCE code verifier
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// S256 code challenge for a PKCE code verifier
+pub fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+pub fn authorization_url(
+    provider: &OidcProviderConfig,
+    metadata: &ProviderMetadata,
+    state: &str,
+    nonce: &str,
+    code_verifier: &str,
+) -> Result<String, String> {
+    let challenge = pkce_challenge(code_verifier);
+
+    let url = reqwest::Url::parse_with_params(
+        &metadata.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", provider.client_id.as_str()),
+            ("redirect_uri", provider.redirect_url.as_str()),
+            ("scope", provider.scopes.as_str()),
+            ("state", state),
+            ("nonce", nonce),
+            ("code_challenge", challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .map_err(|e| format!("Invalid authorization endpoint: {}", e))?;
+
+    Ok(url.to_string())
+}
+
+// Exchange the authorization code for tokens and return the ID token
+pub async fn exchange_code(
+    provider: &OidcProviderConfig,
+    metadata: &ProviderMetadata,
+    code: &str,
+    code_verifier: &str,
+) -> Result<String, String> {
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", provider.redirect_url.as_str()),
+        ("client_id", provider.client_id.as_str()),
+        ("code_verifier", code_verifier),
+    ];
+    if let Some(secret) = &provider.client_secret {
+        form.push(("client_secret", secret.as_str()));
+    }
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(&metadata.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| format!("OIDC token request failed: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!(
+            "OIDC token endpoint returned status: {}",
+            response.status()
+        ));
+    }
+
+    let tokens: TokenResponse = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse OIDC token response: {}", e))?;
+
+    tokens
+        .id_token
+        .ok_or_else(|| "OIDC token response has no id_token".to_string())
+}
+
+// Verify the ID token signature against the provider's JWKS and check iss, aud, exp and nonce
+pub async fn verify_id_token(
+    cache: &OidcCache,
+    provider: &OidcProviderConfig,
+    metadata: &ProviderMetadata,
+    id_token: &str,
+    expected_nonce: &str,
+) -> Result<IdTokenClaims, String> {
+    let header = decode_header(id_token).map_err(|e| format!("Invalid ID token header: {}", e))?;
+
+    let jwk = signing_key(cache, &metadata.jwks_uri, header.kid.as_deref()).await?;
+    let algorithms = allowed_algorithms(provider, &jwk)?;
+    if !algorithms.contains(&header.alg) {
+        return Err(format!(
+            "ID token uses disallowed algorithm {:?}",
+            header.alg
+        ));
+    }
+
+    let key =
+        DecodingKey::from_jwk(&jwk).map_err(|e| format!("Unsupported OIDC signing key: {}", e))?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.algorithms = algorithms;
+    validation.set_issuer(&[&metadata.issuer]);
+    validation.set_audience(&[&provider.client_id]);
+
+    let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+        .map_err(|e| format!("ID token validation failed: {}", e))?
+        .claims;
+
+    if claims.nonce.as_deref() != Some(expected_nonce) {
+        return Err("ID token nonce mismatch".to_string());
+    }
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Form, Json, Router,
+        extract::State,
+        routing::{get, post},
+    };
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use serde_json::{Value, json};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // 2048-bit RSA key that only exists for these tests
+    const TEST_KEY: &str = include_str!("testdata/oidc_test_key.pem");
+    const TEST_KEY_N: &str = "2eiHmGwJRD6QZsLWWjfvdPoJe2sOKYWCNwd9Y-d-GOG-mLyf4tdmUbS3so0sFWaazmjd4r0IqJ37rF4ZJG6zFsa_OSR074B4xq9QXPhOv7Op_HSreOfaNcFkC_eF2X031fGi2R1r-Ec6ZOd-Id3h4QKlddxLjeSHuJbQHkf7ZAusM3gCXrcL2x4fDcNhWeqW9gBa1kyJXarl9HecpT3_gfsktp2nsKFuRhHmz6yjT8BAnkyMAQORviKThQGIUdq_zxn54zl-N4nJ07CSNntub973fXg2_OXSn3u6u49RMr1Rb-RXiqra2N5QjETDdEePr1XNucEkFs_D7X6TDhQz9w";
+    const KID: &str = "test-key";
+    const CLIENT_ID: &str = "scholarvault";
+    const NONCE: &str = "test-nonce";
+    const CODE: &str = "test-code";
+
+    #[derive(Clone)]
+    struct MockIdp {
+        issuer: String,
+        // `alg` published on the signing key, if any
+        key_alg: Option<&'static str>,
+        id_token: Arc<Mutex<String>>,
+        discovery_hits: Arc<AtomicUsize>,
+        jwks_hits: Arc<AtomicUsize>,
+    }
+
+    async fn discovery(State(idp): State<MockIdp>) -> Json<Value> {
+        idp.discovery_hits.fetch_add(1, Ordering::SeqCst);
+        Json(json!({
+            "issuer": idp.issuer,
+            "authorization_endpoint": format!("{}/authorize", idp.issuer),
+            "token_endpoint": format!("{}/token", idp.issuer),
+            "jwks_uri": format!("{}/jwks", idp.issuer),
+        }))
+    }
+
+    async fn jwks(State(idp): State<MockIdp>) -> Json<Value> {
+        idp.jwks_hits.fetch_add(1, Ordering::SeqCst);
+        let mut key = json!({"kty": "RSA", "kid": KID, "use": "sig", "n": TEST_KEY_N, "e": "AQAB"});
+        if let Some(alg) = idp.key_alg {
+            key["alg"] = json!(alg);
+        }
+        Json(json!({ "keys": [key] }))
+    }
+
+    async fn token(
+        State(idp): State<MockIdp>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Result<Json<Value>, axum::http::StatusCode> {
+        if form.get("code").map(String::as_str) != Some(CODE) || !form.contains_key("code_verifier")
+        {
+            return Err(axum::http::StatusCode::BAD_REQUEST);
+        }
+        Ok(Json(json!({ "id_token": *idp.id_token.lock().unwrap() })))
+    }
+
+    // Serve discovery, JWKS and a token endpoint on a random local port
+    async fn start_idp(key_alg: Option<&'static str>) -> (MockIdp, OidcProviderConfig) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let idp = MockIdp {
+            issuer: format!("http://{}", listener.local_addr().unwrap()),
+            key_alg,
+            id_token: Arc::default(),
+            discovery_hits: Arc::default(),
+            jwks_hits: Arc::default(),
+        };
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .with_state(idp.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let provider = OidcProviderConfig {
+            name: "mock".to_string(),
+            issuer_url: idp.issuer.clone(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: None,
+            redirect_url: "http://localhost:3001/auth/callback/mock".to_string(),
+            scopes: "openid email profile".to_string(),
+            signing_algorithms: vec![Algorithm::RS256],
+            trust_email: false,
+        };
+        (idp, provider)
+    }
+
+    fn claims(issuer: &str) -> Value {
+        json!({
+            "iss": issuer,
+            "aud": CLIENT_ID,
+            "sub": "user-1",
+            "email": "ada@example.edu",
+            "email_verified": true,
+            "nonce": NONCE,
+            "exp": chrono::Utc::now().timestamp() + 300,
+        })
+    }
+
+    fn sign(claims: &Value, alg: Algorithm, kid: &str) -> String {
+        let mut header = Header::new(alg);
+        header.kid = Some(kid.to_string());
+        let key = match alg {
+            Algorithm::HS256 => EncodingKey::from_secret(TEST_KEY_N.as_bytes()),
+            _ => EncodingKey::from_rsa_pem(TEST_KEY.as_bytes()).unwrap(),
+        };
+        encode(&header, claims, &key).unwrap()
+    }
+
+    #[tokio::test]
+    async fn completes_login_against_mock_provider() {
+        let (idp, provider) = start_idp(None).await;
+        let cache = OidcCache::default();
+        *idp.id_token.lock().unwrap() = sign(&claims(&idp.issuer), Algorithm::RS256, KID);
+
+        let metadata = discover(&cache, &provider).await.unwrap();
+        let url = authorization_url(&provider, &metadata, "state", NONCE, "verifier").unwrap();
+        assert!(url.starts_with(&format!("{}/authorize?", idp.issuer)));
+        assert!(url.contains("code_challenge_method=S256"));
+
+        let id_token = exchange_code(&provider, &metadata, CODE, "verifier")
+            .await
+            .unwrap();
+        let claims = verify_id_token(&cache, &provider, &metadata, &id_token, NONCE)
+            .await
+            .unwrap();
+        assert_eq!(claims.sub, "user-1");
+        assert_eq!(claims.email.as_deref(), Some("ada@example.edu"));
+        assert!(claims.email_verified);
+
+        assert!(
+            exchange_code(&provider, &metadata, "wrong-code", "verifier")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn caches_discovery_and_keys() {
+        let (idp, provider) = start_idp(None).await;
+        let cache = OidcCache::default();
+        let token = sign(&claims(&idp.issuer), Algorithm::RS256, KID);
+
+        for _ in 0..3 {
+            let metadata = discover(&cache, &provider).await.unwrap();
+            verify_id_token(&cache, &provider, &metadata, &token, NONCE)
+                .await
+                .unwrap();
+        }
+        assert_eq!(idp.discovery_hits.load(Ordering::SeqCst), 1);
+        assert_eq!(idp.jwks_hits.load(Ordering::SeqCst), 1);
+
+        // An unknown key id refreshes the key set once before failing
+        let metadata = discover(&cache, &provider).await.unwrap();
+        let rotated = sign(&claims(&idp.issuer), Algorithm::RS256, "rotated-key");
+        assert!(
+            verify_id_token(&cache, &provider, &metadata, &rotated, NONCE)
+                .await
+                .is_err()
+        );
+        assert_eq!(idp.jwks_hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_algorithms_the_key_does_not_allow() {
+        let (idp, provider) = start_idp(None).await;
+        let cache = OidcCache::default();
+        let metadata = discover(&cache, &provider).await.unwrap();
+
+        // Not in the configured list
+        let hs256 = sign(&claims(&idp.issuer), Algorithm::HS256, KID);
+        let rs384 = sign(&claims(&idp.issuer), Algorithm::RS384, KID);
+        for token in [hs256, rs384] {
+            assert!(
+                verify_id_token(&cache, &provider, &metadata, &token, NONCE)
+                    .await
+                    .is_err()
+            );
+        }
+
+        // The key's own alg takes precedence over the configured list
+        let (idp, provider) = start_idp(Some("RS384")).await;
+        let metadata = discover(&cache, &provider).await.unwrap();
+        let rs256 = sign(&claims(&idp.issuer), Algorithm::RS256, KID);
+        let rs384 = sign(&claims(&idp.issuer), Algorithm::RS384, KID);
+        assert!(
+            verify_id_token(&cache, &provider, &metadata, &rs256, NONCE)
+                .await
+                .is_err()
+        );
+        assert!(
+            verify_id_token(&cache, &provider, &metadata, &rs384, NONCE)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_nonce_audience_and_issuer() {
+        let (idp, provider) = start_idp(None).await;
+        let cache = OidcCache::default();
+        let metadata = discover(&cache, &provider).await.unwrap();
+
+        let valid = sign(&claims(&idp.issuer), Algorithm::RS256, KID);
+        assert!(
+            verify_id_token(&cache, &provider, &metadata, &valid, "other-nonce")
+                .await
+                .is_err()
+        );
+
+        for (field, value) in [("aud", "someone-else"), ("iss", "https://evil.example")] {
+            let mut claims = claims(&idp.issuer);
+            claims[field] = json!(value);
+            let token = sign(&claims, Algorithm::RS256, KID);
+            assert!(
+                verify_id_token(&cache, &provider, &metadata, &token, NONCE)
+                    .await
+                    .is_err()
+            );
+        }
+    }
+}
@@ -0,0 +1,203 @@
+// Manual ordering with fractional positions: sibling collections, and documents within a collection
+use uuid::Uuid;
+
+// Below this spacing between neighbours the whole list is renumbered instead
+const MIN_POSITION_GAP: f64 = 1e-9;
+
+// New positions placing `moved`, in the given order, right after `after` (or first when None)
+// within `items` (id, position). Only the moved items are rewritten, unless there is no room
+// left between their neighbours and the list is renumbered.
+pub fn reorder(
+    items: &[(Uuid, f64)],
+    moved: &[Uuid],
+    after: Option<Uuid>,
+) -> Result<Vec<(Uuid, f64)>, &'static str> {
+    if moved.is_empty() {
+        return Err("No items to reorder");
+    }
+    for (i, id) in moved.iter().enumerate() {
+        if moved[..i].contains(id) {
+            return Err("An item is listed more than once");
+        }
+        if !items.iter().any(|(item, _)| item == id) {
+            return Err("Every item must belong to the list being reordered");
+        }
+    }
+
+    let mut rest: Vec<(Uuid, f64)> = items
+        .iter()
+        .filter(|(id, _)| !moved.contains(id))
+        .copied()
+        .collect();
+    rest.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let insert_at = match after {
+        Some(after) => {
+            rest.iter()
+                .position(|(id, _)| *id == after)
+                .ok_or("'after' must be an item of the list that is not being moved")?
+                + 1
+        }
+        None => 0,
+    };
+
+    let slots = (moved.len() + 1) as f64;
+    let lower = insert_at.checked_sub(1).map(|i| rest[i].1);
+    let upper = rest.get(insert_at).map(|(_, position)| *position);
+    let (lower, upper) = match (lower, upper) {
+        (Some(lower), Some(upper)) => (lower, upper),
+        (Some(lower), None) => (lower, lower + slots),
+        (None, Some(upper)) => (upper - slots, upper),
+        (None, None) => (0.0, slots),
+    };
+
+    let step = (upper - lower) / slots;
+    if step >= MIN_POSITION_GAP {
+        return Ok(moved
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, lower + step * (i + 1) as f64))
+            .collect());
+    }
+
+    let order = rest[..insert_at]
+        .iter()
+        .map(|(id, _)| *id)
+        .chain(moved.iter().copied())
+        .chain(rest[insert_at..].iter().map(|(id, _)| *id));
+    Ok(order
+        .enumerate()
+        .map(|(i, id)| (id, (i + 1) as f64))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(count: u128) -> Vec<Uuid> {
+        (1..=count).map(Uuid::from_u128).collect()
+    }
+
+    // The ids of `items` after applying `updates`, by position
+    fn order_after(items: &[(Uuid, f64)], updates: &[(Uuid, f64)]) -> Vec<Uuid> {
+        let mut merged: Vec<(Uuid, f64)> = items
+            .iter()
+            .map(|(id, position)| {
+                let updated = updates.iter().find(|(u, _)| u == id);
+                (*id, updated.map_or(*position, |(_, p)| *p))
+            })
+            .collect();
+        merged.sort_by(|a, b| a.1.total_cmp(&b.1));
+        merged.into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn moves_into_the_gap_between_neighbours() {
+        let [a, b, c] = ids(3)[..] else {
+            unreachable!()
+        };
+        let items = [(a, 1.0), (b, 2.0), (c, 3.0)];
+
+        let updates = reorder(&items, &[c], Some(a)).unwrap();
+
+        assert_eq!(updates, vec![(c, 1.5)]);
+        assert_eq!(order_after(&items, &updates), vec![a, c, b]);
+    }
+
+    #[test]
+    fn spreads_several_moved_items_evenly_in_their_given_order() {
+        let [a, b, c, d] = ids(4)[..] else {
+            unreachable!()
+        };
+        let items = [(a, 1.0), (b, 2.0), (c, 3.0), (d, 4.0)];
+
+        let updates = reorder(&items, &[d, c], Some(a)).unwrap();
+
+        assert_eq!(updates.len(), 2);
+        assert_eq!(order_after(&items, &updates), vec![a, d, c, b]);
+        assert!((updates[0].1 - 4.0 / 3.0).abs() < 1e-12);
+        assert!((updates[1].1 - 5.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn moves_to_the_start_and_the_end() {
+        let [a, b, c] = ids(3)[..] else {
+            unreachable!()
+        };
+        let items = [(a, 1.0), (b, 2.0), (c, 3.0)];
+
+        let first = reorder(&items, &[c], None).unwrap();
+        assert_eq!(order_after(&items, &first), vec![c, a, b]);
+
+        let last = reorder(&items, &[a], Some(c)).unwrap();
+        assert_eq!(order_after(&items, &last), vec![b, c, a]);
+    }
+
+    #[test]
+    fn orders_by_position_not_by_input_order() {
+        let [a, b, c] = ids(3)[..] else {
+            unreachable!()
+        };
+        let items = [(c, 3.0), (a, 1.0), (b, 2.0)];
+
+        let updates = reorder(&items, &[a], Some(b)).unwrap();
+
+        assert_eq!(order_after(&items, &updates), vec![b, a, c]);
+    }
+
+    #[test]
+    fn numbers_a_list_with_nothing_else_in_it() {
+        let [a, b] = ids(2)[..] else { unreachable!() };
+        let items = [(a, 7.0), (b, 8.0)];
+
+        let updates = reorder(&items, &[b, a], None).unwrap();
+
+        assert_eq!(updates, vec![(b, 1.0), (a, 2.0)]);
+    }
+
+    #[test]
+    fn renumbers_everything_when_the_gap_is_exhausted() {
+        let [a, b, c] = ids(3)[..] else {
+            unreachable!()
+        };
+        let items = [(a, 1.0), (b, 1.0 + 1e-10), (c, 2.0)];
+
+        let updates = reorder(&items, &[c], Some(a)).unwrap();
+
+        assert_eq!(updates, vec![(a, 1.0), (c, 2.0), (b, 3.0)]);
+    }
+
+    #[test]
+    fn rejects_placing_an_item_after_itself() {
+        let [a, b] = ids(2)[..] else { unreachable!() };
+        let items = [(a, 1.0), (b, 2.0)];
+
+        assert_eq!(
+            reorder(&items, &[a], Some(a)),
+            Err("'after' must be an item of the list that is not being moved")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_requests() {
+        let [a, b, outsider] = ids(3)[..] else {
+            unreachable!()
+        };
+        let items = [(a, 1.0), (b, 2.0)];
+
+        assert_eq!(reorder(&items, &[], None), Err("No items to reorder"));
+        assert_eq!(
+            reorder(&items, &[a, a], None),
+            Err("An item is listed more than once")
+        );
+        assert_eq!(
+            reorder(&items, &[outsider], None),
+            Err("Every item must belong to the list being reordered")
+        );
+        assert_eq!(
+            reorder(&items, &[a], Some(outsider)),
+            Err("'after' must be an item of the list that is not being moved")
+        );
+    }
+}
@@ -0,0 +1,231 @@
+// Password policy and hashing (Argon2id, with bcrypt verification for older accounts)
+use argon2::{
+    Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use std::{collections::HashSet, sync::LazyLock};
+
+// Longer inputs only make hashing slower; nobody types this much
+const MAX_PASSWORD_LENGTH: usize = 1024;
+
+static COMMON_PASSWORDS: LazyLock<HashSet<String>> = LazyLock::new(|| {
+    include_str!("../data/common_passwords.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+});
+
+#[derive(Debug, Clone, Copy)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub reject_common: bool,
+}
+
+impl PasswordPolicy {
+    // Check a new password, returning every rule it breaks
+    pub fn validate(&self, password: &str, email: &str) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            problems.push(format!(
+                "Password must be at least {} characters long",
+                self.min_length
+            ));
+        }
+        if length > MAX_PASSWORD_LENGTH {
+            problems.push(format!(
+                "Password must be at most {} characters long",
+                MAX_PASSWORD_LENGTH
+            ));
+        }
+        if self.reject_common && COMMON_PASSWORDS.contains(&password.to_lowercase()) {
+            problems.push("Password is too common".to_string());
+        }
+        if password.eq_ignore_ascii_case(email) {
+            problems.push("Password must not be your email address".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+// Argon2 is deliberately slow, so hashing and verification run on the blocking pool
+// instead of stalling the async runtime
+pub async fn hash_password(password: &str) -> Result<String, String> {
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || hash_password_blocking(&password))
+        .await
+        .map_err(|e| format!("Password hashing task failed: {}", e))?
+}
+
+pub async fn verify_password(password: &str, stored_hash: &str) -> Result<bool, String> {
+    let (password, stored_hash) = (password.to_string(), stored_hash.to_string());
+    tokio::task::spawn_blocking(move || verify_password_blocking(&password, &stored_hash))
+        .await
+        .map_err(|e| format!("Password verification task failed: {}", e))?
+}
+
+fn hash_password_blocking(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+fn verify_password_blocking(password: &str, stored_hash: &str) -> Result<bool, String> {
+    if is_bcrypt(stored_hash) {
+        return bcrypt::verify(password, stored_hash)
+            .map_err(|e| format!("Failed to verify password: {}", e));
+    }
+
+    let parsed =
+        PasswordHash::new(stored_hash).map_err(|e| format!("Invalid password hash: {}", e))?;
+
+    Ok(Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_ok())
+}
+
+// Whether a hash should be replaced after a successful login: bcrypt hashes and
+// Argon2 hashes made with different parameters are upgraded transparently
+pub fn needs_rehash(stored_hash: &str) -> bool {
+    if is_bcrypt(stored_hash) {
+        return true;
+    }
+
+    match PasswordHash::new(stored_hash) {
+        Ok(parsed) => {
+            // Parsed params carry the output length while the defaults leave it unset,
+            // so only the cost parameters are compared
+            let defaults = Params::default();
+            parsed.algorithm != argon2::ARGON2ID_IDENT
+                || Params::try_from(&parsed).ok().is_none_or(|params| {
+                    (params.m_cost(), params.t_cost(), params.p_cost())
+                        != (defaults.m_cost(), defaults.t_cost(), defaults.p_cost())
+                })
+        }
+        Err(_) => true,
+    }
+}
+
+fn is_bcrypt(stored_hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| stored_hash.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: PasswordPolicy = PasswordPolicy {
+        min_length: 8,
+        reject_common: true,
+    };
+
+    #[test]
+    fn accepts_a_reasonable_password() {
+        assert!(
+            POLICY
+                .validate("correct horse battery", "ada@example.edu")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn enforces_length_in_characters() {
+        assert_eq!(
+            POLICY.validate("x7#kQ2", "ada@example.edu"),
+            Err(vec![
+                "Password must be at least 8 characters long".to_string()
+            ])
+        );
+        // Eight characters, but more than eight bytes
+        assert!(POLICY.validate("ünïcødé!", "ada@example.edu").is_ok());
+
+        let too_long = "a1".repeat(MAX_PASSWORD_LENGTH);
+        assert_eq!(
+            POLICY.validate(&too_long, "ada@example.edu"),
+            Err(vec![format!(
+                "Password must be at most {} characters long",
+                MAX_PASSWORD_LENGTH
+            )])
+        );
+    }
+
+    #[test]
+    fn rejects_common_passwords_case_insensitively() {
+        for password in ["password", "PassWord", "00000000"] {
+            assert_eq!(
+                POLICY.validate(password, "ada@example.edu"),
+                Err(vec!["Password is too common".to_string()])
+            );
+        }
+
+        let lenient = PasswordPolicy {
+            reject_common: false,
+            ..POLICY
+        };
+        assert!(lenient.validate("password", "ada@example.edu").is_ok());
+    }
+
+    #[test]
+    fn rejects_the_email_address() {
+        assert_eq!(
+            POLICY.validate("Ada@Example.edu", "ada@example.edu"),
+            Err(vec!["Password must not be your email address".to_string()])
+        );
+    }
+
+    #[test]
+    fn reports_every_broken_rule() {
+        let problems = POLICY.validate("a@b.c", "a@b.c").unwrap_err();
+        assert_eq!(problems.len(), 2);
+
+        let strict = PasswordPolicy {
+            min_length: 10,
+            ..POLICY
+        };
+        assert_eq!(
+            strict
+                .validate("password", "ada@example.edu")
+                .unwrap_err()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn hashes_and_verifies() {
+        let hash = hash_password("correct horse battery").await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(!needs_rehash(&hash));
+        assert!(
+            verify_password("correct horse battery", &hash)
+                .await
+                .unwrap()
+        );
+        assert!(!verify_password("wrong horse battery", &hash).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn verifies_and_upgrades_bcrypt_hashes() {
+        let hash = bcrypt::hash("correct horse battery", 4).unwrap();
+        assert!(needs_rehash(&hash));
+        assert!(
+            verify_password("correct horse battery", &hash)
+                .await
+                .unwrap()
+        );
+        assert!(!verify_password("wrong horse battery", &hash).await.unwrap());
+    }
+}
@@ -0,0 +1,144 @@
+// PDF text stored per page at upload, so search and chat never re-extract it
+use crate::{cleanup::is_managed_upload, state::AppState};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// PDFs indexed per query by the background task
+const INDEX_BATCH_SIZE: i64 = 20;
+
+// Text of a stored PDF's pages, extracted off the async runtime
+pub async fn extract_pages(pdf_path: &str) -> Result<Vec<String>, String> {
+    if !is_managed_upload(pdf_path) {
+        return Err("PDF is not a stored upload".to_string());
+    }
+
+    let path = pdf_path.to_string();
+    let pages = tokio::task::spawn_blocking(move || crate::metadata::extract_pdf_pages(&path))
+        .await
+        .map_err(|e| format!("PDF extraction task failed: {}", e))??;
+
+    // Postgres text cannot hold NUL characters
+    Ok(pages.into_iter().map(|p| p.replace('\0', "")).collect())
+}
+
+// Replace the document's indexed text with pages from `extract_pages`. A PDF that cannot be
+// read now will not be readable later either, so a failed extraction is stored without pages.
+pub async fn store_extracted(
+    db: &PgPool,
+    document_id: Uuid,
+    extracted: &Result<Vec<String>, String>,
+) -> Result<(), sqlx::Error> {
+    let pages = extracted.as_deref().unwrap_or_default();
+    store_pages(db, document_id, pages).await
+}
+
+// Extract a stored PDF's pages and replace the document's indexed text
+pub async fn index_pdf(
+    db: &PgPool,
+    document_id: Uuid,
+    pdf_path: &str,
+) -> Result<Vec<String>, String> {
+    let extracted = extract_pages(pdf_path).await;
+    store_extracted(db, document_id, &extracted)
+        .await
+        .map_err(|e| format!("Failed to store PDF text: {}", e))?;
+
+    extracted
+}
+
+// Index PDFs stored before text was kept, or whose indexing failed, a batch at a time;
+// returns how many were attempted
+pub async fn index_unindexed_pdfs(db: &PgPool) -> Result<usize, sqlx::Error> {
+    let documents = sqlx::query!(
+        r#"
+        SELECT id, stored_file_path AS "stored_file_path!" FROM documents
+        WHERE stored_file_path IS NOT NULL AND pages_indexed_at IS NULL
+        ORDER BY created_at
+        LIMIT $1
+        "#,
+        INDEX_BATCH_SIZE
+    )
+    .fetch_all(db)
+    .await?;
+
+    for document in &documents {
+        let extracted = extract_pages(&document.stored_file_path).await;
+        if let Err(e) = &extracted {
+            eprintln!("PDF text indexing of {} failed: {}", document.id, e);
+        }
+        store_extracted(db, document.id, &extracted).await?;
+    }
+
+    Ok(documents.len())
+}
+
+// Work through the PDFs left unindexed once at startup; new uploads are indexed as they arrive
+pub fn spawn_indexing_task(state: AppState) {
+    tokio::spawn(async move {
+        loop {
+            match index_unindexed_pdfs(&state.db).await {
+                Ok(0) => break,
+                Ok(count) => println!("Indexed the text of {} PDF(s)", count),
+                Err(e) => {
+                    eprintln!("PDF text indexing pass failed: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+}
+
+async fn store_pages(db: &PgPool, document_id: Uuid, pages: &[String]) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query!(
+        "DELETE FROM document_pages WHERE document_id = $1",
+        document_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Blank pages are skipped but keep their numbering
+    sqlx::query!(
+        r#"
+        INSERT INTO document_pages (document_id, page_number, content)
+        SELECT $1, page.number::INTEGER, page.content
+        FROM unnest($2::TEXT[]) WITH ORDINALITY AS page(content, number)
+        WHERE btrim(page.content) <> ''
+        "#,
+        document_id,
+        pages
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE documents SET pages_indexed_at = NOW() WHERE id = $1",
+        document_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+}
+
+// The document's full stored text, or None if it has not been indexed. PDFs without a
+// text layer were indexed but have no pages, so they come back empty.
+pub async fn load_text(db: &PgPool, document_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+    let document = sqlx::query!(
+        r#"
+        SELECT d.pages_indexed_at IS NOT NULL AS "indexed!",
+            (SELECT string_agg(p.content, E'\n' ORDER BY p.page_number)
+             FROM document_pages p WHERE p.document_id = d.id) AS text
+        FROM documents d
+        WHERE d.id = $1
+        "#,
+        document_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(document
+        .filter(|d| d.indexed)
+        .map(|d| d.text.unwrap_or_default()))
+}
@@ -0,0 +1,724 @@
+// Advanced search query language, e.g.
+//   author:smith year:2018..2022 type:journal-article "graph neural" -survey
+// Terms are ANDed unless joined with OR; `-term` or `NOT term` excludes; parentheses group.
+
+use std::{collections::HashSet, sync::LazyLock};
+
+// Guard against pathological queries
+const MAX_TOKENS: usize = 64;
+
+const HAS_VALUES: &str = "pdf, abstract, doi, keywords";
+
+static STOP_WORDS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    include_str!("../data/english_stop_words.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+});
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchExpr {
+    And(Vec<SearchExpr>),
+    Or(Vec<SearchExpr>),
+    Not(Box<SearchExpr>),
+    Text(TextTerm),
+    Field(FieldFilter),
+}
+
+// Free text matched against the full-text index
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextTerm {
+    pub text: String,
+    pub phrase: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldFilter {
+    Author(String),
+    Title(TextTerm),
+    Abstract(TextTerm),
+    // Extracted PDF text
+    Content(TextTerm),
+    Keyword(String),
+    Journal(String),
+    PublicationType(String),
+    Doi(String),
+    Year { from: Option<i32>, to: Option<i32> },
+    Has(Presence),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Presence {
+    Pdf,
+    Abstract,
+    Doi,
+    Keywords,
+}
+
+// Position is a character offset into the query, for pointing at the problem
+#[derive(Debug)]
+pub struct QueryError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl QueryError {
+    fn new(message: impl Into<String>, position: usize) -> Self {
+        QueryError {
+            message: message.into(),
+            position,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    Field { name: String, value: TextTerm },
+    LParen,
+    RParen,
+    Not,
+    And,
+    Or,
+}
+
+fn is_word_boundary(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '(' | ')' | '"')
+}
+
+// Read a quoted string starting at the opening quote; returns the contents and the index after it
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize), QueryError> {
+    let mut end = start + 1;
+    while end < chars.len() && chars[end] != '"' {
+        end += 1;
+    }
+    if end == chars.len() {
+        return Err(QueryError::new("Unterminated quote", start));
+    }
+
+    let text: String = chars[start + 1..end].iter().collect();
+    if text.trim().is_empty() {
+        return Err(QueryError::new("Empty quoted phrase", start));
+    }
+
+    Ok((text.trim().to_string(), end + 1))
+}
+
+fn is_field_name(name: &str) -> bool {
+    matches!(
+        name,
+        "author"
+            | "authors"
+            | "title"
+            | "abstract"
+            | "content"
+            | "fulltext"
+            | "keyword"
+            | "keywords"
+            | "tag"
+            | "journal"
+            | "venue"
+            | "type"
+            | "doi"
+            | "year"
+            | "has"
+    )
+}
+
+// Read a field's value: a quoted phrase or the rest of the word. None when there is no
+// value, so `title:` alone is searched as text.
+fn read_field_value(chars: &[char], start: usize) -> Result<Option<(TextTerm, usize)>, QueryError> {
+    if start < chars.len() && chars[start] == '"' {
+        let (text, next) = read_quoted(chars, start)?;
+        return Ok(Some((TextTerm { text, phrase: true }, next)));
+    }
+
+    let mut end = start;
+    while end < chars.len() && !is_word_boundary(chars[end]) {
+        end += 1;
+    }
+    if end == start {
+        return Ok(None);
+    }
+
+    let text = chars[start..end].iter().collect();
+    Ok(Some((
+        TextTerm {
+            text,
+            phrase: false,
+        },
+        end,
+    )))
+}
+
+fn tokenize(query: &str) -> Result<Vec<(Token, usize)>, QueryError> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let token = match c {
+            '(' => {
+                i += 1;
+                Token::LParen
+            }
+            ')' => {
+                i += 1;
+                Token::RParen
+            }
+            '-' => {
+                i += 1;
+                if i == chars.len() || chars[i].is_whitespace() || chars[i] == ')' {
+                    return Err(QueryError::new("Nothing to exclude after '-'", start));
+                }
+                Token::Not
+            }
+            '"' => {
+                let (text, next) = read_quoted(&chars, i)?;
+                i = next;
+                Token::Phrase(text)
+            }
+            _ => {
+                while i < chars.len() && !is_word_boundary(chars[i]) && chars[i] != ':' {
+                    i += 1;
+                }
+                let name = chars[start..i].iter().collect::<String>().to_lowercase();
+
+                let field = if i < chars.len() && chars[i] == ':' && is_field_name(&name) {
+                    read_field_value(&chars, i + 1)?
+                } else {
+                    None
+                };
+
+                match field {
+                    Some((value, next)) => {
+                        i = next;
+                        Token::Field { name, value }
+                    }
+                    None => {
+                        // Not a field filter (re:invent, "Attention:"): the colon is part of the word
+                        while i < chars.len() && !is_word_boundary(chars[i]) {
+                            i += 1;
+                        }
+                        let word: String = chars[start..i].iter().collect();
+                        match word.as_str() {
+                            "OR" => Token::Or,
+                            "AND" => Token::And,
+                            "NOT" => Token::Not,
+                            _ => Token::Word(word),
+                        }
+                    }
+                }
+            }
+        };
+
+        tokens.push((token, start));
+        if tokens.len() > MAX_TOKENS {
+            return Err(QueryError::new(
+                format!("Search query is too long (at most {} terms)", MAX_TOKENS),
+                start,
+            ));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn parse_year(value: &str, position: usize) -> Result<FieldFilter, QueryError> {
+    let year = |s: &str| -> Result<Option<i32>, QueryError> {
+        if s.is_empty() {
+            return Ok(None);
+        }
+        s.parse::<i32>().map(Some).map_err(|_| {
+            QueryError::new(
+                format!(
+                    "Invalid year '{}'; use e.g. year:2020 or year:2018..2022",
+                    s
+                ),
+                position,
+            )
+        })
+    };
+
+    let (from, to) = match value.split_once("..") {
+        Some((from, to)) => (year(from)?, year(to)?),
+        None => {
+            let y = year(value)?;
+            (y, y)
+        }
+    };
+
+    if from.is_none() && to.is_none() {
+        return Err(QueryError::new(
+            "Year range needs at least one bound",
+            position,
+        ));
+    }
+    if let (Some(from), Some(to)) = (from, to)
+        && from > to
+    {
+        return Err(QueryError::new(
+            format!("Year range {}..{} is reversed", from, to),
+            position,
+        ));
+    }
+
+    Ok(FieldFilter::Year { from, to })
+}
+
+fn field_filter(name: &str, value: TextTerm, position: usize) -> Result<FieldFilter, QueryError> {
+    let filter = match name {
+        "author" | "authors" => FieldFilter::Author(value.text),
+        "title" => FieldFilter::Title(value),
+        "abstract" => FieldFilter::Abstract(value),
+        "content" | "fulltext" => FieldFilter::Content(value),
+        "keyword" | "keywords" | "tag" => FieldFilter::Keyword(value.text),
+        "journal" | "venue" => FieldFilter::Journal(value.text),
+        "type" => FieldFilter::PublicationType(value.text),
+        "doi" => FieldFilter::Doi(value.text),
+        "year" => parse_year(&value.text, position)?,
+        "has" => FieldFilter::Has(match value.text.to_lowercase().as_str() {
+            "pdf" => Presence::Pdf,
+            "abstract" => Presence::Abstract,
+            "doi" => Presence::Doi,
+            "keywords" | "keyword" => Presence::Keywords,
+            other => {
+                return Err(QueryError::new(
+                    format!(
+                        "Unknown value 'has:{}'; expected one of {}",
+                        other, HAS_VALUES
+                    ),
+                    position,
+                ));
+            }
+        }),
+        _ => unreachable!("the tokenizer only emits known fields"),
+    };
+
+    Ok(filter)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.index)
+            .map(|(_, position)| *position)
+            .unwrap_or(self.end)
+    }
+
+    fn next(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<SearchExpr, QueryError> {
+        let mut items = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            items.push(self.parse_and()?);
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            SearchExpr::Or(items)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<SearchExpr, QueryError> {
+        let mut items = vec![self.parse_unary()?];
+        loop {
+            match self.peek() {
+                None | Some(Token::RParen) | Some(Token::Or) => break,
+                Some(Token::And) => {
+                    self.next();
+                }
+                _ => {}
+            }
+            items.push(self.parse_unary()?);
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            SearchExpr::And(items)
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<SearchExpr, QueryError> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(SearchExpr::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<SearchExpr, QueryError> {
+        let position = self.position();
+        match self.next() {
+            Some((Token::LParen, _)) => {
+                let inner = self.parse_or()?;
+                match self.next() {
+                    Some((Token::RParen, _)) => Ok(inner),
+                    _ => Err(QueryError::new("Missing closing parenthesis", position)),
+                }
+            }
+            Some((Token::Word(text), _)) => Ok(SearchExpr::Text(TextTerm {
+                text,
+                phrase: false,
+            })),
+            Some((Token::Phrase(text), _)) => Ok(SearchExpr::Text(TextTerm { text, phrase: true })),
+            Some((Token::Field { name, value }, _)) => {
+                Ok(SearchExpr::Field(field_filter(&name, value, position)?))
+            }
+            Some((Token::RParen, _)) => Err(QueryError::new("Unexpected ')'", position)),
+            Some((Token::Or, _)) | Some((Token::And, _)) => Err(QueryError::new(
+                "AND/OR must appear between two terms",
+                position,
+            )),
+            Some((Token::Not, _)) => Err(QueryError::new("Expected a term to exclude", position)),
+            None => Err(QueryError::new("Unexpected end of query", position)),
+        }
+    }
+}
+
+pub fn parse(query: &str) -> Result<SearchExpr, QueryError> {
+    let tokens = tokenize(query)?;
+    if tokens.is_empty() {
+        return Err(QueryError::new("Search query is empty", 0));
+    }
+
+    let mut parser = Parser {
+        tokens,
+        index: 0,
+        end: query.chars().count(),
+    };
+    let expr = parser.parse_or()?;
+
+    if parser.peek().is_some() {
+        return Err(QueryError::new("Unexpected ')'", parser.position()));
+    }
+
+    expr.without_stop_words().ok_or_else(|| {
+        QueryError::new(
+            "Search query only contains words too common to search for",
+            0,
+        )
+    })
+}
+
+// Whether the full-text index would ignore every word of `term`
+fn is_stop_words_only(term: &TextTerm) -> bool {
+    term.text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .all(|word| STOP_WORDS.contains(word.to_lowercase().as_str()))
+}
+
+impl SearchExpr {
+    // Drop text terms made only of stop words. Their tsquery is empty and would match
+    // everything, or nothing at all once negated. None when nothing is left.
+    fn without_stop_words(self) -> Option<SearchExpr> {
+        match self {
+            SearchExpr::And(items) => Self::join_without_stop_words(items, SearchExpr::And),
+            SearchExpr::Or(items) => Self::join_without_stop_words(items, SearchExpr::Or),
+            SearchExpr::Not(inner) => inner
+                .without_stop_words()
+                .map(|inner| SearchExpr::Not(Box::new(inner))),
+            SearchExpr::Text(term)
+            | SearchExpr::Field(
+                FieldFilter::Title(term) | FieldFilter::Abstract(term) | FieldFilter::Content(term),
+            ) if is_stop_words_only(&term) => None,
+            other => Some(other),
+        }
+    }
+
+    fn join_without_stop_words(
+        items: Vec<SearchExpr>,
+        join: fn(Vec<SearchExpr>) -> SearchExpr,
+    ) -> Option<SearchExpr> {
+        let mut items: Vec<SearchExpr> = items
+            .into_iter()
+            .filter_map(Self::without_stop_words)
+            .collect();
+        match items.len() {
+            0 => None,
+            1 => items.pop(),
+            _ => Some(join(items)),
+        }
+    }
+
+    // Free-text and content terms that contribute to a match, i.e. not under a negation
+    pub fn positive_text_terms(&self) -> Vec<&TextTerm> {
+        match self {
+            SearchExpr::And(items) | SearchExpr::Or(items) => {
+                items.iter().flat_map(|e| e.positive_text_terms()).collect()
+            }
+            SearchExpr::Text(term) | SearchExpr::Field(FieldFilter::Content(term)) => vec![term],
+            SearchExpr::Not(_) | SearchExpr::Field(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str) -> SearchExpr {
+        SearchExpr::Text(TextTerm {
+            text: text.to_string(),
+            phrase: false,
+        })
+    }
+
+    fn phrase(text: &str) -> SearchExpr {
+        SearchExpr::Text(TextTerm {
+            text: text.to_string(),
+            phrase: true,
+        })
+    }
+
+    fn not(expr: SearchExpr) -> SearchExpr {
+        SearchExpr::Not(Box::new(expr))
+    }
+
+    fn error(query: &str) -> (String, usize) {
+        let e = parse(query).unwrap_err();
+        (e.message, e.position)
+    }
+
+    #[test]
+    fn terms_are_anded_by_default() {
+        assert_eq!(parse("graph").unwrap(), word("graph"));
+        assert_eq!(
+            parse("graph neural AND networks").unwrap(),
+            SearchExpr::And(vec![word("graph"), word("neural"), word("networks")])
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("graph neural OR transformer attention").unwrap(),
+            SearchExpr::Or(vec![
+                SearchExpr::And(vec![word("graph"), word("neural")]),
+                SearchExpr::And(vec![word("transformer"), word("attention")]),
+            ])
+        );
+    }
+
+    #[test]
+    fn parentheses_group() {
+        assert_eq!(
+            parse("graph (neural OR transformer)").unwrap(),
+            SearchExpr::And(vec![
+                word("graph"),
+                SearchExpr::Or(vec![word("neural"), word("transformer")]),
+            ])
+        );
+        assert_eq!(parse("((graph))").unwrap(), word("graph"));
+    }
+
+    #[test]
+    fn operators_are_case_sensitive() {
+        // Lowercase "or" is an ordinary word, and a stop word at that
+        assert_eq!(
+            parse("cats or dogs").unwrap(),
+            SearchExpr::And(vec![word("cats"), word("dogs")])
+        );
+    }
+
+    #[test]
+    fn quotes_make_phrases() {
+        assert_eq!(
+            parse("\"graph neural\" survey").unwrap(),
+            SearchExpr::And(vec![phrase("graph neural"), word("survey")])
+        );
+        assert_eq!(parse("\"  padded  \"").unwrap(), phrase("padded"));
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(
+            parse("graph -survey").unwrap(),
+            SearchExpr::And(vec![word("graph"), not(word("survey"))])
+        );
+        assert_eq!(
+            parse("NOT (survey OR review)").unwrap(),
+            not(SearchExpr::Or(vec![word("survey"), word("review")]))
+        );
+        assert_eq!(parse("--graph").unwrap(), not(not(word("graph"))));
+        assert_eq!(
+            parse("-\"deep learning\"").unwrap(),
+            not(phrase("deep learning"))
+        );
+        // A hyphen inside a word is not a negation
+        assert_eq!(parse("self-attention").unwrap(), word("self-attention"));
+    }
+
+    #[test]
+    fn field_filters() {
+        assert_eq!(
+            parse("author:smith title:\"graph networks\" Type:journal-article").unwrap(),
+            SearchExpr::And(vec![
+                SearchExpr::Field(FieldFilter::Author("smith".to_string())),
+                SearchExpr::Field(FieldFilter::Title(TextTerm {
+                    text: "graph networks".to_string(),
+                    phrase: true,
+                })),
+                SearchExpr::Field(FieldFilter::PublicationType("journal-article".to_string())),
+            ])
+        );
+        assert_eq!(
+            parse("venue:nature").unwrap(),
+            SearchExpr::Field(FieldFilter::Journal("nature".to_string()))
+        );
+        assert_eq!(
+            parse("has:PDF -has:abstract").unwrap(),
+            SearchExpr::And(vec![
+                SearchExpr::Field(FieldFilter::Has(Presence::Pdf)),
+                not(SearchExpr::Field(FieldFilter::Has(Presence::Abstract))),
+            ])
+        );
+        // Only the first colon separates the field
+        assert_eq!(
+            parse("doi:10.1000/a:b").unwrap(),
+            SearchExpr::Field(FieldFilter::Doi("10.1000/a:b".to_string()))
+        );
+    }
+
+    #[test]
+    fn year_ranges() {
+        let year = |from, to| SearchExpr::Field(FieldFilter::Year { from, to });
+        assert_eq!(parse("year:2020").unwrap(), year(Some(2020), Some(2020)));
+        assert_eq!(
+            parse("year:2018..2022").unwrap(),
+            year(Some(2018), Some(2022))
+        );
+        assert_eq!(parse("year:2018..").unwrap(), year(Some(2018), None));
+        assert_eq!(parse("year:..2022").unwrap(), year(None, Some(2022)));
+    }
+
+    #[test]
+    fn unknown_fields_and_missing_values_are_text() {
+        assert_eq!(parse("re:invent").unwrap(), word("re:invent"));
+        assert_eq!(
+            parse("Attention: all you need").unwrap(),
+            SearchExpr::And(vec![word("Attention:"), word("need")])
+        );
+        assert_eq!(
+            parse("title: graphs").unwrap(),
+            SearchExpr::And(vec![word("title:"), word("graphs")])
+        );
+        assert_eq!(
+            parse("http://example.com").unwrap(),
+            word("http://example.com")
+        );
+    }
+
+    #[test]
+    fn stop_word_terms_are_dropped() {
+        assert_eq!(parse("the graph -the").unwrap(), word("graph"));
+        assert_eq!(parse("graph -(of OR \"it is\")").unwrap(), word("graph"));
+        assert_eq!(parse("graph OR title:the").unwrap(), word("graph"));
+        // Only the words of a term all being stop words makes it droppable
+        assert_eq!(parse("\"the graph\"").unwrap(), phrase("the graph"));
+        assert_eq!(
+            error("the -a"),
+            (
+                "Search query only contains words too common to search for".to_string(),
+                0
+            )
+        );
+        assert!(parse("don't").is_err());
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(error(""), ("Search query is empty".to_string(), 0));
+        assert_eq!(error("   "), ("Search query is empty".to_string(), 0));
+        assert_eq!(
+            error("graph \"neural"),
+            ("Unterminated quote".to_string(), 6)
+        );
+        assert_eq!(error("\"  \""), ("Empty quoted phrase".to_string(), 0));
+        assert_eq!(
+            error("graph -"),
+            ("Nothing to exclude after '-'".to_string(), 6)
+        );
+        assert_eq!(
+            error("(graph neural"),
+            ("Missing closing parenthesis".to_string(), 0)
+        );
+        assert_eq!(error("graph)"), ("Unexpected ')'".to_string(), 5));
+        assert_eq!(
+            error("OR graph"),
+            ("AND/OR must appear between two terms".to_string(), 0)
+        );
+        assert_eq!(
+            error("graph OR"),
+            ("Unexpected end of query".to_string(), 8)
+        );
+        assert_eq!(
+            error("graph NOT"),
+            ("Unexpected end of query".to_string(), 9)
+        );
+        assert_eq!(
+            error("year:2022..2018"),
+            ("Year range 2022..2018 is reversed".to_string(), 0)
+        );
+        assert_eq!(
+            error("x year:soon"),
+            (
+                "Invalid year 'soon'; use e.g. year:2020 or year:2018..2022".to_string(),
+                2
+            )
+        );
+        assert_eq!(
+            error("year:.."),
+            ("Year range needs at least one bound".to_string(), 0)
+        );
+        assert!(
+            error("has:cheese")
+                .0
+                .starts_with("Unknown value 'has:cheese'")
+        );
+    }
+
+    #[test]
+    fn limits_the_number_of_terms() {
+        let query = vec!["graph"; MAX_TOKENS + 1].join(" ");
+        assert!(error(&query).0.starts_with("Search query is too long"));
+        assert!(parse(&vec!["graph"; MAX_TOKENS].join(" ")).is_ok());
+    }
+
+    #[test]
+    fn positive_terms_skip_negations_and_filters() {
+        let expr = parse("graph -survey content:molecules author:smith").unwrap();
+        let terms: Vec<&str> = expr
+            .positive_text_terms()
+            .iter()
+            .map(|t| t.text.as_str())
+            .collect();
+        assert_eq!(terms, ["graph", "molecules"]);
+    }
+}
@@ -1,16 +1,51 @@
-use crate::{handlers, handlers::upload_pdf, state::AppState};
+use crate::{handlers, handlers::upload_pdf, rate_limit, state::AppState};
 use axum::{
     Router,
+    middleware::from_fn_with_state,
     routing::{delete, get, post, put},
 };
 
 pub fn create_routes(state: AppState) -> Router {
+    let auth_limit = from_fn_with_state(state.auth_limiter.clone(), rate_limit::limit);
+    let upload_limit = from_fn_with_state(state.upload_limiter.clone(), rate_limit::limit);
+    let chat_limit = from_fn_with_state(state.chat_limiter.clone(), rate_limit::limit);
+    let public_limit = from_fn_with_state(state.public_limiter.clone(), rate_limit::limit);
+
     Router::new()
         .route("/health", get(handlers::health_check))
-        .route("/api/auth/register", post(handlers::register_user))
-        .route("/api/auth/login", post(handlers::login_user))
+        .route(
+            "/api/auth/register",
+            post(handlers::register_user).layer(auth_limit.clone()),
+        )
+        .route(
+            "/api/auth/login",
+            post(handlers::login_user).layer(auth_limit.clone()),
+        )
+        .route(
+            "/api/auth/oidc/providers",
+            get(handlers::list_oidc_providers),
+        )
+        .route("/api/auth/oidc/{provider}/login", get(handlers::oidc_login))
+        .route(
+            "/api/auth/oidc/{provider}/link",
+            post(handlers::link_oidc_identity),
+        )
+        .route(
+            "/api/auth/oidc/{provider}/callback",
+            post(handlers::oidc_callback).layer(auth_limit.clone()),
+        )
         .route("/api/user/me", get(handlers::get_current_user))
+        .route("/api/user/me", delete(handlers::request_account_deletion))
+        .route(
+            "/api/user/me/cancel-deletion",
+            post(handlers::cancel_account_deletion),
+        )
+        .route("/api/user/export", get(handlers::export_user_data))
         .route("/api/user/profile", put(handlers::update_profile))
+        .route(
+            "/api/user/password",
+            put(handlers::change_password).layer(auth_limit),
+        )
         .route(
             "/api/user/profile-image",
             post(handlers::upload_profile_image),
@@ -21,12 +56,75 @@ pub fn create_routes(state: AppState) -> Router {
         )
         .route("/api/collections", get(handlers::get_user_collections))
         .route("/api/collections", post(handlers::create_collection))
+        .route("/api/collections/tree", get(handlers::get_collection_tree))
+        .route("/api/collections/order", put(handlers::reorder_collections))
         .route("/api/collections/{id}", put(handlers::update_collection))
         .route("/api/collections/{id}", delete(handlers::delete_collection))
+        .route(
+            "/api/collections/shared-with-me",
+            get(handlers::get_shared_collections),
+        )
+        .route(
+            "/api/collections/{collection_id}/shares",
+            get(handlers::list_collection_shares),
+        )
+        .route(
+            "/api/collections/{collection_id}/shares",
+            post(handlers::share_collection),
+        )
+        .route(
+            "/api/collections/{collection_id}/links",
+            get(handlers::list_collection_links),
+        )
+        .route(
+            "/api/collections/{collection_id}/links",
+            post(handlers::create_collection_link),
+        )
+        .route(
+            "/api/collections/{collection_id}/links/{link_id}",
+            delete(handlers::delete_collection_link),
+        )
+        .route(
+            "/api/public/collections/{token}",
+            get(handlers::get_public_collection).layer(public_limit.clone()),
+        )
+        .route(
+            "/api/public/collections/{token}/bibliography",
+            get(handlers::get_public_bibliography).layer(public_limit.clone()),
+        )
+        .route(
+            "/api/public/collections/{token}/documents/{document_id}/pdf",
+            get(handlers::download_public_pdf).layer(public_limit),
+        )
+        .route(
+            "/api/shares/invitations/{token}",
+            get(handlers::get_share_invitation),
+        )
+        .route(
+            "/api/shares/invitations/{token}/accept",
+            post(handlers::accept_share),
+        )
+        .route("/api/shares/{share_id}", delete(handlers::delete_share))
+        .route(
+            "/api/collections/{collection_id}/duplicate",
+            post(handlers::duplicate_collection),
+        )
+        .route(
+            "/api/collections/{collection_id}/path",
+            get(handlers::get_collection_path),
+        )
         .route(
             "/api/collections/{collection_id}/documents",
             get(handlers::get_collection_documents),
         )
+        .route(
+            "/api/collections/{collection_id}/documents/order",
+            put(handlers::reorder_collection_documents),
+        )
+        .route(
+            "/api/collections/{collection_id}/export",
+            get(handlers::export_collection),
+        )
         .route(
             "/api/collections/{collection_id}/documents/{document_id}",
             post(handlers::add_document_to_collection),
@@ -35,16 +133,143 @@ pub fn create_routes(state: AppState) -> Router {
             "/api/collections/{collection_id}/documents/{document_id}",
             delete(handlers::remove_document_from_collection),
         )
+        .route("/api/groups", get(handlers::get_user_groups))
+        .route("/api/groups", post(handlers::create_group))
+        .route("/api/groups/{group_id}", put(handlers::update_group))
+        .route("/api/groups/{group_id}", delete(handlers::delete_group))
+        .route(
+            "/api/groups/{group_id}/members",
+            get(handlers::get_group_members),
+        )
+        .route(
+            "/api/groups/{group_id}/members",
+            post(handlers::add_group_member),
+        )
+        .route(
+            "/api/groups/{group_id}/members/{member_id}",
+            put(handlers::update_group_member),
+        )
+        .route(
+            "/api/groups/{group_id}/members/{member_id}",
+            delete(handlers::remove_group_member),
+        )
+        .route(
+            "/api/groups/{group_id}/documents",
+            get(handlers::get_group_documents),
+        )
+        .route(
+            "/api/groups/{group_id}/documents",
+            post(handlers::create_group_document),
+        )
+        .route(
+            "/api/groups/{group_id}/documents/upload",
+            post(handlers::upload_group_pdf).layer(upload_limit.clone()),
+        )
+        .route(
+            "/api/groups/{group_id}/documents/search",
+            get(handlers::search_group_documents),
+        )
+        .route(
+            "/api/groups/{group_id}/documents/semantic-search",
+            get(handlers::semantic_search_group_documents),
+        )
+        .route(
+            "/api/groups/{group_id}/documents/facets",
+            get(handlers::group_document_facets),
+        )
+        .route(
+            "/api/groups/{group_id}/documents/bulk",
+            post(handlers::bulk_update_group_documents),
+        )
+        .route(
+            "/api/groups/{group_id}/suggest",
+            get(handlers::suggest_in_group),
+        )
+        .route(
+            "/api/groups/{group_id}/collections",
+            get(handlers::get_group_collections),
+        )
+        .route(
+            "/api/groups/{group_id}/collections",
+            post(handlers::create_group_collection),
+        )
+        .route(
+            "/api/groups/{group_id}/collections/tree",
+            get(handlers::get_group_collection_tree),
+        )
+        .route(
+            "/api/groups/{group_id}/trash",
+            get(handlers::get_group_trash),
+        )
+        .route(
+            "/api/groups/{group_id}/trash",
+            delete(handlers::empty_group_trash),
+        )
+        .route("/api/trash", get(handlers::get_trash))
+        .route("/api/trash", delete(handlers::empty_trash))
+        .route(
+            "/api/trash/documents/{id}/restore",
+            post(handlers::restore_trashed_document),
+        )
+        .route(
+            "/api/trash/documents/{id}",
+            delete(handlers::purge_trashed_document),
+        )
+        .route(
+            "/api/trash/collections/{id}/restore",
+            post(handlers::restore_trashed_collection),
+        )
+        .route(
+            "/api/trash/collections/{id}",
+            delete(handlers::purge_trashed_collection),
+        )
         .route("/api/documents", post(handlers::create_document))
         .route("/api/documents", get(handlers::get_user_documents))
         .route("/api/documents/search", get(handlers::search_documents))
-        .route("/api/documents/upload", post(upload_pdf))
+        .route("/api/documents/facets", get(handlers::document_facets))
+        .route("/api/documents/bulk", post(handlers::bulk_update_documents))
+        .route("/api/suggest", get(handlers::suggest))
+        .route(
+            "/api/documents/semantic-search",
+            get(handlers::semantic_search),
+        )
+        .route(
+            "/api/documents/upload",
+            post(upload_pdf).layer(upload_limit),
+        )
         .route("/api/documents/{id}", get(handlers::get_document))
         .route("/api/documents/{id}", put(handlers::update_document))
         .route("/api/documents/{id}", delete(handlers::delete_document))
+        .route(
+            "/api/documents/{id}/similar",
+            get(handlers::get_similar_documents),
+        )
         .route(
             "/api/documents/{id}/chat",
-            post(handlers::chat_with_document),
+            post(handlers::chat_with_document).layer(chat_limit),
+        )
+        .route("/api/admin/users", get(handlers::admin_list_users))
+        .route(
+            "/api/admin/users/{id}/status",
+            put(handlers::admin_set_user_status),
+        )
+        .route(
+            "/api/admin/users/{id}/role",
+            put(handlers::admin_set_user_role),
+        )
+        .route(
+            "/api/admin/users/{id}/quota",
+            put(handlers::admin_set_user_quota),
+        )
+        .route(
+            "/api/admin/users/{id}/quota",
+            delete(handlers::admin_reset_user_quota),
+        )
+        .route(
+            "/api/admin/users/{id}/impersonate",
+            post(handlers::admin_impersonate_user),
         )
+        .route("/api/admin/storage", get(handlers::admin_storage_usage))
+        .route("/api/admin/audit-log", get(handlers::admin_audit_log))
         .with_state(state)
 }
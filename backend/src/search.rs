@@ -0,0 +1,792 @@
+// Ranked full-text document search, with trigram matching as a typo-tolerant fallback
+use crate::{
+    document_query::DOCUMENT_COLUMNS,
+    embeddings::{sql_literal, vector_literal},
+    groups::Library,
+    models::Document,
+    query_parser::{FieldFilter, Presence, SearchExpr, TextTerm},
+};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+pub const DEFAULT_SEARCH_LIMIT: i64 = 50;
+pub const MAX_SEARCH_LIMIT: i64 = 200;
+
+// Best pages shown per result
+const PAGE_MATCHES_PER_DOCUMENT: i64 = 3;
+
+// A hit in the PDF text counts for less than the same hit in the metadata
+const CONTENT_RANK_WEIGHT: f32 = 0.5;
+
+// Minimum word_similarity for a fuzzy match
+const TRIGRAM_THRESHOLD: f32 = 0.3;
+
+// Snippets come from the abstract, or the title when there is none. ts_headline does not
+// escape the text around matches, so it marks them with private-use characters and
+// `highlight` turns the escaped result into HTML.
+const HEADLINE_OPTIONS: &str =
+    "StartSel=\u{E000}, StopSel=\u{E001}, MaxWords=35, MinWords=15, MaxFragments=2";
+const MATCH_START: char = '\u{E000}';
+const MATCH_STOP: char = '\u{E001}';
+
+// Escape a ts_headline snippet and wrap its matches in <mark>
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_STOP => html.push_str("</mark>"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+// Turn the raw snippets of search results into safe HTML
+pub fn highlight_snippets(results: &mut [SearchResult]) {
+    for result in results {
+        result.snippet = result.snippet.as_deref().map(highlight);
+    }
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub document: Document,
+    pub rank: f32,
+    pub snippet: Option<String>,
+    // True when the result came from the trigram fallback
+    pub fuzzy: bool,
+    #[sqlx(skip)]
+    pub page_matches: Vec<PageMatch>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct PageMatch {
+    #[serde(skip)]
+    pub document_id: Uuid,
+    pub page_number: i32,
+    pub snippet: String,
+}
+
+// SQL for the best trigram similarity of `text` against any searchable field of `d`
+fn similarity(text: &str) -> String {
+    format!(
+        "GREATEST( \
+            word_similarity({t}, d.title), \
+            word_similarity({t}, COALESCE(d.abstract_text, '')), \
+            word_similarity({t}, COALESCE(d.journal, '')), \
+            (SELECT MAX(word_similarity({t}, a)) FROM unnest(d.authors) AS a), \
+            (SELECT MAX(word_similarity({t}, k)) FROM unnest(d.keywords) AS k) \
+        )",
+        t = text
+    )
+}
+
+fn tsquery_function(term: &TextTerm) -> &'static str {
+    if term.phrase {
+        "phraseto_tsquery"
+    } else {
+        "plainto_tsquery"
+    }
+}
+
+// Case-insensitive substring pattern for ILIKE
+fn contains_pattern(value: &str) -> String {
+    format!("%{}%", escape_like(value))
+}
+
+// Escape LIKE wildcards so user input matches literally
+pub fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// OR of every ranking term as one tsquery, or NULL when there are none
+fn push_rank_query(qb: &mut QueryBuilder<'static, Postgres>, terms: &[&TextTerm]) {
+    if terms.is_empty() {
+        qb.push("NULL::tsquery");
+        return;
+    }
+    qb.push("(");
+    for (i, term) in terms.iter().enumerate() {
+        if i > 0 {
+            qb.push(" || ");
+        }
+        qb.push(tsquery_function(term))
+            .push("('english', ")
+            .push_bind(term.text.clone())
+            .push(")");
+    }
+    qb.push(")");
+}
+
+// Append a boolean condition on `d` for `expr`. Free text uses the full-text index,
+// or trigram similarity when `fuzzy`; field filters are the same in both modes.
+fn push_condition(qb: &mut QueryBuilder<'static, Postgres>, expr: &SearchExpr, fuzzy: bool) {
+    match expr {
+        SearchExpr::And(items) | SearchExpr::Or(items) => {
+            let joiner = if matches!(expr, SearchExpr::And(_)) {
+                " AND "
+            } else {
+                " OR "
+            };
+            qb.push("(");
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    qb.push(joiner);
+                }
+                push_condition(qb, item, fuzzy);
+            }
+            qb.push(")");
+        }
+        SearchExpr::Not(inner) => {
+            // Missing values count as not matching, so they survive the negation
+            qb.push("NOT COALESCE(");
+            push_condition(qb, inner, fuzzy);
+            qb.push(", FALSE)");
+        }
+        SearchExpr::Text(term) if fuzzy => {
+            qb.push("(SELECT ")
+                .push(similarity("s.t"))
+                .push(" FROM (SELECT ")
+                .push_bind(term.text.clone())
+                .push(format!("::TEXT AS t) s) > {}", TRIGRAM_THRESHOLD));
+        }
+        SearchExpr::Text(term) => {
+            // The parser drops terms made only of stop words, so tq is never empty
+            qb.push(
+                "(SELECT d.search_vector @@ tq OR EXISTS ( \
+                    SELECT 1 FROM document_pages p \
+                    WHERE p.document_id = d.id AND p.search_vector @@ tq) \
+                FROM ",
+            )
+            .push(tsquery_function(term))
+            .push("('english', ")
+            .push_bind(term.text.clone())
+            .push(") AS tq)");
+        }
+        SearchExpr::Field(filter) => push_field_condition(qb, filter),
+    }
+}
+
+fn push_field_condition(qb: &mut QueryBuilder<'static, Postgres>, filter: &FieldFilter) {
+    match filter {
+        FieldFilter::Author(name) => {
+            qb.push("EXISTS (SELECT 1 FROM unnest(d.authors) AS a WHERE a ILIKE ")
+                .push_bind(contains_pattern(name))
+                .push(")");
+        }
+        FieldFilter::Title(term) => {
+            qb.push("to_tsvector('english', d.title) @@ ")
+                .push(tsquery_function(term))
+                .push("('english', ")
+                .push_bind(term.text.clone())
+                .push(")");
+        }
+        FieldFilter::Abstract(term) => {
+            qb.push("to_tsvector('english', COALESCE(d.abstract_text, '')) @@ ")
+                .push(tsquery_function(term))
+                .push("('english', ")
+                .push_bind(term.text.clone())
+                .push(")");
+        }
+        FieldFilter::Content(term) => {
+            qb.push(
+                "EXISTS (SELECT 1 FROM document_pages p \
+                WHERE p.document_id = d.id AND p.search_vector @@ ",
+            )
+            .push(tsquery_function(term))
+            .push("('english', ")
+            .push_bind(term.text.clone())
+            .push("))");
+        }
+        FieldFilter::Keyword(keyword) => {
+            qb.push("EXISTS (SELECT 1 FROM unnest(d.keywords) AS k WHERE LOWER(k) = LOWER(")
+                .push_bind(keyword.clone())
+                .push("))");
+        }
+        FieldFilter::Journal(journal) => {
+            qb.push("d.journal ILIKE ")
+                .push_bind(contains_pattern(journal));
+        }
+        FieldFilter::PublicationType(publication_type) => {
+            qb.push("LOWER(d.publication_type) = LOWER(")
+                .push_bind(publication_type.clone())
+                .push(")");
+        }
+        FieldFilter::Doi(doi) => {
+            qb.push("LOWER(d.doi) = LOWER(")
+                .push_bind(doi.clone())
+                .push(")");
+        }
+        FieldFilter::Year { from, to } => {
+            qb.push("(TRUE");
+            if let Some(from) = from {
+                qb.push(" AND d.year >= ").push_bind(*from);
+            }
+            if let Some(to) = to {
+                qb.push(" AND d.year <= ").push_bind(*to);
+            }
+            qb.push(")");
+        }
+        FieldFilter::Has(presence) => {
+            qb.push(match presence {
+                Presence::Pdf => "d.stored_file_path IS NOT NULL",
+                Presence::Abstract => "COALESCE(d.abstract_text, '') <> ''",
+                Presence::Doi => "COALESCE(d.doi, '') <> ''",
+                Presence::Keywords => "COALESCE(cardinality(d.keywords), 0) > 0",
+            });
+        }
+    }
+}
+
+// Collections a search is limited to; smart collections contribute their query's matches
+#[derive(Debug, Default)]
+pub struct SearchScope {
+    pub collection_ids: Vec<Uuid>,
+    pub smart_queries: Vec<SearchExpr>,
+}
+
+// The collection and, if asked, all its descendants; None if the library has no such collection
+pub async fn resolve_scope(
+    db: &PgPool,
+    library: Library,
+    collection_id: Uuid,
+    include_subcollections: bool,
+) -> Result<Option<SearchScope>, sqlx::Error> {
+    let collections = sqlx::query!(
+        r#"
+        WITH RECURSIVE subtree AS (
+            SELECT id, query
+            FROM collections
+            WHERE id = $1 AND (user_id = $2 OR group_id = $4) AND deleted_at IS NULL
+            -- UNION rather than UNION ALL stops at a parent_id cycle
+            UNION
+            SELECT c.id, c.query
+            FROM collections c
+            INNER JOIN subtree s ON c.parent_id = s.id
+            WHERE $3 AND (c.user_id = $2 OR c.group_id = $4) AND c.deleted_at IS NULL
+        )
+        SELECT id AS "id!", query FROM subtree
+        "#,
+        collection_id,
+        library.user_id(),
+        include_subcollections,
+        library.group_id()
+    )
+    .fetch_all(db)
+    .await?;
+
+    if collections.is_empty() {
+        return Ok(None);
+    }
+
+    let mut scope = SearchScope::default();
+    for collection in collections {
+        match collection.query {
+            Some(query) => match crate::query_parser::parse(&query) {
+                Ok(expr) => scope.smart_queries.push(expr),
+                Err(e) => eprintln!(
+                    "Skipping smart collection {} with invalid query: {}",
+                    collection.id, e.message
+                ),
+            },
+            None => scope.collection_ids.push(collection.id),
+        }
+    }
+
+    Ok(Some(scope))
+}
+
+// Append a condition (and " AND ") limiting `d` to the scope's documents
+fn push_scope(qb: &mut QueryBuilder<'static, Postgres>, scope: Option<&SearchScope>) {
+    let Some(scope) = scope else {
+        return;
+    };
+
+    qb.push(
+        "(EXISTS (SELECT 1 FROM document_collections dc \
+        WHERE dc.document_id = d.id AND dc.collection_id = ANY(",
+    )
+    .push_bind(scope.collection_ids.clone())
+    .push("))");
+    for expr in &scope.smart_queries {
+        qb.push(" OR ");
+        push_condition(qb, expr, false);
+    }
+    qb.push(") AND ");
+}
+
+// Start a query with the CTEs `input` (ranking tsquery) and `matches` (matching documents
+// of the library with `rank` and `fuzzy` columns); the caller continues with its own SELECT.
+// Full-text matches are ranked by cover density (ts_rank scores negated terms as zero).
+// Only when nothing matches in full is free text retried by trigram similarity; without
+// `fuzzy_fallback` only exact matches count, as for smart collection membership.
+pub fn matches_query(
+    library: Library,
+    expr: &SearchExpr,
+    fuzzy_fallback: bool,
+    scope: Option<&SearchScope>,
+) -> QueryBuilder<'static, Postgres> {
+    let terms = expr.positive_text_terms();
+
+    let mut qb = QueryBuilder::new("WITH input AS (SELECT ");
+    push_rank_query(&mut qb, &terms);
+    let raw = terms
+        .iter()
+        .map(|t| t.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    qb.push(" AS query, ")
+        .push_bind(raw)
+        .push("::TEXT AS raw), ");
+
+    qb.push(format!(
+        "strict AS ( \
+            SELECT d.*, FALSE AS fuzzy, \
+                GREATEST( \
+                    COALESCE(ts_rank_cd(d.search_vector, input.query, 32), 0), \
+                    COALESCE(( \
+                        SELECT MAX(ts_rank_cd(p.search_vector, input.query, 32)) \
+                        FROM document_pages p WHERE p.document_id = d.id \
+                    ), 0) * {content_weight} \
+                ) AS rank \
+            FROM documents d, input \
+            WHERE ",
+        content_weight = CONTENT_RANK_WEIGHT
+    ));
+    library.push_condition(&mut qb, "d");
+    qb.push(" AND d.deleted_at IS NULL AND ");
+    push_scope(&mut qb, scope);
+    push_condition(&mut qb, expr, false);
+
+    qb.push(format!(
+        "), fuzzy AS ( \
+            SELECT d.*, TRUE AS fuzzy, {} AS rank \
+            FROM documents d, input \
+            WHERE ",
+        similarity("input.raw")
+    ));
+    library.push_condition(&mut qb, "d");
+    qb.push(" AND d.deleted_at IS NULL AND NOT EXISTS (SELECT 1 FROM strict) AND ");
+    push_scope(&mut qb, scope);
+    if terms.is_empty() || !fuzzy_fallback {
+        // Without free text there is nothing to be typo-tolerant about
+        qb.push("FALSE");
+    } else {
+        push_condition(&mut qb, expr, true);
+    }
+
+    qb.push(format!(
+        "), matches AS ( \
+            SELECT * FROM strict \
+            UNION ALL \
+            SELECT * FROM fuzzy WHERE fuzzy.rank > {} \
+        ) ",
+        TRIGRAM_THRESHOLD
+    ));
+
+    qb
+}
+
+// (collection_id, document_id) rows for the documents of the library matching each smart
+// collection's query, from one pass over the library. Exact matches only, as in `matches_query`
+// without `fuzzy_fallback`.
+pub fn smart_memberships_query(
+    library: Library,
+    collections: &[(Uuid, SearchExpr)],
+) -> QueryBuilder<'static, Postgres> {
+    let mut qb = QueryBuilder::new(
+        "SELECT m.collection_id, d.id AS document_id FROM documents d CROSS JOIN LATERAL (",
+    );
+    for (i, (collection_id, expr)) in collections.iter().enumerate() {
+        if i > 0 {
+            qb.push(" UNION ALL ");
+        }
+        qb.push("SELECT ")
+            .push_bind(*collection_id)
+            .push("::UUID AS collection_id WHERE ");
+        push_condition(&mut qb, expr, false);
+    }
+    qb.push(") m WHERE ");
+    library.push_condition(&mut qb, "d");
+    qb.push(" AND d.deleted_at IS NULL");
+
+    qb
+}
+
+pub fn search_documents_query(
+    library: Library,
+    expr: &SearchExpr,
+    scope: Option<&SearchScope>,
+    limit: i64,
+) -> QueryBuilder<'static, Postgres> {
+    let mut qb = matches_query(library, expr, true, scope);
+    qb.push(format!(
+        "SELECT {columns}, d.rank::REAL AS rank, \
+            ts_headline('english', COALESCE(d.abstract_text, d.title), input.query, '{options}') AS snippet, \
+            d.fuzzy \
+        FROM matches d, input \
+        ORDER BY d.rank DESC, d.created_at DESC \
+        LIMIT ",
+        columns = DOCUMENT_COLUMNS,
+        options = HEADLINE_OPTIONS,
+    ));
+    qb.push_bind(limit.clamp(1, MAX_SEARCH_LIMIT));
+
+    qb
+}
+
+// Documents of a smart collection: every exact match of its query, newest first
+pub fn smart_collection_query(
+    library: Library,
+    expr: &SearchExpr,
+) -> QueryBuilder<'static, Postgres> {
+    let mut qb = matches_query(library, expr, false, None);
+    qb.push(format!(
+        "SELECT {} FROM matches d ORDER BY d.created_at DESC",
+        DOCUMENT_COLUMNS
+    ));
+    qb
+}
+
+// Which of the smart collections contain one document, without listing their others; the
+// collection id is the first column. `collections` must not be empty.
+pub fn smart_collections_containing_query(
+    library: Library,
+    collections: &[(Uuid, SearchExpr)],
+    document_id: Uuid,
+) -> QueryBuilder<'static, Postgres> {
+    let mut qb = smart_memberships_query(library, collections);
+    qb.push(" AND d.id = ").push_bind(document_id);
+    qb
+}
+
+// Fill in the best matching PDF pages, with snippets, for each result
+pub async fn attach_page_matches(
+    db: &PgPool,
+    expr: &SearchExpr,
+    results: &mut [SearchResult],
+) -> Result<(), sqlx::Error> {
+    let terms = expr.positive_text_terms();
+    if terms.is_empty() || results.is_empty() {
+        return Ok(());
+    }
+
+    let document_ids: Vec<Uuid> = results.iter().map(|r| r.document.id).collect();
+
+    let mut qb = QueryBuilder::new("WITH input AS (SELECT ");
+    push_rank_query(&mut qb, &terms);
+    qb.push(format!(
+        " AS query) \
+        SELECT m.document_id, m.page_number, \
+            ts_headline('english', m.content, input.query, '{options}') AS snippet \
+        FROM ( \
+            SELECT p.document_id, p.page_number, p.content, \
+                ROW_NUMBER() OVER ( \
+                    PARTITION BY p.document_id \
+                    ORDER BY ts_rank_cd(p.search_vector, input.query, 32) DESC, p.page_number \
+                ) AS position \
+            FROM document_pages p, input \
+            WHERE p.search_vector @@ input.query AND p.document_id = ANY(",
+        options = HEADLINE_OPTIONS
+    ));
+    qb.push_bind(document_ids)
+        .push(")) m, input WHERE m.position <= ")
+        .push_bind(PAGE_MATCHES_PER_DOCUMENT)
+        .push(" ORDER BY m.document_id, m.position");
+
+    let matches: Vec<PageMatch> = qb.build_query_as().fetch_all(db).await?;
+
+    for mut page_match in matches {
+        page_match.snippet = highlight(&page_match.snippet);
+        if let Some(result) = results
+            .iter_mut()
+            .find(|r| r.document.id == page_match.document_id)
+        {
+            result.page_matches.push(page_match);
+        }
+    }
+
+    Ok(())
+}
+
+// Passages returned per semantic search result
+const SEMANTIC_PASSAGES_PER_DOCUMENT: i64 = 3;
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct SemanticResult {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub document: Document,
+    // Cosine similarity of the best matching chunk
+    pub score: f32,
+    #[sqlx(skip)]
+    pub passages: Vec<Passage>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Passage {
+    #[serde(skip)]
+    pub document_id: Uuid,
+    // "metadata" for title and abstract, "page" for PDF text
+    pub source: String,
+    pub page_number: Option<i32>,
+    pub content: String,
+    pub score: f32,
+}
+
+// Chunks fetched from the vector index per requested result, since one document can own several
+// of the nearest chunks; also the index's search breadth (hnsw.ef_search), capped by pgvector
+const SEMANTIC_CANDIDATES_PER_RESULT: i64 = 10;
+const MAX_SEMANTIC_CANDIDATES: i64 = 1000;
+
+// Libraries with at most this many chunks of the model are searched exactly instead of by index
+const EXACT_SEMANTIC_SCAN_MAX_CHUNKS: i64 = 20_000;
+
+// Documents of the library ranked by their closest chunk to `query_vector`, with the best passages
+// of each. Runtime SQL as the vector type is not in the query cache.
+//
+// The HNSW index covers every library, and its nearest chunks are only then filtered to this one,
+// so on a shared instance a plain index scan can return few or none of the library's own chunks.
+// Small libraries are therefore scanned exactly, restricted to their chunks first. Larger ones
+// walk the index from `ensure_vector_index` (same cast and model literal, so the planner uses it)
+// with iterative scans, which keep searching until enough rows pass the filter (pgvector 0.8+).
+pub async fn semantic_search(
+    db: &PgPool,
+    library: Library,
+    model: &str,
+    query_vector: &[f32],
+    limit: i64,
+) -> Result<Vec<SemanticResult>, sqlx::Error> {
+    let limit = limit.clamp(1, MAX_SEARCH_LIMIT);
+    let candidates = (limit * SEMANTIC_CANDIDATES_PER_RESULT).min(MAX_SEMANTIC_CANDIDATES);
+    let distance = format!(
+        "c.embedding::vector({dimensions}) <=> $1::TEXT::vector({dimensions})",
+        dimensions = query_vector.len()
+    );
+    let query_vector = vector_literal(query_vector);
+
+    let library_chunks = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM ( \
+            SELECT 1 FROM document_chunks c \
+            INNER JOIN documents d ON d.id = c.document_id \
+            WHERE c.model = $1 AND (d.user_id = $2 OR d.group_id = $3) AND d.deleted_at IS NULL \
+            LIMIT $4 \
+        ) library_chunks",
+    )
+    .bind(model)
+    .bind(library.user_id())
+    .bind(library.group_id())
+    .bind(EXACT_SEMANTIC_SCAN_MAX_CHUNKS + 1)
+    .fetch_one(db)
+    .await?;
+
+    // MATERIALIZED keeps the planner from pushing the ordering into the shared index
+    let nearest = if library_chunks <= EXACT_SEMANTIC_SCAN_MAX_CHUNKS {
+        format!(
+            "library_chunks AS MATERIALIZED ( \
+                SELECT c.document_id, c.embedding \
+                FROM document_chunks c \
+                INNER JOIN documents d ON d.id = c.document_id \
+                WHERE c.model = {model} AND (d.user_id = $2 OR d.group_id = $5) \
+                    AND d.deleted_at IS NULL \
+            ), \
+            nearest AS ( \
+                SELECT c.document_id, {distance} AS distance \
+                FROM library_chunks c \
+                ORDER BY {distance} \
+                LIMIT $3 \
+            )",
+            model = sql_literal(model)
+        )
+    } else {
+        format!(
+            "nearest AS MATERIALIZED ( \
+                SELECT c.document_id, {distance} AS distance \
+                FROM document_chunks c \
+                WHERE c.model = {model} \
+                    AND EXISTS ( \
+                        SELECT 1 FROM documents d \
+                        WHERE d.id = c.document_id AND (d.user_id = $2 OR d.group_id = $5) \
+                            AND d.deleted_at IS NULL \
+                    ) \
+                ORDER BY {distance} \
+                LIMIT $3 \
+            )",
+            model = sql_literal(model)
+        )
+    };
+
+    let sql = format!(
+        "WITH {nearest}, \
+        best AS ( \
+            SELECT document_id, MIN(distance) AS distance FROM nearest GROUP BY document_id \
+        ) \
+        SELECT {columns}, (1 - b.distance)::REAL AS score \
+        FROM best b \
+        INNER JOIN documents d ON d.id = b.document_id \
+        ORDER BY b.distance, d.created_at DESC \
+        LIMIT $4",
+        columns = DOCUMENT_COLUMNS
+    );
+
+    // SET cannot take bind parameters; the value is a bounded integer
+    let mut tx = db.begin().await?;
+    sqlx::query(&format!("SET LOCAL hnsw.ef_search = {}", candidates))
+        .execute(&mut *tx)
+        .await?;
+    // Older pgvector has no iterative scans and rejects the setting
+    let iterative_scans = sqlx::query_scalar::<_, bool>(
+        "SELECT COALESCE(( \
+            SELECT string_to_array(extversion, '.')::INTEGER[] >= ARRAY[0, 8] \
+            FROM pg_extension WHERE extname = 'vector' \
+        ), FALSE)",
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if iterative_scans {
+        // Results are re-sorted by distance below, so the relaxed order is fine
+        sqlx::query("SET LOCAL hnsw.iterative_scan = relaxed_order")
+            .execute(&mut *tx)
+            .await?;
+    }
+    let mut results = sqlx::query_as::<_, SemanticResult>(&sql)
+        .bind(&query_vector)
+        .bind(library.user_id())
+        .bind(candidates)
+        .bind(limit)
+        .bind(library.group_id())
+        .fetch_all(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    if results.is_empty() {
+        return Ok(results);
+    }
+
+    let document_ids: Vec<Uuid> = results.iter().map(|r| r.document.id).collect();
+    let passages = sqlx::query_as::<_, Passage>(
+        "SELECT p.document_id, p.source, p.page_number, p.content, p.score \
+        FROM ( \
+            SELECT c.document_id, c.source::TEXT AS source, c.page_number, c.content, \
+                (1 - (c.embedding <=> $1::TEXT::vector))::REAL AS score, \
+                ROW_NUMBER() OVER ( \
+                    PARTITION BY c.document_id \
+                    ORDER BY c.embedding <=> $1::TEXT::vector \
+                ) AS position \
+            FROM document_chunks c \
+            WHERE c.document_id = ANY($2) AND c.model = $3 \
+        ) p \
+        WHERE p.position <= $4 \
+        ORDER BY p.document_id, p.position",
+    )
+    .bind(&query_vector)
+    .bind(&document_ids)
+    .bind(model)
+    .bind(SEMANTIC_PASSAGES_PER_DOCUMENT)
+    .fetch_all(db)
+    .await?;
+
+    for passage in passages {
+        if let Some(result) = results
+            .iter_mut()
+            .find(|r| r.document.id == passage.document_id)
+        {
+            result.passages.push(passage);
+        }
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Inserts a user whose library holds one document with `chunks` chunks at `embedding`
+    async fn seed_library(db: &PgPool, model: &str, chunks: i32, embedding: &str) -> (Uuid, Uuid) {
+        let user_id = sqlx::query_scalar::<_, Uuid>(
+            "INSERT INTO users (email) VALUES (gen_random_uuid() || '@example.com') RETURNING id",
+        )
+        .fetch_one(db)
+        .await
+        .unwrap();
+        let document_id = sqlx::query_scalar::<_, Uuid>(
+            "INSERT INTO documents (user_id, title) VALUES ($1, 'Seeded') RETURNING id",
+        )
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO document_chunks (document_id, chunk_index, source, content, model, embedding) \
+            SELECT $1, i, 'page', 'Seeded', $2, $3::TEXT::vector FROM generate_series(0, $4 - 1) AS i",
+        )
+        .bind(document_id)
+        .bind(model)
+        .bind(embedding)
+        .bind(chunks)
+        .execute(db)
+        .await
+        .unwrap();
+        (user_id, document_id)
+    }
+
+    // A large library whose chunks are all further from the query than a small library's must
+    // still find its own documents through the shared index, and the small one only its own
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing to a migrated database with pgvector"]
+    async fn semantic_search_stays_within_each_library() {
+        let db = PgPool::connect(&std::env::var("DATABASE_URL").unwrap())
+            .await
+            .unwrap();
+        crate::embeddings::ensure_chunk_table(&db).await.unwrap();
+        let model = format!("test-{}", Uuid::new_v4());
+
+        let (near_user, near_document) = seed_library(&db, &model, 500, "[1,0,0]").await;
+        let (far_user, far_document) = seed_library(
+            &db,
+            &model,
+            EXACT_SEMANTIC_SCAN_MAX_CHUNKS as i32 + 1,
+            "[0,1,0]",
+        )
+        .await;
+        crate::embeddings::ensure_vector_index(&db, &model)
+            .await
+            .unwrap();
+
+        let near =
+            semantic_search(&db, Library::User(near_user), &model, &[1.0, 0.0, 0.0], 5).await;
+        let far = semantic_search(&db, Library::User(far_user), &model, &[1.0, 0.0, 0.0], 5).await;
+
+        sqlx::query("DELETE FROM users WHERE id = ANY($1)")
+            .bind(vec![near_user, far_user])
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let near: Vec<Uuid> = near.unwrap().iter().map(|r| r.document.id).collect();
+        let far: Vec<Uuid> = far.unwrap().iter().map(|r| r.document.id).collect();
+        assert_eq!(near, vec![near_document]);
+        assert_eq!(far, vec![far_document]);
+    }
+
+    #[test]
+    fn highlight_escapes_text_and_marks_matches() {
+        assert_eq!(
+            highlight("<img src=x onerror=\"alert('x')\"> and \u{E000}graphs\u{E001} & more"),
+            "&lt;img src=x onerror=&quot;alert(&#39;x&#39;)&quot;&gt; and <mark>graphs</mark> &amp; more"
+        );
+    }
+}
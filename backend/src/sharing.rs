@@ -0,0 +1,153 @@
+// Access to collections and documents of other users' or groups' libraries
+use crate::{
+    groups::{GroupRole, Library},
+    query_parser::{SearchExpr, parse},
+    search::smart_collections_containing_query,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// Ordered from least to most privileged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Access {
+    fn from_share(can_edit: bool) -> Self {
+        if can_edit {
+            Access::Editor
+        } else {
+            Access::Viewer
+        }
+    }
+
+    // Every member but a reader has full control of a group's documents and collections
+    fn from_group_role(role: Option<String>) -> Option<Self> {
+        match GroupRole::parse(role.as_deref()?)? {
+            GroupRole::Reader => Some(Access::Viewer),
+            _ => Some(Access::Owner),
+        }
+    }
+}
+
+// The user's access to a collection: their own, their group's, or shared directly or
+// through an ancestor
+pub async fn collection_access(
+    db: &PgPool,
+    user_id: Uuid,
+    collection_id: Uuid,
+) -> Result<Option<Access>, sqlx::Error> {
+    let access = sqlx::query!(
+        r#"
+        SELECT c.user_id = $2 AS owner,
+            (SELECT gm.role FROM group_members gm
+                WHERE gm.group_id = c.group_id AND gm.user_id = $2) AS group_role,
+            (SELECT s.can_edit FROM shared_collections($2) s WHERE s.collection_id = c.id) AS can_edit
+        FROM collections c
+        WHERE c.id = $1 AND c.deleted_at IS NULL
+        "#,
+        collection_id,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(access.and_then(|a| {
+        if a.owner == Some(true) {
+            Some(Access::Owner)
+        } else {
+            Access::from_group_role(a.group_role).max(a.can_edit.map(Access::from_share))
+        }
+    }))
+}
+
+// The user's access to a document: their own, their group's, or filed in (or matched by)
+// a shared collection
+pub async fn document_access(
+    db: &PgPool,
+    user_id: Uuid,
+    document_id: Uuid,
+) -> Result<Option<Access>, sqlx::Error> {
+    let Some(document) = sqlx::query!(
+        r#"
+        SELECT d.user_id AS owner_id,
+            (SELECT gm.role FROM group_members gm
+                WHERE gm.group_id = d.group_id AND gm.user_id = $2) AS group_role,
+            (SELECT bool_or(s.can_edit)
+                FROM shared_collections($2) s
+                INNER JOIN document_collections dc ON dc.collection_id = s.collection_id
+                WHERE dc.document_id = d.id) AS can_edit
+        FROM documents d
+        WHERE d.id = $1 AND d.deleted_at IS NULL
+        "#,
+        document_id,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    if document.owner_id == Some(user_id) {
+        return Ok(Some(Access::Owner));
+    }
+
+    let mut access =
+        Access::from_group_role(document.group_role).max(document.can_edit.map(Access::from_share));
+    // Only personal collections are shared, so only personal documents can match a smart one
+    let Some(owner_id) = document.owner_id else {
+        return Ok(access);
+    };
+    if access >= Some(Access::Editor) {
+        return Ok(access);
+    }
+
+    // Shared smart collections list whichever of the owner's documents match their query
+    let smart = sqlx::query!(
+        r#"
+        SELECT s.collection_id AS "collection_id!", s.can_edit AS "can_edit!", c.query AS "query!"
+        FROM shared_collections($1) s
+        INNER JOIN collections c ON c.id = s.collection_id
+        WHERE c.query IS NOT NULL AND c.user_id = $2
+        "#,
+        user_id,
+        owner_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut editable = Vec::new();
+    let mut collections: Vec<(Uuid, SearchExpr)> = Vec::new();
+    for collection in smart {
+        // Only shares that would raise the access are worth checking
+        if access >= Some(Access::from_share(collection.can_edit)) {
+            continue;
+        }
+        // An invalid saved query matches nothing
+        let Ok(expr) = parse(&collection.query) else {
+            continue;
+        };
+        if collection.can_edit {
+            editable.push(collection.collection_id);
+        }
+        collections.push((collection.collection_id, expr));
+    }
+    if collections.is_empty() {
+        return Ok(access);
+    }
+
+    let matched: Vec<Uuid> =
+        smart_collections_containing_query(Library::User(owner_id), &collections, document_id)
+            .build_query_scalar()
+            .fetch_all(db)
+            .await?;
+    for collection_id in matched {
+        access = access.max(Some(Access::from_share(editable.contains(&collection_id))));
+    }
+
+    Ok(access)
+}
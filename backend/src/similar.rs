@@ -0,0 +1,125 @@
+// Related documents from shared authors, shared keywords, trigram text similarity and,
+// when embeddings are available, semantic similarity of title and abstract
+use crate::{document_query::DOCUMENT_COLUMNS, groups::Library, models::Document};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+pub const DEFAULT_SIMILAR_LIMIT: i64 = 10;
+pub const MAX_SIMILAR_LIMIT: i64 = 50;
+
+// Weights of each signal in the combined score; they sum to 1
+const AUTHOR_WEIGHT: f32 = 0.3;
+const KEYWORD_WEIGHT: f32 = 0.3;
+const TITLE_WEIGHT: f32 = 0.25;
+const ABSTRACT_WEIGHT: f32 = 0.15;
+
+// Share of the score taken by embedding similarity when both documents have embeddings
+const EMBEDDING_WEIGHT: f32 = 0.5;
+
+// Scores below this are noise rather than relatedness
+const MIN_SCORE: f32 = 0.05;
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct SimilarDocument {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub document: Document,
+    pub score: f32,
+    pub shared_authors: Vec<String>,
+    pub shared_keywords: Vec<String>,
+    pub title_similarity: f32,
+    pub abstract_similarity: f32,
+    // Cosine similarity of the title and abstract embeddings, if both documents have one
+    pub embedding_similarity: Option<f32>,
+}
+
+// Other documents of the library ranked by similarity to `document_id`, which must be in it.
+// `embedding_model` selects the embeddings to compare; None leaves them out.
+pub async fn similar_documents(
+    db: &PgPool,
+    library: Library,
+    document_id: Uuid,
+    embedding_model: Option<&str>,
+    limit: i64,
+) -> Result<Vec<SimilarDocument>, sqlx::Error> {
+    // Chunk 0 embeds the title and abstract; runtime SQL as the vector type is not in the query cache
+    let embedding_similarity = if embedding_model.is_some() {
+        "(SELECT 1 - (c.embedding <=> e.embedding) \
+            FROM document_chunks c, document_chunks e \
+            WHERE c.document_id = d.id AND c.chunk_index = 0 AND c.model = $5 \
+                AND e.document_id = s.id AND e.chunk_index = 0 AND e.model = $5)"
+    } else {
+        "NULL::REAL"
+    };
+
+    // Authors and keywords compare case-insensitively; overlap is Jaccard similarity.
+    // Shared authors keep one spelling per normalized name, so they count like author_count.
+    let sql = format!(
+        "WITH source AS ( \
+            SELECT id, title, abstract_text, \
+                ARRAY(SELECT DISTINCT LOWER(btrim(a)) FROM unnest(authors) AS a) AS authors, \
+                ARRAY(SELECT DISTINCT LOWER(btrim(k)) FROM unnest(keywords) AS k) AS keywords \
+            FROM documents WHERE id = $1 AND (user_id = $2 OR group_id = $3) AND deleted_at IS NULL \
+        ), \
+        signals AS ( \
+            SELECT d.*, s.authors AS source_authors, s.keywords AS source_keywords, \
+                ARRAY(SELECT DISTINCT ON (LOWER(btrim(a))) btrim(a) FROM unnest(d.authors) AS a \
+                    WHERE LOWER(btrim(a)) = ANY(s.authors) \
+                    ORDER BY LOWER(btrim(a)), btrim(a)) AS shared_authors, \
+                ARRAY(SELECT DISTINCT LOWER(btrim(k)) FROM unnest(d.keywords) AS k \
+                    WHERE LOWER(btrim(k)) = ANY(s.keywords)) AS shared_keywords, \
+                (SELECT COUNT(DISTINCT LOWER(btrim(a))) FROM unnest(d.authors) AS a) AS author_count, \
+                (SELECT COUNT(DISTINCT LOWER(btrim(k))) FROM unnest(d.keywords) AS k) AS keyword_count, \
+                similarity(d.title, s.title) AS title_similarity, \
+                CASE WHEN d.abstract_text IS NULL OR s.abstract_text IS NULL THEN 0 \
+                    ELSE similarity(d.abstract_text, s.abstract_text) END AS abstract_similarity, \
+                {embedding_similarity} AS embedding_similarity \
+            FROM documents d, source s \
+            WHERE (d.user_id = $2 OR d.group_id = $3) AND d.deleted_at IS NULL AND d.id <> s.id \
+        ), \
+        base AS ( \
+            SELECT d.*, \
+                ({author_weight} * COALESCE(cardinality(d.shared_authors)::REAL / NULLIF( \
+                    d.author_count + cardinality(d.source_authors) - cardinality(d.shared_authors), 0), 0) \
+                + {keyword_weight} * COALESCE(cardinality(d.shared_keywords)::REAL / NULLIF( \
+                    d.keyword_count + cardinality(d.source_keywords) - cardinality(d.shared_keywords), 0), 0) \
+                + {title_weight} * d.title_similarity \
+                + {abstract_weight} * d.abstract_similarity)::REAL AS base_score \
+            FROM signals d \
+        ), \
+        scored AS ( \
+            SELECT d.*, \
+                (CASE WHEN d.embedding_similarity IS NULL THEN d.base_score \
+                    ELSE (1 - {embedding_weight}) * d.base_score \
+                        + {embedding_weight} * d.embedding_similarity END)::REAL AS score \
+            FROM base d \
+        ) \
+        SELECT {columns}, d.score, d.shared_authors, d.shared_keywords, \
+            d.title_similarity::REAL AS title_similarity, \
+            d.abstract_similarity::REAL AS abstract_similarity, \
+            d.embedding_similarity::REAL AS embedding_similarity \
+        FROM scored d \
+        WHERE d.score >= {min_score} \
+        ORDER BY d.score DESC, d.created_at DESC \
+        LIMIT $4",
+        author_weight = AUTHOR_WEIGHT,
+        keyword_weight = KEYWORD_WEIGHT,
+        title_weight = TITLE_WEIGHT,
+        abstract_weight = ABSTRACT_WEIGHT,
+        embedding_weight = EMBEDDING_WEIGHT,
+        min_score = MIN_SCORE,
+        columns = DOCUMENT_COLUMNS,
+    );
+
+    let mut query = sqlx::query_as::<_, SimilarDocument>(&sql)
+        .bind(document_id)
+        .bind(library.user_id())
+        .bind(library.group_id())
+        .bind(limit.clamp(1, MAX_SIMILAR_LIMIT));
+    if let Some(model) = embedding_model {
+        query = query.bind(model);
+    }
+
+    query.fetch_all(db).await
+}
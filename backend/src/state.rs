@@ -1,12 +1,54 @@
+use crate::{
+    config::{Config, OidcProviderConfig},
+    embeddings::{EmbeddingProvider, provider_from_config},
+    oidc::OidcCache,
+    password::PasswordPolicy,
+    rate_limit::{LockoutConfig, RateLimiter},
+};
 use sqlx::PgPool;
+use std::sync::Arc;
+
 #[derive(Clone)]
 pub struct AppState {
     pub db: PgPool,
     pub jwt_secret: String,
+    pub oidc_providers: Vec<OidcProviderConfig>,
+    pub oidc_cache: OidcCache,
+    pub auth_limiter: RateLimiter,
+    pub upload_limiter: RateLimiter,
+    pub chat_limiter: RateLimiter,
+    pub public_limiter: RateLimiter,
+    pub login_lockout: LockoutConfig,
+    pub default_storage_quota_bytes: Option<i64>,
+    pub account_deletion_grace_days: i64,
+    // Trashed documents and collections are purged after this long
+    pub trash_retention_days: i32,
+    pub password_policy: PasswordPolicy,
+    // None when no embedding provider is configured; semantic search is then unavailable
+    pub embedder: Option<Arc<dyn EmbeddingProvider>>,
 }
 
 impl AppState {
-    pub fn new(db: PgPool, jwt_secret: String) -> Self {
-        Self { db, jwt_secret }
+    pub fn new(db: PgPool, config: Config) -> Self {
+        Self {
+            db,
+            jwt_secret: config.jwt_secret,
+            oidc_providers: config.oidc_providers,
+            oidc_cache: OidcCache::default(),
+            auth_limiter: RateLimiter::new(config.auth_rate_limit),
+            upload_limiter: RateLimiter::new(config.upload_rate_limit),
+            chat_limiter: RateLimiter::new(config.chat_rate_limit),
+            public_limiter: RateLimiter::new(config.public_rate_limit),
+            login_lockout: config.login_lockout,
+            default_storage_quota_bytes: config.default_storage_quota_bytes,
+            account_deletion_grace_days: config.account_deletion_grace_days,
+            trash_retention_days: config.trash_retention_days,
+            password_policy: config.password_policy,
+            embedder: config.embedding.as_ref().map(provider_from_config),
+        }
+    }
+
+    pub fn oidc_provider(&self, name: &str) -> Option<&OidcProviderConfig> {
+        self.oidc_providers.iter().find(|p| p.name == name)
     }
 }
@@ -0,0 +1,91 @@
+// Search-as-you-type completions for titles, authors, journals and keywords
+use crate::{groups::Library, search::escape_like};
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub const DEFAULT_SUGGEST_LIMIT: i64 = 10;
+pub const MAX_SUGGEST_LIMIT: i64 = 25;
+
+// Trigram indexes can't serve patterns with fewer than three characters, so shorter prefixes
+// would scan every document; they get no suggestions
+pub const MIN_SUGGEST_PREFIX_CHARS: usize = 3;
+
+#[derive(Debug, Serialize)]
+pub struct Suggestion {
+    // "title", "author", "journal" or "keyword"
+    pub kind: String,
+    pub value: String,
+    pub document_count: i64,
+    // Set for titles, which name a single document
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_id: Option<Uuid>,
+}
+
+// Values starting with `prefix` come first, then values with a word starting with it;
+// within each, the most common. The ILIKE patterns are served by the trigram indexes.
+pub async fn suggest(
+    db: &PgPool,
+    library: Library,
+    prefix: &str,
+    limit: i64,
+) -> Result<Vec<Suggestion>, sqlx::Error> {
+    let escaped = escape_like(prefix);
+    let starts_with = format!("{}%", escaped);
+    let word_starts_with = format!("% {}%", escaped);
+    let contains = format!("%{}%", escaped);
+
+    sqlx::query_as!(
+        Suggestion,
+        r#"
+        SELECT s.kind AS "kind!", s.value AS "value!", s.document_count AS "document_count!",
+            s.document_id
+        FROM (
+            (SELECT 'title' AS kind, d.title AS value, 1::BIGINT AS document_count,
+                d.id AS document_id, d.title ILIKE $2 AS at_start
+            FROM documents d
+            WHERE (d.user_id = $1 OR d.group_id = $6) AND d.deleted_at IS NULL AND (d.title ILIKE $2 OR d.title ILIKE $3)
+            ORDER BY at_start DESC, length(d.title)
+            LIMIT $5)
+            UNION ALL
+            (SELECT 'author' AS kind, MIN(btrim(a)) AS value, COUNT(DISTINCT d.id) AS document_count,
+                NULL::UUID AS document_id, bool_or(btrim(a) ILIKE $2) AS at_start
+            FROM documents d
+            CROSS JOIN LATERAL unnest(d.authors) AS a
+            WHERE (d.user_id = $1 OR d.group_id = $6) AND d.deleted_at IS NULL AND text_array_to_string(d.authors) ILIKE $4
+                AND (btrim(a) ILIKE $2 OR btrim(a) ILIKE $3)
+            GROUP BY LOWER(btrim(a))
+            ORDER BY at_start DESC, document_count DESC
+            LIMIT $5)
+            UNION ALL
+            (SELECT 'journal' AS kind, MIN(d.journal) AS value, COUNT(*) AS document_count,
+                NULL::UUID AS document_id, bool_or(d.journal ILIKE $2) AS at_start
+            FROM documents d
+            WHERE (d.user_id = $1 OR d.group_id = $6) AND d.deleted_at IS NULL AND (d.journal ILIKE $2 OR d.journal ILIKE $3)
+            GROUP BY LOWER(d.journal)
+            ORDER BY at_start DESC, document_count DESC
+            LIMIT $5)
+            UNION ALL
+            (SELECT 'keyword' AS kind, MIN(btrim(k)) AS value, COUNT(DISTINCT d.id) AS document_count,
+                NULL::UUID AS document_id, bool_or(btrim(k) ILIKE $2) AS at_start
+            FROM documents d
+            CROSS JOIN LATERAL unnest(d.keywords) AS k
+            WHERE (d.user_id = $1 OR d.group_id = $6) AND d.deleted_at IS NULL AND text_array_to_string(d.keywords) ILIKE $4
+                AND (btrim(k) ILIKE $2 OR btrim(k) ILIKE $3)
+            GROUP BY LOWER(btrim(k))
+            ORDER BY at_start DESC, document_count DESC
+            LIMIT $5)
+        ) s
+        ORDER BY s.at_start DESC, s.document_count DESC, length(s.value), s.value
+        LIMIT $5
+        "#,
+        library.user_id(),
+        starts_with,
+        word_starts_with,
+        contains,
+        limit.clamp(1, MAX_SUGGEST_LIMIT),
+        library.group_id()
+    )
+    .fetch_all(db)
+    .await
+}
@@ -0,0 +1,336 @@
+// Trash: listing, restoring and purging soft-deleted documents and collections
+use crate::{
+    cleanup::remove_unreferenced_files,
+    collection_delete::ItemRef,
+    collection_tree::lock_collection_tree,
+    document_query::DOCUMENT_COLUMNS,
+    groups::Library,
+    models::{TrashedCollection, TrashedDocument},
+};
+use serde::Serialize;
+use sqlx::{PgPool, QueryBuilder};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct TrashContents {
+    // Items are purged for good this many days after they were deleted
+    pub retention_days: i32,
+    pub documents: Vec<TrashedDocument>,
+    // Only the collections deleted directly; their subcollections come back with them
+    pub collections: Vec<TrashedCollection>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RestoreReport {
+    pub restored_collections: Vec<ItemRef>,
+    // Documents deleted along with the collections, now back in them
+    pub restored_documents: Vec<ItemRef>,
+    // Set when the collection's parent is still in the trash, so it was restored at the root
+    pub moved_to_root: bool,
+}
+
+pub async fn list_trash(
+    db: &PgPool,
+    library: Library,
+    retention_days: i32,
+) -> Result<TrashContents, sqlx::Error> {
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT {}, d.deleted_at FROM documents d WHERE d.deleted_at IS NOT NULL AND ",
+        DOCUMENT_COLUMNS
+    ));
+    library.push_condition(&mut qb, "d");
+    qb.push(" ORDER BY d.deleted_at DESC, d.title");
+    let documents = qb.build_query_as().fetch_all(db).await?;
+
+    let mut qb = QueryBuilder::new(
+        "SELECT c.id, c.user_id, c.group_id, c.name, c.parent_id, c.query, c.position, \
+            c.created_at, c.updated_at, c.deleted_at \
+        FROM collections c \
+        WHERE c.deleted_at IS NOT NULL \
+            AND NOT EXISTS (SELECT 1 FROM collections p \
+                WHERE p.id = c.parent_id AND p.deleted_at = c.deleted_at) \
+            AND ",
+    );
+    library.push_condition(&mut qb, "c");
+    qb.push(" ORDER BY c.deleted_at DESC, c.name");
+    let collections = qb.build_query_as().fetch_all(db).await?;
+
+    Ok(TrashContents {
+        retention_days,
+        documents,
+        collections,
+    })
+}
+
+// The library of a trashed document; None when it is not in the trash
+pub async fn trashed_document_library(
+    db: &PgPool,
+    document_id: Uuid,
+) -> Result<Option<Library>, sqlx::Error> {
+    let document = sqlx::query!(
+        "SELECT user_id, group_id FROM documents WHERE id = $1 AND deleted_at IS NOT NULL",
+        document_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(document.map(|d| Library::of(d.user_id, d.group_id)))
+}
+
+pub async fn trashed_collection_library(
+    db: &PgPool,
+    collection_id: Uuid,
+) -> Result<Option<Library>, sqlx::Error> {
+    let collection = sqlx::query!(
+        "SELECT user_id, group_id FROM collections WHERE id = $1 AND deleted_at IS NOT NULL",
+        collection_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(collection.map(|c| Library::of(c.user_id, c.group_id)))
+}
+
+// Memberships were kept, so the document reappears in its collections
+pub async fn restore_document(db: &PgPool, document_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE documents SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL",
+        document_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Restore a collection with the subcollections and documents deleted in the same operation.
+// None when it is not in the trash.
+pub async fn restore_collection(
+    db: &PgPool,
+    library: Library,
+    collection_id: Uuid,
+) -> Result<Option<RestoreReport>, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    lock_collection_tree(&mut tx, library).await?;
+
+    let Some(collection) = sqlx::query!(
+        r#"
+        SELECT c.deleted_at AS "deleted_at!",
+            (SELECT p.deleted_at IS NOT NULL FROM collections p WHERE p.id = c.parent_id)
+                AS parent_trashed
+        FROM collections c
+        WHERE c.id = $1 AND c.deleted_at IS NOT NULL
+        "#,
+        collection_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let restored_collections = sqlx::query_as!(
+        ItemRef,
+        r#"
+        WITH RECURSIVE batch AS (
+            SELECT id FROM collections WHERE id = $1
+            UNION
+            SELECT c.id
+            FROM collections c
+            INNER JOIN batch b ON c.parent_id = b.id
+            WHERE c.deleted_at = $2
+        )
+        UPDATE collections c SET deleted_at = NULL
+        FROM batch
+        WHERE c.id = batch.id
+        RETURNING c.id, c.name
+        "#,
+        collection_id,
+        collection.deleted_at
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let restored_ids: Vec<Uuid> = restored_collections.iter().map(|c| c.id).collect();
+
+    let moved_to_root = collection.parent_trashed == Some(true);
+    if moved_to_root {
+        sqlx::query!(
+            r#"
+            UPDATE collections
+            SET parent_id = NULL, position = (
+                SELECT COALESCE(MAX(s.position), 0) + 1
+                FROM collections s
+                WHERE (s.user_id = $2 OR s.group_id = $3) AND s.parent_id IS NULL
+                    AND s.deleted_at IS NULL
+            )
+            WHERE id = $1
+            "#,
+            collection_id,
+            library.user_id(),
+            library.group_id()
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let restored_documents = sqlx::query_as!(
+        ItemRef,
+        r#"
+        UPDATE documents d SET deleted_at = NULL
+        WHERE d.deleted_at = $1 AND (d.user_id = $3 OR d.group_id = $4)
+            AND EXISTS (
+                SELECT 1 FROM document_collections dc
+                WHERE dc.document_id = d.id AND dc.collection_id = ANY($2)
+            )
+        RETURNING d.id, d.title AS name
+        "#,
+        collection.deleted_at,
+        &restored_ids,
+        library.user_id(),
+        library.group_id()
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(RestoreReport {
+        restored_collections,
+        restored_documents,
+        moved_to_root,
+    }))
+}
+
+pub async fn purge_document(db: &PgPool, document_id: Uuid) -> Result<bool, sqlx::Error> {
+    let Some(stored_file) = sqlx::query_scalar!(
+        "DELETE FROM documents WHERE id = $1 AND deleted_at IS NOT NULL RETURNING stored_file_path",
+        document_id
+    )
+    .fetch_optional(db)
+    .await?
+    else {
+        return Ok(false);
+    };
+
+    remove_unreferenced_files(db, stored_file.as_slice()).await;
+    Ok(true)
+}
+
+// Permanently delete a trashed collection, its subcollections and the documents deleted with it
+pub async fn purge_collection(db: &PgPool, collection_id: Uuid) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let Some(deleted_at) = sqlx::query_scalar!(
+        "SELECT deleted_at FROM collections WHERE id = $1 AND deleted_at IS NOT NULL FOR UPDATE",
+        collection_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .flatten() else {
+        return Ok(false);
+    };
+
+    let removed_files = sqlx::query_scalar!(
+        r#"
+        WITH RECURSIVE subtree AS (
+            SELECT id FROM collections WHERE id = $1
+            UNION
+            SELECT c.id
+            FROM collections c
+            INNER JOIN subtree s ON c.parent_id = s.id
+        )
+        DELETE FROM documents d
+        WHERE d.deleted_at = $2
+            AND EXISTS (
+                SELECT 1 FROM document_collections dc
+                WHERE dc.document_id = d.id AND dc.collection_id IN (SELECT id FROM subtree)
+            )
+        RETURNING d.stored_file_path
+        "#,
+        collection_id,
+        deleted_at
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // Subcollections cascade
+    sqlx::query!("DELETE FROM collections WHERE id = $1", collection_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    // Files go only after the rows are gone, so a failed commit never loses data
+    let removed_files: Vec<String> = removed_files.into_iter().flatten().collect();
+    remove_unreferenced_files(db, &removed_files).await;
+    Ok(true)
+}
+
+// Permanently delete everything in a library's trash; returns how many documents went
+pub async fn empty_trash(db: &PgPool, library: Library) -> Result<usize, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let removed_files = sqlx::query_scalar!(
+        r#"
+        DELETE FROM documents
+        WHERE deleted_at IS NOT NULL AND (user_id = $1 OR group_id = $2)
+        RETURNING stored_file_path
+        "#,
+        library.user_id(),
+        library.group_id()
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM collections
+        WHERE deleted_at IS NOT NULL AND (user_id = $1 OR group_id = $2)
+        "#,
+        library.user_id(),
+        library.group_id()
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let purged = removed_files.len();
+    let removed_files: Vec<String> = removed_files.into_iter().flatten().collect();
+    remove_unreferenced_files(db, &removed_files).await;
+    Ok(purged)
+}
+
+// Purge whatever has been in any trash for longer than the retention period;
+// returns how many documents and collections went
+pub async fn purge_expired_trash(
+    db: &PgPool,
+    retention_days: i32,
+) -> Result<(usize, u64), sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let removed_files = sqlx::query_scalar!(
+        r#"
+        DELETE FROM documents
+        WHERE deleted_at < NOW() - make_interval(days => $1)
+        RETURNING stored_file_path
+        "#,
+        retention_days
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let collections = sqlx::query!(
+        "DELETE FROM collections WHERE deleted_at < NOW() - make_interval(days => $1)",
+        retention_days
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let purged = removed_files.len();
+    let removed_files: Vec<String> = removed_files.into_iter().flatten().collect();
+    remove_unreferenced_files(db, &removed_files).await;
+    Ok((purged, collections.rows_affected()))
+}
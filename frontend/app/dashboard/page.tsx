@@ -383,7 +383,7 @@ export default function DashboardPage() {
       setCollectionDocuments((prev) =>
         prev.filter((doc) => doc.id !== documentId)
       );
-      toast.success("Document deleted permanently");
+      toast.success("Document moved to trash");
     } catch (error) {
       console.error("Delete error:", error);
       toast.error("Failed to delete document");
@@ -502,7 +502,7 @@ export default function DashboardPage() {
         onOpenChange={setDeleteFolderOpen}
         onConfirm={handleConfirmDelete}
         title={`Delete "${folderToDelete?.name}"?`}
-        description="This will permanently delete this collection and all its subfolders. Documents will not be deleted."
+        description="This will move this collection and all its subfolders to the trash. Documents will not be deleted."
       />
     </SidebarProvider>
   );
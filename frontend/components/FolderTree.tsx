@@ -29,7 +29,7 @@ export function FolderTree({
   const buildTree = (parentId: string | null): Collection[] => {
     return collections
       .filter((c) => c.parent_id === parentId)
-      .sort((a, b) => a.name.localeCompare(b.name));
+      .sort((a, b) => a.position - b.position || a.name.localeCompare(b.name));
   };
 
   const toggleExpand = (collectionId: string) => {
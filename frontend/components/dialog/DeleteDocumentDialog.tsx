@@ -28,10 +28,10 @@ export function DeleteDocumentDialog({
     <AlertDialog open={open} onOpenChange={onOpenChange}>
       <AlertDialogContent>
         <AlertDialogHeader>
-          <AlertDialogTitle>Delete Document Permanently?</AlertDialogTitle>
+          <AlertDialogTitle>Move Document to Trash?</AlertDialogTitle>
           <AlertDialogDescription>
-            Are you sure you want to permanently delete &quot;{documentTitle}&quot;?
-            This action cannot be undone and will remove the document from all collections.
+            &quot;{documentTitle}&quot; will be moved to the trash and hidden from all collections.
+            You can restore it from the trash until it is purged.
           </AlertDialogDescription>
         </AlertDialogHeader>
         <AlertDialogFooter>
@@ -40,7 +40,7 @@ export function DeleteDocumentDialog({
             onClick={onConfirm}
             className="bg-red-600 hover:bg-red-700"
           >
-            Delete Permanently
+            Move to Trash
           </AlertDialogAction>
         </AlertDialogFooter>
       </AlertDialogContent>
@@ -14,7 +14,9 @@ export interface LoginResponse {
 
 export interface Document {
     id: string;
-    user_id: string;
+    // Exactly one is set: the user or group whose library holds the document
+    user_id: string | null;
+    group_id: string | null;
     title: string;
     authors: string[] | null;
     year: number | null;
@@ -33,16 +35,247 @@ export interface Document {
     updated_at: string;
 }
 
+export interface DocumentPage {
+    documents: Document[];
+    total: number;
+    next_cursor: string | null;
+}
+
+// `snippet` is HTML-escaped text with matched terms wrapped in <mark>
+export interface SearchResult extends Document {
+    rank: number;
+    snippet: string | null;
+    fuzzy: boolean;
+    page_matches: PageMatch[];
+}
+
+export interface PageMatch {
+    page_number: number;
+    snippet: string;
+}
+
+export interface SimilarDocument extends Document {
+    score: number;
+    shared_authors: string[];
+    shared_keywords: string[];
+    title_similarity: number;
+    abstract_similarity: number;
+    embedding_similarity: number | null;
+}
+
+export type BulkAction =
+    | { action: 'delete' }
+    | { action: 'add_to_collection'; collection_id: string }
+    | { action: 'remove_from_collection'; collection_id: string }
+    | { action: 'add_keywords'; keywords: string[] }
+    | { action: 'remove_keywords'; keywords: string[] }
+    | { action: 'set_field'; field: 'publication_type' | 'journal' | 'publisher' | 'year'; value: string | number | null };
+
+export interface BulkItemResult {
+    id: string;
+    status: 'updated' | 'deleted' | 'unchanged' | 'not_found';
+}
+
+export interface Suggestion {
+    kind: 'title' | 'author' | 'journal' | 'keyword';
+    value: string;
+    document_count: number;
+    document_id?: string;
+}
+
+export interface Passage {
+    source: 'metadata' | 'page';
+    page_number: number | null;
+    content: string;
+    score: number;
+}
+
+export interface SemanticResult extends Document {
+    score: number;
+    passages: Passage[];
+}
+
+export interface FacetCount {
+    value: string;
+    label?: string;
+    count: number;
+}
+
+export interface DocumentFacets {
+    total: number;
+    years: FacetCount[];
+    publication_types: FacetCount[];
+    journals: FacetCount[];
+    authors: FacetCount[];
+    keywords: FacetCount[];
+    collections: FacetCount[];
+}
+
+export interface DocumentListParams {
+    sort?: 'title' | 'year' | 'created_at' | 'updated_at' | 'first_author';
+    order?: 'asc' | 'desc';
+    limit?: number;
+    cursor?: string;
+    year_from?: number;
+    year_to?: number;
+    publication_type?: string;
+    journal?: string;
+    has_pdf?: boolean;
+    collection_id?: string;
+}
+
 
 export interface Collection {
     id: string;
-    user_id: string;
+    // Exactly one is set, as for documents
+    user_id: string | null;
+    group_id: string | null;
     name: string;
     parent_id: string | null;
+    // Smart collections hold a search query instead of hand-picked documents
+    query: string | null;
+    // Manual order among siblings
+    position: number;
     created_at: string;
     updated_at: string;
 }
 
+export interface CollectionNode extends Collection {
+    direct_count: number;
+    recursive_count: number;
+    children: CollectionNode[];
+}
+
+export type DeleteCollectionMode = 'move_children_up' | 'delete_subtree' | 'delete_documents';
+
+// Deleted collections and documents go to the trash
+export interface DeleteCollectionReport {
+    dry_run: boolean;
+    deleted_collections: { id: string; name: string }[];
+    moved_collections: { id: string; name: string }[];
+    // Documents kept in the library that end up in no collection
+    unfiled_documents: number;
+    deleted_documents: { id: string; name: string }[];
+}
+
+export interface TrashedDocument extends Document {
+    deleted_at: string;
+}
+
+export interface TrashedCollection extends Collection {
+    deleted_at: string;
+}
+
+export interface TrashContents {
+    // Items are purged for good this many days after they were deleted
+    retention_days: number;
+    documents: TrashedDocument[];
+    // Only collections deleted directly; their subcollections are restored with them
+    collections: TrashedCollection[];
+}
+
+export interface RestoreReport {
+    restored_collections: { id: string; name: string }[];
+    restored_documents: { id: string; name: string }[];
+    // The collection's parent is still in the trash, so it was restored at the top level
+    moved_to_root: boolean;
+}
+
+export interface CollectionPathEntry {
+    id: string;
+    name: string;
+}
+
+export type GroupRole = 'owner' | 'admin' | 'member' | 'reader';
+
+// A group the current user belongs to, with their role in it
+export interface Group {
+    id: string;
+    name: string;
+    role: GroupRole;
+    member_count: number;
+    created_at: string;
+    updated_at: string;
+}
+
+export interface GroupMember {
+    user_id: string;
+    email: string;
+    username: string | null;
+    role: GroupRole;
+    created_at: string;
+}
+
+export type ShareRole = 'viewer' | 'editor';
+
+export interface CollectionShare {
+    id: string;
+    collection_id: string;
+    email: string;
+    // Set once the invited user accepts
+    user_id: string | null;
+    role: ShareRole;
+    include_subcollections: boolean;
+    // One-time token for the owner to send to the invitee; null once accepted
+    invite_token: string | null;
+    created_at: string;
+    accepted_at: string | null;
+}
+
+export interface ShareInvitation {
+    id: string;
+    collection_id: string;
+    collection_name: string;
+    role: ShareRole;
+    include_subcollections: boolean;
+    invited_by: string;
+    created_at: string;
+}
+
+export interface SharedCollection extends Collection {
+    can_edit: boolean;
+    owner_email: string;
+    owner_username: string | null;
+}
+
+// A public read-only link; anyone holding the token can view the collection
+export interface CollectionLink {
+    id: string;
+    collection_id: string;
+    token: string;
+    allow_downloads: boolean;
+    expires_at: string | null;
+    created_at: string;
+}
+
+export interface PublicDocument {
+    id: string;
+    title: string;
+    authors: string[] | null;
+    year: number | null;
+    publication_type: string | null;
+    journal: string | null;
+    volume: string | null;
+    issue: string | null;
+    pages: string | null;
+    publisher: string | null;
+    doi: string | null;
+    url: string | null;
+    abstract_text: string | null;
+    keywords: string[] | null;
+    // Whether the PDF can be downloaded through the link
+    has_pdf: boolean;
+}
+
+export interface PublicCollection {
+    name: string;
+    allow_downloads: boolean;
+    expires_at: string | null;
+    documents: PublicDocument[];
+}
+
+export type BibliographyFormat = 'bibtex' | 'text';
+
 class ApiClient {
     private getHeaders(token?: string): HeadersInit {
         const headers: HeadersInit = {
@@ -94,7 +327,26 @@ class ApiClient {
     }
 
     async getDocuments(token: string): Promise<Document[]> {
-        const response = await fetch(`${API_BASE_URL}/api/documents`, {
+        const documents: Document[] = [];
+        let cursor: string | null = null;
+
+        // Follow cursors until the whole library is loaded
+        do {
+            const page = await this.getDocumentPage(token, cursor ? { cursor, limit: 200 } : { limit: 200 });
+            documents.push(...page.documents);
+            cursor = page.next_cursor;
+        } while (cursor);
+
+        return documents;
+    }
+
+    async getDocumentPage(token: string, params: DocumentListParams = {}): Promise<DocumentPage> {
+        const query = new URLSearchParams();
+        Object.entries(params).forEach(([key, value]) => {
+            if (value !== undefined && value !== null) query.set(key, String(value));
+        });
+
+        const response = await fetch(`${API_BASE_URL}/api/documents?${query}`, {
             headers: this.getHeaders(token),
         });
 
@@ -105,8 +357,97 @@ class ApiClient {
     }
 
 
-    async searchDocuments(token: string, query: string): Promise<Document[]> {
-        const response = await fetch(`${API_BASE_URL}/api/documents/search?q=${encodeURIComponent(query)}`, {
+    // Pass a groupId for the group library's facets
+    async getFacets(token: string, query?: string, groupId?: string): Promise<DocumentFacets> {
+        const params = query ? `?q=${encodeURIComponent(query)}` : '';
+        const path = groupId ? `/api/groups/${groupId}/documents/facets` : '/api/documents/facets';
+        const response = await fetch(`${API_BASE_URL}${path}${params}`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            throw new Error('Failed to fetch facets');
+        }
+        return response.json();
+    }
+
+    async getSimilarDocuments(token: string, documentId: string, limit = 10): Promise<SimilarDocument[]> {
+        const response = await fetch(`${API_BASE_URL}/api/documents/${documentId}/similar?limit=${limit}`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            throw new Error('Failed to fetch similar documents');
+        }
+        return response.json();
+    }
+
+    // Pass a groupId when the documents are in a group library
+    async bulkUpdateDocuments(
+        token: string,
+        documentIds: string[],
+        action: BulkAction,
+        groupId?: string,
+    ): Promise<BulkItemResult[]> {
+        const path = groupId ? `/api/groups/${groupId}/documents/bulk` : '/api/documents/bulk';
+        const response = await fetch(`${API_BASE_URL}${path}`, {
+            method: 'POST',
+            headers: this.getHeaders(token),
+            body: JSON.stringify({ document_ids: documentIds, ...action }),
+        });
+
+        if (!response.ok) {
+            const error = await response.json().catch(() => ({}));
+            throw new Error(error.error || 'Failed to update documents');
+        }
+        const body = await response.json();
+        return body.results;
+    }
+
+    // Prefixes shorter than three characters get no suggestions
+    async suggest(token: string, prefix: string, limit = 10, groupId?: string): Promise<Suggestion[]> {
+        const params = new URLSearchParams({ prefix, limit: String(limit) });
+        const path = groupId ? `/api/groups/${groupId}/suggest` : '/api/suggest';
+        const response = await fetch(`${API_BASE_URL}${path}?${params}`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            throw new Error('Failed to fetch suggestions');
+        }
+        return response.json();
+    }
+
+    async semanticSearch(token: string, query: string, limit = 50, groupId?: string): Promise<SemanticResult[]> {
+        const params = new URLSearchParams({ q: query, limit: String(limit) });
+        const path = groupId
+            ? `/api/groups/${groupId}/documents/semantic-search`
+            : '/api/documents/semantic-search';
+        const response = await fetch(`${API_BASE_URL}${path}?${params}`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json().catch(() => ({}));
+            throw new Error(error.error || 'Semantic search failed');
+        }
+        return response.json();
+    }
+
+    async searchDocuments(
+        token: string,
+        query: string,
+        scope?: { collection_id: string; include_subcollections?: boolean },
+        groupId?: string,
+    ): Promise<SearchResult[]> {
+        const params = new URLSearchParams({ q: query });
+        if (scope) {
+            params.set('collection_id', scope.collection_id);
+            params.set('include_subcollections', String(scope.include_subcollections ?? false));
+        }
+
+        const path = groupId ? `/api/groups/${groupId}/documents/search` : '/api/documents/search';
+        const response = await fetch(`${API_BASE_URL}${path}?${params}`, {
             headers: this.getHeaders(token),
         });
 
@@ -149,6 +490,7 @@ class ApiClient {
         return response.json();
     }
 
+    // Moves the document to the trash
     async deleteDocument(token: string, documentId: string): Promise<void> {
         const response = await fetch(`${API_BASE_URL}/api/documents/${documentId}`, {
             method: 'DELETE',
@@ -160,6 +502,84 @@ class ApiClient {
         }
     }
 
+    // Pass a groupId for the group library's trash
+    async getTrash(token: string, groupId?: string): Promise<TrashContents> {
+        const path = groupId ? `/api/groups/${groupId}/trash` : '/api/trash';
+        const response = await fetch(`${API_BASE_URL}${path}`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to fetch trash');
+        }
+        return response.json();
+    }
+
+    // Permanently deletes everything in the trash, with the files
+    async emptyTrash(token: string, groupId?: string): Promise<void> {
+        const path = groupId ? `/api/groups/${groupId}/trash` : '/api/trash';
+        const response = await fetch(`${API_BASE_URL}${path}`, {
+            method: 'DELETE',
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to empty trash');
+        }
+    }
+
+    async restoreDocument(token: string, documentId: string): Promise<void> {
+        const response = await fetch(`${API_BASE_URL}/api/trash/documents/${documentId}/restore`, {
+            method: 'POST',
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to restore document');
+        }
+    }
+
+    async purgeDocument(token: string, documentId: string): Promise<void> {
+        const response = await fetch(`${API_BASE_URL}/api/trash/documents/${documentId}`, {
+            method: 'DELETE',
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to delete document');
+        }
+    }
+
+    // Also restores the subcollections and documents deleted along with the collection
+    async restoreCollection(token: string, collectionId: string): Promise<RestoreReport> {
+        const response = await fetch(`${API_BASE_URL}/api/trash/collections/${collectionId}/restore`, {
+            method: 'POST',
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to restore collection');
+        }
+        return response.json();
+    }
+
+    async purgeCollection(token: string, collectionId: string): Promise<void> {
+        const response = await fetch(`${API_BASE_URL}/api/trash/collections/${collectionId}`, {
+            method: 'DELETE',
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to delete collection');
+        }
+    }
+
     async getDocument(token: string, documentId: string): Promise<Document> {
         const response = await fetch(`${API_BASE_URL}/api/documents/${documentId}`, {
             headers: this.getHeaders(token),
@@ -200,6 +620,20 @@ class ApiClient {
         return response.json();
     }
 
+    // currentPassword may be omitted by single sign-on accounts that have no password yet
+    async changePassword(token: string, newPassword: string, currentPassword?: string): Promise<void> {
+        const response = await fetch(`${API_BASE_URL}/api/user/password`, {
+            method: 'PUT',
+            headers: this.getHeaders(token),
+            body: JSON.stringify({ current_password: currentPassword, new_password: newPassword }),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to change password');
+        }
+    }
+
     async uploadProfileImage(token: string, file: File): Promise<User> {
         const formData = new FormData();
         formData.append('file', file);
@@ -246,11 +680,35 @@ class ApiClient {
         return response.json();
     }
 
-    async createCollection(token: string, name: string, parent_id: string | null): Promise<Collection> {
+    // Pass a groupId for the group library's tree
+    async getCollectionTree(token: string, groupId?: string): Promise<CollectionNode[]> {
+        const path = groupId ? `/api/groups/${groupId}/collections/tree` : '/api/collections/tree';
+        const response = await fetch(`${API_BASE_URL}${path}`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            throw new Error('Failed to fetch collections');
+        }
+        return response.json();
+    }
+
+    async getCollectionPath(token: string, collectionId: string): Promise<CollectionPathEntry[]> {
+        const response = await fetch(`${API_BASE_URL}/api/collections/${collectionId}/path`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            throw new Error('Failed to fetch collection path');
+        }
+        return response.json();
+    }
+
+    async createCollection(token: string, name: string, parent_id: string | null, query?: string): Promise<Collection> {
         const response = await fetch(`${API_BASE_URL}/api/collections`, {
             method: 'POST',
             headers: this.getHeaders(token),
-            body: JSON.stringify({ name, parent_id }),
+            body: JSON.stringify({ name, parent_id, query }),
         });
 
         if (!response.ok) {
@@ -260,7 +718,7 @@ class ApiClient {
         return response.json();
     }
 
-    async updateCollection(token: string, collectionId: string, updates: { name?: string; parent_id?: string | null }): Promise<Collection> {
+    async updateCollection(token: string, collectionId: string, updates: { name?: string; parent_id?: string | null; query?: string }): Promise<Collection> {
         const response = await fetch(`${API_BASE_URL}/api/collections/${collectionId}`, {
             method: 'PUT',
             headers: this.getHeaders(token),
@@ -274,8 +732,395 @@ class ApiClient {
         return response.json();
     }
 
-    async deleteCollection(token: string, collectionId: string): Promise<void> {
-        const response = await fetch(`${API_BASE_URL}/api/collections/${collectionId}`, {
+    // Copies the collection, its subcollections and their document placements
+    async duplicateCollection(token: string, collectionId: string, name?: string): Promise<Collection> {
+        const response = await fetch(`${API_BASE_URL}/api/collections/${collectionId}/duplicate`, {
+            method: 'POST',
+            headers: this.getHeaders(token),
+            body: JSON.stringify({ name }),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to duplicate collection');
+        }
+        return response.json();
+    }
+
+    async getGroups(token: string): Promise<Group[]> {
+        const response = await fetch(`${API_BASE_URL}/api/groups`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            throw new Error('Failed to fetch groups');
+        }
+        return response.json();
+    }
+
+    async createGroup(token: string, name: string): Promise<Group> {
+        const response = await fetch(`${API_BASE_URL}/api/groups`, {
+            method: 'POST',
+            headers: this.getHeaders(token),
+            body: JSON.stringify({ name }),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to create group');
+        }
+        return response.json();
+    }
+
+    async renameGroup(token: string, groupId: string, name: string): Promise<Group> {
+        const response = await fetch(`${API_BASE_URL}/api/groups/${groupId}`, {
+            method: 'PUT',
+            headers: this.getHeaders(token),
+            body: JSON.stringify({ name }),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to rename group');
+        }
+        return response.json();
+    }
+
+    // Deletes the group's documents, collections and files too
+    async deleteGroup(token: string, groupId: string): Promise<void> {
+        const response = await fetch(`${API_BASE_URL}/api/groups/${groupId}`, {
+            method: 'DELETE',
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to delete group');
+        }
+    }
+
+    async getGroupMembers(token: string, groupId: string): Promise<GroupMember[]> {
+        const response = await fetch(`${API_BASE_URL}/api/groups/${groupId}/members`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to fetch members');
+        }
+        return response.json();
+    }
+
+    async addGroupMember(token: string, groupId: string, email: string, role: GroupRole): Promise<GroupMember> {
+        const response = await fetch(`${API_BASE_URL}/api/groups/${groupId}/members`, {
+            method: 'POST',
+            headers: this.getHeaders(token),
+            body: JSON.stringify({ email, role }),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to add member');
+        }
+        return response.json();
+    }
+
+    // Giving someone the owner role transfers ownership; the previous owner becomes an admin
+    async updateGroupMember(token: string, groupId: string, userId: string, role: GroupRole): Promise<void> {
+        const response = await fetch(`${API_BASE_URL}/api/groups/${groupId}/members/${userId}`, {
+            method: 'PUT',
+            headers: this.getHeaders(token),
+            body: JSON.stringify({ role }),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to update member');
+        }
+    }
+
+    // Removes a member, or leaves the group when userId is the current user
+    async removeGroupMember(token: string, groupId: string, userId: string): Promise<void> {
+        const response = await fetch(`${API_BASE_URL}/api/groups/${groupId}/members/${userId}`, {
+            method: 'DELETE',
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to remove member');
+        }
+    }
+
+    async getGroupDocumentPage(token: string, groupId: string, params: DocumentListParams = {}): Promise<DocumentPage> {
+        const query = new URLSearchParams();
+        Object.entries(params).forEach(([key, value]) => {
+            if (value !== undefined && value !== null) query.set(key, String(value));
+        });
+
+        const response = await fetch(`${API_BASE_URL}/api/groups/${groupId}/documents?${query}`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to fetch documents');
+        }
+        return response.json();
+    }
+
+    async createGroupDocument(token: string, groupId: string, document: Partial<Document>): Promise<Document> {
+        const response = await fetch(`${API_BASE_URL}/api/groups/${groupId}/documents`, {
+            method: 'POST',
+            headers: this.getHeaders(token),
+            body: JSON.stringify(document),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to create document');
+        }
+        return response.json();
+    }
+
+    // The file counts against the group owner's storage quota
+    async uploadGroupPdf(token: string, groupId: string, file: File): Promise<Document> {
+        const formData = new FormData();
+        formData.append('file', file);
+
+        const response = await fetch(`${API_BASE_URL}/api/groups/${groupId}/documents/upload`, {
+            method: 'POST',
+            headers: {
+                'Authorization': `Bearer ${token}`,
+            },
+            body: formData,
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to upload PDF');
+        }
+        return response.json();
+    }
+
+    async getGroupCollections(token: string, groupId: string): Promise<Collection[]> {
+        const response = await fetch(`${API_BASE_URL}/api/groups/${groupId}/collections`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to fetch collections');
+        }
+        return response.json();
+    }
+
+    async createGroupCollection(
+        token: string,
+        groupId: string,
+        name: string,
+        parent_id: string | null,
+        query?: string,
+    ): Promise<Collection> {
+        const response = await fetch(`${API_BASE_URL}/api/groups/${groupId}/collections`, {
+            method: 'POST',
+            headers: this.getHeaders(token),
+            body: JSON.stringify({ name, parent_id, query }),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to create collection');
+        }
+        return response.json();
+    }
+
+    async getCollectionShares(token: string, collectionId: string): Promise<CollectionShare[]> {
+        const response = await fetch(`${API_BASE_URL}/api/collections/${collectionId}/shares`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to fetch shares');
+        }
+        return response.json();
+    }
+
+    // Sharing again with the same email changes the existing share
+    async shareCollection(
+        token: string,
+        collectionId: string,
+        share: { email: string; role: ShareRole; include_subcollections?: boolean },
+    ): Promise<CollectionShare> {
+        const response = await fetch(`${API_BASE_URL}/api/collections/${collectionId}/shares`, {
+            method: 'POST',
+            headers: this.getHeaders(token),
+            body: JSON.stringify(share),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to share collection');
+        }
+        return response.json();
+    }
+
+    // Revokes a share as its owner, or leaves it as the user who accepted it
+    async deleteShare(token: string, shareId: string): Promise<void> {
+        const response = await fetch(`${API_BASE_URL}/api/shares/${shareId}`, {
+            method: 'DELETE',
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to remove share');
+        }
+    }
+
+    // The invitation behind a token the collection owner sent
+    async getShareInvitation(token: string, inviteToken: string): Promise<ShareInvitation> {
+        const response = await fetch(`${API_BASE_URL}/api/shares/invitations/${inviteToken}`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to fetch invitation');
+        }
+        return response.json();
+    }
+
+    async acceptShare(token: string, inviteToken: string): Promise<CollectionShare> {
+        const response = await fetch(`${API_BASE_URL}/api/shares/invitations/${inviteToken}/accept`, {
+            method: 'POST',
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to accept invitation');
+        }
+        return response.json();
+    }
+
+    async getSharedCollections(token: string): Promise<SharedCollection[]> {
+        const response = await fetch(`${API_BASE_URL}/api/collections/shared-with-me`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to fetch shared collections');
+        }
+        return response.json();
+    }
+
+    async getCollectionLinks(token: string, collectionId: string): Promise<CollectionLink[]> {
+        const response = await fetch(`${API_BASE_URL}/api/collections/${collectionId}/links`, {
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to fetch links');
+        }
+        return response.json();
+    }
+
+    async createCollectionLink(
+        token: string,
+        collectionId: string,
+        options: { allow_downloads?: boolean; expires_at?: string | null } = {},
+    ): Promise<CollectionLink> {
+        const response = await fetch(`${API_BASE_URL}/api/collections/${collectionId}/links`, {
+            method: 'POST',
+            headers: this.getHeaders(token),
+            body: JSON.stringify(options),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to create link');
+        }
+        return response.json();
+    }
+
+    async revokeCollectionLink(token: string, collectionId: string, linkId: string): Promise<void> {
+        const response = await fetch(`${API_BASE_URL}/api/collections/${collectionId}/links/${linkId}`, {
+            method: 'DELETE',
+            headers: this.getHeaders(token),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to revoke link');
+        }
+    }
+
+    // No sign-in needed: the link token is the credential
+    async getPublicCollection(linkToken: string): Promise<PublicCollection> {
+        const response = await fetch(`${API_BASE_URL}/api/public/collections/${linkToken}`);
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to fetch collection');
+        }
+        return response.json();
+    }
+
+    publicBibliographyUrl(linkToken: string, format: BibliographyFormat = 'bibtex'): string {
+        return `${API_BASE_URL}/api/public/collections/${linkToken}/bibliography?format=${format}`;
+    }
+
+    // Only for documents with has_pdf set
+    publicPdfUrl(linkToken: string, documentId: string): string {
+        return `${API_BASE_URL}/api/public/collections/${linkToken}/documents/${documentId}/pdf`;
+    }
+
+    // Moves `ids`, in order, to right after `after` (or to the start) among their sibling collections
+    async reorderCollections(token: string, ids: string[], after: string | null = null): Promise<void> {
+        const response = await fetch(`${API_BASE_URL}/api/collections/order`, {
+            method: 'PUT',
+            headers: this.getHeaders(token),
+            body: JSON.stringify({ ids, after }),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to reorder collections');
+        }
+    }
+
+    async reorderCollectionDocuments(
+        token: string,
+        collectionId: string,
+        ids: string[],
+        after: string | null = null,
+    ): Promise<void> {
+        const response = await fetch(`${API_BASE_URL}/api/collections/${collectionId}/documents/order`, {
+            method: 'PUT',
+            headers: this.getHeaders(token),
+            body: JSON.stringify({ ids, after }),
+        });
+
+        if (!response.ok) {
+            const error = await response.json();
+            throw new Error(error.error || 'Failed to reorder documents');
+        }
+    }
+
+    async deleteCollection(
+        token: string,
+        collectionId: string,
+        options: { mode?: DeleteCollectionMode; dry_run?: boolean } = {},
+    ): Promise<DeleteCollectionReport> {
+        const params = new URLSearchParams();
+        if (options.mode) params.set('mode', options.mode);
+        if (options.dry_run) params.set('dry_run', 'true');
+
+        const response = await fetch(`${API_BASE_URL}/api/collections/${collectionId}?${params}`, {
             method: 'DELETE',
             headers: this.getHeaders(token),
         });
@@ -283,6 +1128,7 @@ class ApiClient {
         if (!response.ok) {
             throw new Error('Failed to delete collection');
         }
+        return response.json();
     }
 
     async getCollectionDocuments(token: string, collectionId: string): Promise<Document[]> {